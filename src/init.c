@@ -6,6 +6,8 @@
 
 #include "rust/api.h"
 
+void yaml12_init_lazy_list(DllInfo *dll);
+
 static uintptr_t TAGGED_POINTER_MASK = (uintptr_t)1;
 static SEXP path_expand_sym;
 
@@ -67,19 +69,31 @@ SEXP savvy_init_yaml12__impl(DllInfo* c_arg___dll_info) {
     return handle_result(res);
 }
 
-SEXP savvy_parse_yaml_native__impl(SEXP c_arg__text, SEXP c_arg__multi, SEXP c_arg__simplify, SEXP c_arg__handlers) {
-    SEXP res = savvy_parse_yaml_native__ffi(c_arg__text, c_arg__multi, c_arg__simplify, c_arg__handlers);
+SEXP savvy_parse_yaml_native__impl(SEXP c_arg__text, SEXP c_arg__multi, SEXP c_arg__simplify, SEXP c_arg__handlers, SEXP c_arg__handle_untagged, SEXP c_arg__strict_tags, SEXP c_arg__interpolate, SEXP c_arg__lazy, SEXP c_arg__recover, SEXP c_arg__all_errors) {
+    SEXP res = savvy_parse_yaml_native__ffi(c_arg__text, c_arg__multi, c_arg__simplify, c_arg__handlers, c_arg__handle_untagged, c_arg__strict_tags, c_arg__interpolate, c_arg__lazy, c_arg__recover, c_arg__all_errors);
     return handle_result(res);
 }
 
-SEXP savvy_read_yaml_native__impl(SEXP c_arg__path, SEXP c_arg__multi, SEXP c_arg__simplify, SEXP c_arg__handlers) {
+SEXP savvy_read_config_native__impl(SEXP c_arg__path, SEXP c_arg__config, SEXP c_arg__simplify, SEXP c_arg__handlers, SEXP c_arg__interpolate) {
+    if (!has_tilde_prefix(c_arg__path)) {
+        SEXP res = savvy_read_config_native__ffi(c_arg__path, c_arg__config, c_arg__simplify, c_arg__handlers, c_arg__interpolate);
+        return handle_result(res);
+    }
+
+    c_arg__path = PROTECT(expand_tilde_path(c_arg__path));
+    SEXP result = handle_result(savvy_read_config_native__ffi(c_arg__path, c_arg__config, c_arg__simplify, c_arg__handlers, c_arg__interpolate));
+    UNPROTECT(1);
+    return result;
+}
+
+SEXP savvy_read_yaml_native__impl(SEXP c_arg__path, SEXP c_arg__multi, SEXP c_arg__simplify, SEXP c_arg__handlers, SEXP c_arg__handle_untagged, SEXP c_arg__strict_tags, SEXP c_arg__interpolate, SEXP c_arg__include, SEXP c_arg__lazy, SEXP c_arg__recover, SEXP c_arg__all_errors) {
     if (!has_tilde_prefix(c_arg__path)) {
-        SEXP res = savvy_read_yaml_native__ffi(c_arg__path, c_arg__multi, c_arg__simplify, c_arg__handlers);
+        SEXP res = savvy_read_yaml_native__ffi(c_arg__path, c_arg__multi, c_arg__simplify, c_arg__handlers, c_arg__handle_untagged, c_arg__strict_tags, c_arg__interpolate, c_arg__include, c_arg__lazy, c_arg__recover, c_arg__all_errors);
         return handle_result(res);
     }
 
     c_arg__path = PROTECT(expand_tilde_path(c_arg__path));
-    SEXP result = handle_result(savvy_read_yaml_native__ffi(c_arg__path, c_arg__multi, c_arg__simplify, c_arg__handlers));
+    SEXP result = handle_result(savvy_read_yaml_native__ffi(c_arg__path, c_arg__multi, c_arg__simplify, c_arg__handlers, c_arg__handle_untagged, c_arg__strict_tags, c_arg__interpolate, c_arg__include, c_arg__lazy, c_arg__recover, c_arg__all_errors));
     UNPROTECT(1);
     return result;
 }
@@ -96,13 +110,122 @@ SEXP savvy_write_yaml_native__impl(SEXP c_arg__value, SEXP c_arg__path, SEXP c_a
     return result;
 }
 
+SEXP savvy_yaml_delete_native__impl(SEXP c_arg__x, SEXP c_arg__is_file, SEXP c_arg__query) {
+    SEXP res = savvy_yaml_delete_native__ffi(c_arg__x, c_arg__is_file, c_arg__query);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_diff_native__impl(SEXP c_arg__old, SEXP c_arg__new, SEXP c_arg__ignore_order, SEXP c_arg__report, SEXP c_arg__simplify) {
+    SEXP res = savvy_yaml_diff_native__ffi(c_arg__old, c_arg__new, c_arg__ignore_order, c_arg__report, c_arg__simplify);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_document_native__impl(SEXP c_arg__x, SEXP c_arg__is_file) {
+    SEXP res = savvy_yaml_document_native__ffi(c_arg__x, c_arg__is_file);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_document_convert_native__impl(SEXP c_arg__doc, SEXP c_arg__path, SEXP c_arg__simplify, SEXP c_arg__handlers) {
+    SEXP res = savvy_yaml_document_convert_native__ffi(c_arg__doc, c_arg__path, c_arg__simplify, c_arg__handlers);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_document_kind_native__impl(SEXP c_arg__doc, SEXP c_arg__path) {
+    SEXP res = savvy_yaml_document_kind_native__ffi(c_arg__doc, c_arg__path);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_document_length_native__impl(SEXP c_arg__doc, SEXP c_arg__path) {
+    SEXP res = savvy_yaml_document_length_native__ffi(c_arg__doc, c_arg__path);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_document_names_native__impl(SEXP c_arg__doc, SEXP c_arg__path) {
+    SEXP res = savvy_yaml_document_names_native__ffi(c_arg__doc, c_arg__path);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_equal_native__impl(SEXP c_arg__a, SEXP c_arg__b, SEXP c_arg__ignore_tags, SEXP c_arg__ignore_order, SEXP c_arg__multi) {
+    SEXP res = savvy_yaml_equal_native__ffi(c_arg__a, c_arg__b, c_arg__ignore_tags, c_arg__ignore_order, c_arg__multi);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_events_native__impl(SEXP c_arg__text, SEXP c_arg__text_format) {
+    SEXP res = savvy_yaml_events_native__ffi(c_arg__text, c_arg__text_format);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_format_file_native__impl(SEXP c_arg__path, SEXP c_arg__check) {
+    SEXP res = savvy_yaml_format_file_native__ffi(c_arg__path, c_arg__check);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_get_native__impl(SEXP c_arg__x, SEXP c_arg__is_file, SEXP c_arg__query, SEXP c_arg__simplify, SEXP c_arg__handlers) {
+    SEXP res = savvy_yaml_get_native__ffi(c_arg__x, c_arg__is_file, c_arg__query, c_arg__simplify, c_arg__handlers);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_hash_native__impl(SEXP c_arg__x, SEXP c_arg__ignore_tags, SEXP c_arg__ignore_order, SEXP c_arg__multi) {
+    SEXP res = savvy_yaml_hash_native__ffi(c_arg__x, c_arg__ignore_tags, c_arg__ignore_order, c_arg__multi);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_lint_native__impl(SEXP c_arg__x, SEXP c_arg__text, SEXP c_arg__rules, SEXP c_arg__severities, SEXP c_arg__max_line_length, SEXP c_arg__indent_spaces, SEXP c_arg__document_start) {
+    SEXP res = savvy_yaml_lint_native__ffi(c_arg__x, c_arg__text, c_arg__rules, c_arg__severities, c_arg__max_line_length, c_arg__indent_spaces, c_arg__document_start);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_merge_native__impl(SEXP c_arg__values, SEXP c_arg__text, SEXP c_arg__sequences, SEXP c_arg__nulls, SEXP c_arg__simplify) {
+    SEXP res = savvy_yaml_merge_native__ffi(c_arg__values, c_arg__text, c_arg__sequences, c_arg__nulls, c_arg__simplify);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_patch_native__impl(SEXP c_arg__doc, SEXP c_arg__ops) {
+    SEXP res = savvy_yaml_patch_native__ffi(c_arg__doc, c_arg__ops);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_reformat_native__impl(SEXP c_arg__text) {
+    SEXP res = savvy_yaml_reformat_native__ffi(c_arg__text);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_set_native__impl(SEXP c_arg__x, SEXP c_arg__is_file, SEXP c_arg__query, SEXP c_arg__value) {
+    SEXP res = savvy_yaml_set_native__ffi(c_arg__x, c_arg__is_file, c_arg__query, c_arg__value);
+    return handle_result(res);
+}
+
+SEXP savvy_yaml_validate_native__impl(SEXP c_arg__x, SEXP c_arg__text, SEXP c_arg__duplicate_keys, SEXP c_arg__known_tags) {
+    SEXP res = savvy_yaml_validate_native__ffi(c_arg__x, c_arg__text, c_arg__duplicate_keys, c_arg__known_tags);
+    return handle_result(res);
+}
+
 
 static const R_CallMethodDef CallEntries[] = {
     {"savvy_dbg_yaml_native__impl", (DL_FUNC) &savvy_dbg_yaml_native__impl, 1},
     {"savvy_format_yaml_native__impl", (DL_FUNC) &savvy_format_yaml_native__impl, 3},
-    {"savvy_parse_yaml_native__impl", (DL_FUNC) &savvy_parse_yaml_native__impl, 4},
-    {"savvy_read_yaml_native__impl", (DL_FUNC) &savvy_read_yaml_native__impl, 4},
+    {"savvy_parse_yaml_native__impl", (DL_FUNC) &savvy_parse_yaml_native__impl, 10},
+    {"savvy_read_config_native__impl", (DL_FUNC) &savvy_read_config_native__impl, 5},
+    {"savvy_read_yaml_native__impl", (DL_FUNC) &savvy_read_yaml_native__impl, 11},
     {"savvy_write_yaml_native__impl", (DL_FUNC) &savvy_write_yaml_native__impl, 5},
+    {"savvy_yaml_delete_native__impl", (DL_FUNC) &savvy_yaml_delete_native__impl, 3},
+    {"savvy_yaml_diff_native__impl", (DL_FUNC) &savvy_yaml_diff_native__impl, 5},
+    {"savvy_yaml_document_native__impl", (DL_FUNC) &savvy_yaml_document_native__impl, 2},
+    {"savvy_yaml_document_convert_native__impl", (DL_FUNC) &savvy_yaml_document_convert_native__impl, 4},
+    {"savvy_yaml_document_kind_native__impl", (DL_FUNC) &savvy_yaml_document_kind_native__impl, 2},
+    {"savvy_yaml_document_length_native__impl", (DL_FUNC) &savvy_yaml_document_length_native__impl, 2},
+    {"savvy_yaml_document_names_native__impl", (DL_FUNC) &savvy_yaml_document_names_native__impl, 2},
+    {"savvy_yaml_equal_native__impl", (DL_FUNC) &savvy_yaml_equal_native__impl, 5},
+    {"savvy_yaml_events_native__impl", (DL_FUNC) &savvy_yaml_events_native__impl, 2},
+    {"savvy_yaml_format_file_native__impl", (DL_FUNC) &savvy_yaml_format_file_native__impl, 2},
+    {"savvy_yaml_get_native__impl", (DL_FUNC) &savvy_yaml_get_native__impl, 5},
+    {"savvy_yaml_hash_native__impl", (DL_FUNC) &savvy_yaml_hash_native__impl, 4},
+    {"savvy_yaml_lint_native__impl", (DL_FUNC) &savvy_yaml_lint_native__impl, 7},
+    {"savvy_yaml_merge_native__impl", (DL_FUNC) &savvy_yaml_merge_native__impl, 5},
+    {"savvy_yaml_patch_native__impl", (DL_FUNC) &savvy_yaml_patch_native__impl, 2},
+    {"savvy_yaml_reformat_native__impl", (DL_FUNC) &savvy_yaml_reformat_native__impl, 1},
+    {"savvy_yaml_set_native__impl", (DL_FUNC) &savvy_yaml_set_native__impl, 4},
+    {"savvy_yaml_validate_native__impl", (DL_FUNC) &savvy_yaml_validate_native__impl, 4},
     {NULL, NULL, 0}
 };
 
@@ -114,4 +237,5 @@ void R_init_yaml12(DllInfo *dll) {
 
     // Functions for initialzation, if any.
     savvy_init_yaml12__impl(dll);
+    yaml12_init_lazy_list(dll);
 }
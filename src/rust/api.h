@@ -1,6 +1,25 @@
 SEXP savvy_dbg_yaml_native__ffi(SEXP c_arg__text);
 SEXP savvy_format_yaml_native__ffi(SEXP c_arg__value, SEXP c_arg__multi, SEXP c_arg__width);
 SEXP savvy_init_yaml12__ffi(DllInfo* c_arg___dll_info);
-SEXP savvy_parse_yaml_native__ffi(SEXP c_arg__text, SEXP c_arg__multi, SEXP c_arg__simplify, SEXP c_arg__handlers);
-SEXP savvy_read_yaml_native__ffi(SEXP c_arg__path, SEXP c_arg__multi, SEXP c_arg__simplify, SEXP c_arg__handlers);
+SEXP savvy_parse_yaml_native__ffi(SEXP c_arg__text, SEXP c_arg__multi, SEXP c_arg__simplify, SEXP c_arg__handlers, SEXP c_arg__handle_untagged, SEXP c_arg__strict_tags, SEXP c_arg__interpolate, SEXP c_arg__lazy, SEXP c_arg__recover, SEXP c_arg__all_errors);
+SEXP savvy_read_config_native__ffi(SEXP c_arg__path, SEXP c_arg__config, SEXP c_arg__simplify, SEXP c_arg__handlers, SEXP c_arg__interpolate);
+SEXP savvy_read_yaml_native__ffi(SEXP c_arg__path, SEXP c_arg__multi, SEXP c_arg__simplify, SEXP c_arg__handlers, SEXP c_arg__handle_untagged, SEXP c_arg__strict_tags, SEXP c_arg__interpolate, SEXP c_arg__include, SEXP c_arg__lazy, SEXP c_arg__recover, SEXP c_arg__all_errors);
 SEXP savvy_write_yaml_native__ffi(SEXP c_arg__value, SEXP c_arg__path, SEXP c_arg__multi, SEXP c_arg__width, SEXP c_arg__append);
+SEXP savvy_yaml_delete_native__ffi(SEXP c_arg__x, SEXP c_arg__is_file, SEXP c_arg__query);
+SEXP savvy_yaml_diff_native__ffi(SEXP c_arg__old, SEXP c_arg__new, SEXP c_arg__ignore_order, SEXP c_arg__report, SEXP c_arg__simplify);
+SEXP savvy_yaml_document_native__ffi(SEXP c_arg__x, SEXP c_arg__is_file);
+SEXP savvy_yaml_document_convert_native__ffi(SEXP c_arg__doc, SEXP c_arg__path, SEXP c_arg__simplify, SEXP c_arg__handlers);
+SEXP savvy_yaml_document_kind_native__ffi(SEXP c_arg__doc, SEXP c_arg__path);
+SEXP savvy_yaml_document_length_native__ffi(SEXP c_arg__doc, SEXP c_arg__path);
+SEXP savvy_yaml_document_names_native__ffi(SEXP c_arg__doc, SEXP c_arg__path);
+SEXP savvy_yaml_equal_native__ffi(SEXP c_arg__a, SEXP c_arg__b, SEXP c_arg__ignore_tags, SEXP c_arg__ignore_order, SEXP c_arg__multi);
+SEXP savvy_yaml_events_native__ffi(SEXP c_arg__text, SEXP c_arg__text_format);
+SEXP savvy_yaml_format_file_native__ffi(SEXP c_arg__path, SEXP c_arg__check);
+SEXP savvy_yaml_get_native__ffi(SEXP c_arg__x, SEXP c_arg__is_file, SEXP c_arg__query, SEXP c_arg__simplify, SEXP c_arg__handlers);
+SEXP savvy_yaml_hash_native__ffi(SEXP c_arg__x, SEXP c_arg__ignore_tags, SEXP c_arg__ignore_order, SEXP c_arg__multi);
+SEXP savvy_yaml_lint_native__ffi(SEXP c_arg__x, SEXP c_arg__text, SEXP c_arg__rules, SEXP c_arg__severities, SEXP c_arg__max_line_length, SEXP c_arg__indent_spaces, SEXP c_arg__document_start);
+SEXP savvy_yaml_merge_native__ffi(SEXP c_arg__values, SEXP c_arg__text, SEXP c_arg__sequences, SEXP c_arg__nulls, SEXP c_arg__simplify);
+SEXP savvy_yaml_patch_native__ffi(SEXP c_arg__doc, SEXP c_arg__ops);
+SEXP savvy_yaml_reformat_native__ffi(SEXP c_arg__text);
+SEXP savvy_yaml_set_native__ffi(SEXP c_arg__x, SEXP c_arg__is_file, SEXP c_arg__query, SEXP c_arg__value);
+SEXP savvy_yaml_validate_native__ffi(SEXP c_arg__x, SEXP c_arg__text, SEXP c_arg__duplicate_keys, SEXP c_arg__known_tags);
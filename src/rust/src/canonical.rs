@@ -0,0 +1,308 @@
+use crate::r_ext::text_arg;
+use crate::tree::resolve_tree;
+use crate::yaml_to_r::scan_yaml_documents;
+use crate::{api_other, Fallible};
+use saphyr::{Scalar, Tag, Yaml};
+use savvy::StringSexp;
+
+/// Bumped whenever the canonical encoding changes, so that old digests
+/// never collide with new ones.
+const CANONICAL_VERSION: &[u8] = b"yaml12-canonical-1\n";
+
+/// What the canonical form disregards.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CanonicalOptions {
+    pub(crate) ignore_tags: bool,
+    pub(crate) ignore_order: bool,
+}
+
+/// Append the canonical encoding of resolved `node` to `out`.
+///
+/// Every value is written with a type marker, and strings and tags with
+/// their byte length, so distinct trees never share an encoding. Tags are
+/// written in full (`!!str` and `!<tag:yaml.org,2002:str>` agree), and an
+/// explicit `!!map` or `!!seq` on a collection of that kind is dropped. When
+/// key order is ignored, mapping entries are sorted by their encoded keys.
+pub(crate) fn encode(node: &Yaml<'_>, options: CanonicalOptions, out: &mut Vec<u8>) {
+    match node {
+        Yaml::Value(Scalar::Null) | Yaml::BadValue => out.push(b'~'),
+        Yaml::Value(Scalar::Boolean(true)) => out.push(b't'),
+        Yaml::Value(Scalar::Boolean(false)) => out.push(b'f'),
+        Yaml::Value(Scalar::Integer(value)) => {
+            out.push(b'i');
+            out.extend_from_slice(value.to_string().as_bytes());
+            out.push(b';');
+        }
+        Yaml::Value(Scalar::FloatingPoint(value)) => {
+            out.push(b'd');
+            // `-0.0 == 0.0`; NaN and infinities print as `NaN`, `inf`, `-inf`.
+            let value = if value.0 == 0.0 { 0.0 } else { value.0 };
+            out.extend_from_slice(value.to_string().as_bytes());
+            out.push(b';');
+        }
+        Yaml::Value(Scalar::String(value)) | Yaml::Representation(value, _, _) => {
+            encode_bytes(b's', value.as_bytes(), out);
+        }
+        Yaml::Sequence(items) => {
+            out.push(b'[');
+            for item in items {
+                encode(item, options, out);
+            }
+            out.push(b']');
+        }
+        Yaml::Mapping(map) => {
+            let mut entries: Vec<(Vec<u8>, Vec<u8>)> = map
+                .iter()
+                .map(|(key, value)| {
+                    let mut encoded_key = Vec::new();
+                    encode(key, options, &mut encoded_key);
+                    let mut encoded_value = Vec::new();
+                    encode(value, options, &mut encoded_value);
+                    (encoded_key, encoded_value)
+                })
+                .collect();
+            if options.ignore_order {
+                entries.sort();
+            }
+            out.push(b'{');
+            for (key, value) in entries {
+                out.extend_from_slice(&key);
+                out.extend_from_slice(&value);
+            }
+            out.push(b'}');
+        }
+        Yaml::Tagged(tag, inner) => {
+            if !options.ignore_tags && !is_implied_tag(tag, inner) {
+                let full = format!("{}{}", tag.handle, tag.suffix);
+                encode_bytes(b'!', full.as_bytes(), out);
+            }
+            encode(inner, options, out);
+        }
+        Yaml::Alias(_) => out.push(b'*'),
+    }
+}
+
+fn encode_bytes(marker: u8, bytes: &[u8], out: &mut Vec<u8>) {
+    out.push(marker);
+    out.extend_from_slice(bytes.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(bytes);
+}
+
+/// Whether `tag` only restates the kind of collection it is on.
+fn is_implied_tag(tag: &Tag, inner: &Yaml<'_>) -> bool {
+    tag.is_yaml_core_schema()
+        && matches!(
+            (tag.suffix.as_str(), inner),
+            ("map", Yaml::Mapping(_)) | ("seq", Yaml::Sequence(_))
+        )
+}
+
+/// The canonical encoding of the first document of `text`, or of every
+/// document when `multi` is set.
+pub(crate) fn canonical_text(
+    text: &str,
+    name: &str,
+    multi: bool,
+    options: CanonicalOptions,
+) -> Fallible<Vec<u8>> {
+    let mut docs = scan_yaml_documents(text, multi)
+        .map_err(|err| api_other(format!("YAML parse error in `{name}`: {err}")))?;
+    if !multi && docs.is_empty() {
+        docs.push(Yaml::Value(Scalar::Null));
+    }
+    let mut out = Vec::new();
+    for doc in &mut docs {
+        resolve_tree(doc);
+        if multi {
+            out.push(b'-');
+        }
+        encode(doc, options, &mut out);
+    }
+    Ok(out)
+}
+
+pub(crate) fn yaml_equal_impl(
+    a: StringSexp,
+    b: StringSexp,
+    multi: bool,
+    options: CanonicalOptions,
+) -> Fallible<bool> {
+    let a = canonical_text(&text_arg(&a, "a")?, "a", multi, options)?;
+    let b = canonical_text(&text_arg(&b, "b")?, "b", multi, options)?;
+    Ok(a == b)
+}
+
+/// The SHA-256 digest of the canonical form of `x`, in lowercase hex.
+pub(crate) fn yaml_hash_impl(
+    x: StringSexp,
+    multi: bool,
+    options: CanonicalOptions,
+) -> Fallible<String> {
+    let mut bytes = CANONICAL_VERSION.to_vec();
+    bytes.extend(canonical_text(&text_arg(&x, "x")?, "x", multi, options)?);
+    Ok(sha256(&bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 (FIPS 180-4). No hashing crate is vendored, and the digest must
+/// be identical across platforms and releases.
+fn sha256(message: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&((message.len() as u64) * 8).to_be_bytes());
+
+    for block in padded.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (chunk, word) in digest.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT: CanonicalOptions = CanonicalOptions {
+        ignore_tags: false,
+        ignore_order: true,
+    };
+
+    fn hex(bytes: &[u8]) -> String {
+        sha256(bytes).iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    fn same(a: &str, b: &str, options: CanonicalOptions) -> bool {
+        canonical_text(a, "a", false, options).unwrap()
+            == canonical_text(b, "b", false, options).unwrap()
+    }
+
+    #[test]
+    fn sha256_matches_known_digests() {
+        assert_eq!(
+            hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            hex(&[b'a'; 1000]),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    #[test]
+    fn encoding_is_stable() {
+        let text = "b: [x, 'y']\na: 1";
+        let encoded = canonical_text(text, "x", false, DEFAULT).unwrap();
+        assert_eq!(encoded, b"{s1:ai1;s1:b[s1:xs1:y]}");
+        let mut versioned = CANONICAL_VERSION.to_vec();
+        versioned.extend(encoded);
+        assert_eq!(
+            hex(&versioned),
+            "47a6c7f4f53c1a71f4993e3d8bbb942ec38441a87c882dff4ef06ad02935d4b0"
+        );
+    }
+
+    #[test]
+    fn ignores_formatting_and_key_order() {
+        let a = "# settings\ndb:\n  host: localhost\n  port: 5432\ntags: [a, b]\n";
+        let b = "{'tags': [\"a\", b], db: {port: 0x1538, host: localhost}}";
+        assert!(same(a, b, DEFAULT));
+        let ordered = CanonicalOptions {
+            ignore_order: false,
+            ..DEFAULT
+        };
+        assert!(!same(a, b, ordered));
+        assert!(same("{a: 1, b: 2}", "{a: 1, b: 2}", ordered));
+        assert!(!same("[a, b]", "[b, a]", DEFAULT));
+    }
+
+    #[test]
+    fn distinguishes_types_and_tags() {
+        assert!(!same("1", "'1'", DEFAULT));
+        assert!(!same("1", "1.0", DEFAULT));
+        assert!(!same("~", "''", DEFAULT));
+        assert!(!same("[ab]", "[a, b]", DEFAULT));
+        assert!(same("0.0", "-0.0", DEFAULT));
+        assert!(same(".nan", ".NaN", DEFAULT));
+        assert!(same("!!str 1", "'1'", DEFAULT));
+        assert!(same("!!map {a: 1}", "{a: 1}", DEFAULT));
+        assert!(same("!!str a", "!<tag:yaml.org,2002:str> a", DEFAULT));
+        assert!(same("!foo a", "!<!foo> a", DEFAULT));
+        assert!(!same("!secret a", "!vault a", DEFAULT));
+        assert!(!same("!secret a", "a", DEFAULT));
+        let untagged = CanonicalOptions {
+            ignore_tags: true,
+            ..DEFAULT
+        };
+        assert!(same("!secret a", "!vault a", untagged));
+        assert!(same("!set {a: 1}", "{a: 1}", untagged));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::merge::{merge_mapping, normalize_keys, MergeOptions, NullMerge, SequenceMerge};
+use crate::yaml_to_r::resolve_representation;
+use crate::{api_other, Fallible};
+use saphyr::{Mapping, Scalar, Yaml};
+
+const DEFAULT_PROFILE: &str = "default";
+const INHERITS_KEY: &str = "inherits";
+/// Profiles replace sequences, and a null setting overrides the inherited one.
+const MERGE: MergeOptions = MergeOptions {
+    sequences: SequenceMerge::Replace,
+    nulls: NullMerge::Keep,
+    vectors: false,
+};
+
+/// The top-level profiles of a configuration file, keyed by name.
+struct Profiles<'input> {
+    entries: Vec<(String, Yaml<'input>)>,
+}
+
+impl<'input> Profiles<'input> {
+    fn from_document(doc: Yaml<'input>) -> Fallible<Self> {
+        let Yaml::Mapping(map) = doc else {
+            return Err(api_other(
+                "A configuration file must be a mapping of profile names to settings",
+            ));
+        };
+        let entries = map
+            .into_iter()
+            .filter_map(|(mut key, value)| {
+                resolve_representation(&mut key, true);
+                match key {
+                    Yaml::Value(Scalar::String(name)) => Some((name.into_owned(), value)),
+                    _ => None,
+                }
+            })
+            .collect();
+        Ok(Self { entries })
+    }
+
+    fn get(&self, name: &str) -> Option<&Yaml<'input>> {
+        self.entries
+            .iter()
+            .find(|(entry, _)| entry == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The settings of `name` merged over the profiles it names in
+    /// `inherits:`, in order.
+    /// `chain` holds the profiles being resolved, to report cycles.
+    fn resolve(&self, name: &str, chain: &mut Vec<String>) -> Fallible<Mapping<'input>> {
+        if chain.iter().any(|entry| entry == name) {
+            chain.push(name.to_string());
+            return Err(api_other(format!(
+                "Configuration profile inheritance cycle: {}",
+                chain.join(" -> ")
+            )));
+        }
+        let Some(profile) = self.get(name) else {
+            let parent = chain.last().map_or(String::new(), String::clone);
+            return Err(api_other(format!(
+                "Configuration profile `{parent}` inherits from unknown profile `{name}`"
+            )));
+        };
+        let mut settings = match profile.clone() {
+            Yaml::Mapping(map) => normalize_keys(map),
+            Yaml::Value(Scalar::Null) | Yaml::BadValue => Mapping::new(),
+            Yaml::Representation(value, _, None) if value.trim().is_empty() => Mapping::new(),
+            _ => {
+                return Err(api_other(format!(
+                    "Configuration profile `{name}` must be a mapping"
+                )))
+            }
+        };
+        let inherits = take_inherits(&mut settings, name)?;
+
+        chain.push(name.to_string());
+        let mut merged = Mapping::new();
+        for parent in inherits {
+            merge_mapping(&mut merged, self.resolve(&parent, chain)?, MERGE);
+        }
+        chain.pop();
+        merge_mapping(&mut merged, settings, MERGE);
+        Ok(merged)
+    }
+}
+
+/// Select `profile` from a configuration document and merge it over its
+/// parents. The `default` profile is the base layer; an `inherits:` entry
+/// (a profile name or a sequence of names) adds parents that are merged in
+/// order between `default` and the profile itself, recursively. Mappings merge key by
+/// key, recursively; any other value, including sequences and null,
+/// replaces the inherited one. A missing profile falls back to `default`.
+pub(crate) fn select_profile<'input>(doc: Yaml<'input>, profile: &str) -> Fallible<Yaml<'input>> {
+    let profiles = Profiles::from_document(doc)?;
+    let name = if profiles.get(profile).is_some() {
+        profile
+    } else if profiles.get(DEFAULT_PROFILE).is_some() {
+        DEFAULT_PROFILE
+    } else {
+        return Err(api_other(format!(
+            "Configuration profile `{profile}` not found, and there is no `{DEFAULT_PROFILE}` profile"
+        )));
+    };
+    let mut merged = Mapping::new();
+    if name != DEFAULT_PROFILE && profiles.get(DEFAULT_PROFILE).is_some() {
+        merged = profiles.resolve(DEFAULT_PROFILE, &mut Vec::new())?;
+    }
+    merge_mapping(&mut merged, profiles.resolve(name, &mut Vec::new())?, MERGE);
+    Ok(Yaml::Mapping(merged))
+}
+
+fn take_inherits(settings: &mut Mapping<'_>, name: &str) -> Fallible<Vec<String>> {
+    let key = Yaml::Value(Scalar::String(INHERITS_KEY.into()));
+    let Some(mut inherits) = settings.remove(&key) else {
+        return Ok(Vec::new());
+    };
+    let invalid = || {
+        api_other(format!(
+            "`{INHERITS_KEY}` in configuration profile `{name}` must be a profile name or a sequence of names"
+        ))
+    };
+    let mut profile_name = |mut node: Yaml<'_>| {
+        resolve_representation(&mut node, true);
+        match node {
+            Yaml::Value(Scalar::String(parent)) => Ok(parent.into_owned()),
+            _ => Err(invalid()),
+        }
+    };
+    match inherits {
+        Yaml::Sequence(parents) => parents.into_iter().map(&mut profile_name).collect(),
+        _ => {
+            resolve_representation(&mut inherits, true);
+            Ok(vec![profile_name(inherits)?])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yaml_to_r::scan_yaml_documents;
+
+    fn profile(text: &str, name: &str) -> Fallible<String> {
+        let doc = scan_yaml_documents(text, false).unwrap().remove(0);
+        let merged = select_profile(doc, name)?;
+        Ok(render(&merged))
+    }
+
+    fn render(node: &Yaml<'_>) -> String {
+        match node {
+            Yaml::Mapping(map) => {
+                let entries: Vec<String> = map
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", render(key), render(value)))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Yaml::Sequence(items) => {
+                let items: Vec<String> = items.iter().map(render).collect();
+                format!("[{}]", items.join(", "))
+            }
+            Yaml::Representation(value, _, _) => value.to_string(),
+            Yaml::Value(Scalar::String(value)) => value.to_string(),
+            Yaml::Value(Scalar::Null) => "~".to_string(),
+            other => format!("{other:?}"),
+        }
+    }
+
+    const CONFIG: &str = r#"
+default:
+  db: {host: localhost, port: 5432, opts: {ssl: false, pool: 5}}
+  tags: [a, b]
+  debug: true
+staging:
+  db: {host: staging.local, opts: {ssl: true}}
+  tags: [c]
+production:
+  inherits: staging
+  db: {host: prod.local}
+  'debug': false
+audit:
+  inherits: [production, extra]
+extra:
+  log: ~
+"#;
+
+    #[test]
+    fn merges_profiles_over_default() {
+        assert_eq!(
+            profile(CONFIG, "default").unwrap(),
+            "{db: {host: localhost, port: 5432, opts: {ssl: false, pool: 5}}, tags: [a, b], debug: true}"
+        );
+        assert_eq!(
+            profile(CONFIG, "staging").unwrap(),
+            "{db: {host: staging.local, port: 5432, opts: {ssl: true, pool: 5}}, tags: [c], debug: true}"
+        );
+        assert_eq!(
+            profile(CONFIG, "production").unwrap(),
+            "{db: {host: prod.local, port: 5432, opts: {ssl: true, pool: 5}}, tags: [c], debug: false}"
+        );
+        assert_eq!(
+            profile(CONFIG, "audit").unwrap(),
+            "{db: {host: prod.local, port: 5432, opts: {ssl: true, pool: 5}}, tags: [c], debug: false, log: ~}"
+        );
+        assert_eq!(
+            profile(CONFIG, "missing").unwrap(),
+            profile(CONFIG, "default").unwrap()
+        );
+    }
+
+    #[test]
+    fn reports_bad_profiles() {
+        let error = |text: &str, name: &str| match profile(text, name) {
+            Err(savvy::Error::GeneralError(message)) => message,
+            other => panic!("expected an error, got {other:?}"),
+        };
+        assert_eq!(
+            error("a: {inherits: b}\nb: {inherits: a}", "a"),
+            "Configuration profile inheritance cycle: a -> b -> a"
+        );
+        assert_eq!(
+            error("a: {inherits: nope}", "a"),
+            "Configuration profile `a` inherits from unknown profile `nope`"
+        );
+        assert_eq!(
+            error("a: [1]", "a"),
+            "Configuration profile `a` must be a mapping"
+        );
+        assert!(error("a: {inherits: {x: 1}}", "a").contains("must be a profile name"));
+        assert!(error("a: 1", "b").contains("no `default` profile"));
+        assert!(error("[1]", "b").contains("must be a mapping of profile names"));
+    }
+}
@@ -0,0 +1,322 @@
+use crate::emitter::{format_tag, YamlEmitter};
+use crate::r_ext::{self, text_arg};
+use crate::r_to_yaml::yaml_body;
+use crate::tree::load_first;
+use crate::yaml_to_r::yaml_value_to_robj;
+use crate::Fallible;
+use saphyr::{Mapping, Scalar, Yaml};
+use savvy::{OwnedListSexp, Sexp, StringSexp};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+    /// The same keys appear in a different order.
+    Reordered,
+}
+
+impl ChangeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+            Self::Reordered => "reordered",
+        }
+    }
+}
+
+/// One difference, at the location named by the JSON Pointer `path`.
+#[derive(Debug)]
+pub(crate) struct Change<'a> {
+    pub(crate) path: String,
+    pub(crate) kind: ChangeKind,
+    pub(crate) old: Option<Yaml<'a>>,
+    pub(crate) new: Option<Yaml<'a>>,
+}
+
+impl Change<'_> {
+    /// A line such as `~ /db/port: 5432 -> 5433` for the text report.
+    fn report_line(&self) -> String {
+        let path = if self.path.is_empty() {
+            "(root)"
+        } else {
+            &self.path
+        };
+        let old = self.old.as_ref().map(flow);
+        let new = self.new.as_ref().map(flow);
+        match self.kind {
+            ChangeKind::Added => format!("+ {path}: {}", new.unwrap_or_default()),
+            ChangeKind::Removed => format!("- {path}: {}", old.unwrap_or_default()),
+            ChangeKind::Changed => format!(
+                "~ {path}: {} -> {}",
+                old.unwrap_or_default(),
+                new.unwrap_or_default()
+            ),
+            ChangeKind::Reordered => format!(
+                "~ {path} (key order): {} -> {}",
+                old.unwrap_or_default(),
+                new.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Collects the differences between two resolved trees.
+struct Differ<'a> {
+    ignore_order: bool,
+    changes: Vec<Change<'a>>,
+}
+
+impl<'a> Differ<'a> {
+    fn push(
+        &mut self,
+        path: &str,
+        kind: ChangeKind,
+        old: Option<&Yaml<'a>>,
+        new: Option<&Yaml<'a>>,
+    ) {
+        self.changes.push(Change {
+            path: path.to_string(),
+            kind,
+            old: old.cloned(),
+            new: new.cloned(),
+        });
+    }
+
+    fn diff(&mut self, old: &Yaml<'a>, new: &Yaml<'a>, path: &mut String) {
+        match (old, new) {
+            (Yaml::Mapping(old), Yaml::Mapping(new)) => self.diff_mappings(old, new, path),
+            (Yaml::Sequence(old), Yaml::Sequence(new)) => self.diff_sequences(old, new, path),
+            (Yaml::Tagged(old_tag, old_inner), Yaml::Tagged(new_tag, new_inner))
+                if old_tag == new_tag =>
+            {
+                self.diff(old_inner, new_inner, path)
+            }
+            (old, new) if old == new => {}
+            (old, new) => self.push(path, ChangeKind::Changed, Some(old), Some(new)),
+        }
+    }
+
+    fn diff_mappings(&mut self, old: &Mapping<'a>, new: &Mapping<'a>, path: &mut String) {
+        if !self.ignore_order {
+            let shared = |a: &Mapping<'a>, b: &Mapping<'a>| -> Vec<Yaml<'a>> {
+                a.keys()
+                    .filter(|key| b.contains_key(key))
+                    .cloned()
+                    .collect()
+            };
+            let old_keys = shared(old, new);
+            let new_keys = shared(new, old);
+            if old_keys != new_keys {
+                self.push(
+                    path,
+                    ChangeKind::Reordered,
+                    Some(&Yaml::Sequence(old_keys)),
+                    Some(&Yaml::Sequence(new_keys)),
+                );
+            }
+        }
+        let len = path.len();
+        for (key, old_value) in old {
+            push_token(path, key);
+            match new.get(key) {
+                Some(new_value) => self.diff(old_value, new_value, path),
+                None => self.push(path, ChangeKind::Removed, Some(old_value), None),
+            }
+            path.truncate(len);
+        }
+        for (key, new_value) in new {
+            if !old.contains_key(key) {
+                push_token(path, key);
+                self.push(path, ChangeKind::Added, None, Some(new_value));
+                path.truncate(len);
+            }
+        }
+    }
+
+    fn diff_sequences(&mut self, old: &[Yaml<'a>], new: &[Yaml<'a>], path: &mut String) {
+        let len = path.len();
+        for index in 0..old.len().max(new.len()) {
+            path.push('/');
+            path.push_str(&index.to_string());
+            match (old.get(index), new.get(index)) {
+                (Some(old), Some(new)) => self.diff(old, new, path),
+                (Some(old), None) => self.push(path, ChangeKind::Removed, Some(old), None),
+                (None, new) => self.push(path, ChangeKind::Added, None, new),
+            }
+            path.truncate(len);
+        }
+    }
+}
+
+/// The differences between resolved trees `old` and `new`, in document
+/// order: changes within `old` first, then keys only `new` has.
+pub(crate) fn diff_nodes<'a>(
+    old: &Yaml<'a>,
+    new: &Yaml<'a>,
+    ignore_order: bool,
+) -> Vec<Change<'a>> {
+    let mut differ = Differ {
+        ignore_order,
+        changes: Vec::new(),
+    };
+    differ.diff(old, new, &mut String::new());
+    differ.changes
+}
+
+/// Append `/token` for mapping key `key`, escaped as in RFC 6901.
+fn push_token(path: &mut String, key: &Yaml<'_>) {
+    let token = match key {
+        Yaml::Value(Scalar::String(key)) => key.to_string(),
+        other => flow(other),
+    };
+    path.push('/');
+    path.push_str(&token.replace('~', "~0").replace('/', "~1"));
+}
+
+/// Render `node` on one line, with collections in flow style.
+fn flow(node: &Yaml<'_>) -> String {
+    match node {
+        Yaml::Mapping(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{}: {}", flow(key), flow(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Yaml::Sequence(items) => {
+            let items: Vec<String> = items.iter().map(flow).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Yaml::Tagged(tag, inner) => format!("{} {}", format_tag(tag), flow(inner)),
+        scalar => {
+            let mut out = String::new();
+            match YamlEmitter::new(&mut out).dump(scalar) {
+                Ok(()) => yaml_body(&out, false).to_string(),
+                Err(_) => String::new(),
+            }
+        }
+    }
+}
+
+fn value_or_null(node: Option<Yaml<'_>>, simplify: bool) -> Fallible<Sexp> {
+    match node {
+        Some(mut node) => yaml_value_to_robj(&mut node, simplify),
+        None => Ok(r_ext::null()),
+    }
+}
+
+/// Compare the first documents of YAML texts `old` and `new`. Returns a list
+/// of `path`, `change`, `old`, and `new` columns, or the lines of a text
+/// report when `report` is set.
+pub(crate) fn yaml_diff_impl(
+    old: StringSexp,
+    new: StringSexp,
+    ignore_order: bool,
+    report: bool,
+    simplify: bool,
+) -> Fallible<Sexp> {
+    let old_text = text_arg(&old, "old")?;
+    let new_text = text_arg(&new, "new")?;
+    let old = load_first(&old_text, "old")?;
+    let new = load_first(&new_text, "new")?;
+    let changes = diff_nodes(&old, &new, ignore_order);
+
+    if report {
+        let lines: Vec<String> = changes.iter().map(Change::report_line).collect();
+        let lines = lines
+            .iter()
+            .map(|line| r_ext::string_data(line))
+            .collect::<Fallible<Vec<_>>>()?;
+        return r_ext::materialize_string_vector(&lines);
+    }
+
+    let paths = changes
+        .iter()
+        .map(|change| r_ext::string_data(&change.path))
+        .collect::<Fallible<Vec<_>>>()?;
+    let kinds = changes
+        .iter()
+        .map(|change| r_ext::string_data(change.kind.as_str()))
+        .collect::<Fallible<Vec<_>>>()?;
+    let mut columns = OwnedListSexp::new(4, true)?;
+    columns.set_name_and_value(0, "path", r_ext::materialize_string_vector(&paths)?)?;
+    columns.set_name_and_value(1, "change", r_ext::materialize_string_vector(&kinds)?)?;
+    let mut old_values = OwnedListSexp::new(changes.len(), false)?;
+    let mut new_values = OwnedListSexp::new(changes.len(), false)?;
+    for (index, change) in changes.into_iter().enumerate() {
+        old_values.set_value(index, value_or_null(change.old, simplify)?)?;
+        new_values.set_value(index, value_or_null(change.new, simplify)?)?;
+    }
+    columns.set_name_and_value(2, "old", old_values)?;
+    columns.set_name_and_value(3, "new", new_values)?;
+    Ok(columns.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(old: &str, new: &str, ignore_order: bool) -> Vec<String> {
+        let old = load_first(old, "old").unwrap();
+        let new = load_first(new, "new").unwrap();
+        diff_nodes(&old, &new, ignore_order)
+            .iter()
+            .map(Change::report_line)
+            .collect()
+    }
+
+    #[test]
+    fn ignores_formatting() {
+        let old = "db:\n  host: localhost\n  port: 5432\ntags: [a, b]\n";
+        let new = "# comment\n'tags': ['a', \"b\"]\ndb: {\"port\": 0x1538, host: localhost}\n";
+        assert!(report(old, new, true).is_empty());
+        assert_eq!(
+            report(old, new, false),
+            [
+                "~ (root) (key order): [db, tags] -> [tags, db]",
+                "~ /db (key order): [host, port] -> [port, host]"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_paths() {
+        let old = "{db: {host: localhost, port: 5432}, tags: [a, b, c], a/b: 1, debug: true}";
+        let new = "{db: {host: db.local, port: 5432, ssl: {mode: require}}, tags: [a, x], a/b: 1}";
+        assert_eq!(
+            report(old, new, true),
+            [
+                "~ /db/host: localhost -> db.local",
+                "+ /db/ssl: {mode: require}",
+                "~ /tags/1: b -> x",
+                "- /tags/2: c",
+                "- /debug: true",
+            ]
+        );
+        assert_eq!(
+            report("{a/b: 1, m~n: 2}", "{a/b: 2}", true),
+            ["~ /a~1b: 1 -> 2", "- /m~0n: 2"]
+        );
+        assert_eq!(
+            report("{1: x}", "{1: y, '1': z}", true),
+            ["~ /1: x -> y", "+ /1: z"]
+        );
+    }
+
+    #[test]
+    fn respects_tags() {
+        assert_eq!(
+            report(
+                "{a: !secret x, b: !set {k: 1}}",
+                "{a: !vault x, b: !set {k: 2}}",
+                true
+            ),
+            ["~ /a: !secret x -> !vault x", "~ /b/k: 1 -> 2"]
+        );
+        assert_eq!(report("!!str 1", "'1'", true), Vec::<String>::new());
+        assert_eq!(report("1", "'1'", true), ["~ (root): 1 -> \"1\""]);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::query::{key_text, lookup, untagged};
+use crate::r_ext;
+use crate::yaml_to_r::{detached_to_robj, scan_yaml_documents, ParseOptions};
+use crate::{api_other, Fallible};
+use saphyr::{Scalar, Yaml};
+use savvy::{ExternalPointerSexp, IntoExtPtrSexp, ListSexp, Sexp, TypedSexp};
+use std::borrow::Cow;
+
+/// A parsed document kept alive behind an R external pointer, so that
+/// subtrees can be looked up and converted without parsing again.
+pub(crate) struct YamlDocument {
+    root: Yaml<'static>,
+}
+
+impl IntoExtPtrSexp for YamlDocument {}
+
+impl YamlDocument {
+    /// Parse the first document of `text`.
+    pub(crate) fn parse(text: &str) -> Fallible<Self> {
+        let docs = scan_yaml_documents(text, false)
+            .map_err(|err| api_other(format!("YAML parse error: {err}")))?;
+        let root = docs.into_iter().next().unwrap_or(Yaml::Value(Scalar::Null));
+        Ok(Self {
+            root: into_static(root),
+        })
+    }
+
+    /// Move the document behind an R external pointer tagged as a
+    /// `yaml_document`.
+    pub(crate) fn into_handle(self) -> Sexp {
+        let handle = self.into_external_pointer();
+        r_ext::set_external_pointer_tag(&handle, r_ext::sym_yaml_document());
+        handle
+    }
+
+    /// Borrow the document held by external pointer `handle`, which must
+    /// have been made by [`YamlDocument::into_handle`].
+    pub(crate) fn from_handle(handle: &Sexp) -> Fallible<&Self> {
+        let pointer = ExternalPointerSexp::try_from(Sexp(handle.0))?;
+        if !r_ext::has_external_pointer_tag(handle, r_ext::sym_yaml_document()) {
+            return Err(api_other("Expected the pointer of a `yaml_document`"));
+        }
+        // Only `into_handle` tags a pointer as a `yaml_document`.
+        let document = unsafe { pointer.cast_unchecked::<Self>() };
+        // The address is null once the pointer has been saved and reloaded.
+        unsafe { document.as_ref() }.ok_or_else(|| {
+            api_other("`yaml_document` is no longer valid; was it saved and reloaded?")
+        })
+    }
+
+    /// The node at `path`, if there is one. Each step of `path` is a key,
+    /// or a 0-based position among the entries of a mapping or the items of
+    /// a sequence.
+    pub(crate) fn get(&self, path: &ListSexp) -> Fallible<Option<&Yaml<'static>>> {
+        let mut node = &self.root;
+        for step in path.values_iter() {
+            let child = match step.into_typed() {
+                TypedSexp::Integer(position) if position.len() == 1 => {
+                    usize::try_from(position.as_slice()[0])
+                        .ok()
+                        .and_then(|position| child_at(node, position))
+                }
+                TypedSexp::String(key) if key.len() == 1 => {
+                    lookup(node, &[r_ext::string_elt(&key, 0)?])
+                }
+                _ => return Err(api_other("`path` must hold keys and positions")),
+            };
+            match child {
+                Some(child) => node = child,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(node))
+    }
+
+    /// The node at `path`, which the R wrappers only build from names and
+    /// indices that exist.
+    pub(crate) fn node(&self, path: &ListSexp) -> Fallible<&Yaml<'static>> {
+        self.get(path)?
+            .ok_or_else(|| api_other("`path` does not name a node in the document"))
+    }
+}
+
+/// The value of the entry at `position` in a mapping, or the item at
+/// `position` in a sequence.
+fn child_at<'n>(node: &'n Yaml<'static>, position: usize) -> Option<&'n Yaml<'static>> {
+    match untagged(node) {
+        Yaml::Mapping(map) => map.values().nth(position),
+        Yaml::Sequence(items) => items.get(position),
+        _ => None,
+    }
+}
+
+/// Copy borrowed text in `node` so it no longer refers to the source.
+pub(crate) fn into_static(node: Yaml<'_>) -> Yaml<'static> {
+    let owned = |text: Cow<'_, str>| Cow::Owned(text.into_owned());
+    match node {
+        Yaml::Representation(value, style, tag) => Yaml::Representation(
+            owned(value),
+            style,
+            tag.map(|tag| Cow::Owned(tag.into_owned())),
+        ),
+        Yaml::Value(Scalar::String(value)) => Yaml::Value(Scalar::String(owned(value))),
+        Yaml::Value(Scalar::Null) => Yaml::Value(Scalar::Null),
+        Yaml::Value(Scalar::Boolean(value)) => Yaml::Value(Scalar::Boolean(value)),
+        Yaml::Value(Scalar::Integer(value)) => Yaml::Value(Scalar::Integer(value)),
+        Yaml::Value(Scalar::FloatingPoint(value)) => Yaml::Value(Scalar::FloatingPoint(value)),
+        Yaml::Sequence(items) => Yaml::Sequence(items.into_iter().map(into_static).collect()),
+        Yaml::Mapping(map) => Yaml::Mapping(
+            map.into_iter()
+                .map(|(key, value)| (into_static(key), into_static(value)))
+                .collect(),
+        ),
+        Yaml::Tagged(tag, inner) => {
+            Yaml::Tagged(Cow::Owned(tag.into_owned()), Box::new(into_static(*inner)))
+        }
+        Yaml::Alias(id) => Yaml::Alias(id),
+        Yaml::BadValue => Yaml::BadValue,
+    }
+}
+
+/// `"mapping"`, `"sequence"`, or `"scalar"`, looking through tags.
+pub(crate) fn node_kind(node: &Yaml<'_>) -> &'static str {
+    match untagged(node) {
+        Yaml::Mapping(_) => "mapping",
+        Yaml::Sequence(_) => "sequence",
+        _ => "scalar",
+    }
+}
+
+/// The number of entries or items in `node`; a scalar counts as one.
+pub(crate) fn node_length(node: &Yaml<'_>) -> usize {
+    match untagged(node) {
+        Yaml::Mapping(map) => map.len(),
+        Yaml::Sequence(items) => items.len(),
+        _ => 1,
+    }
+}
+
+/// The keys of a mapping as text, `""` for a key with no text form, or
+/// `NULL` for any other node.
+pub(crate) fn node_names(node: &Yaml<'_>) -> Fallible<Sexp> {
+    let Yaml::Mapping(map) = untagged(node) else {
+        return Ok(r_ext::null());
+    };
+    let keys: Vec<Cow<'_, str>> = map
+        .keys()
+        .map(|key| key_text(key).unwrap_or_default())
+        .collect();
+    let keys = keys
+        .iter()
+        .map(|key| r_ext::string_data(key))
+        .collect::<Fallible<Vec<_>>>()?;
+    r_ext::materialize_string_vector(&keys)
+}
+
+/// Convert `node` as a document root, as `parse_yaml()` would.
+pub(crate) fn node_to_robj(
+    node: &Yaml<'_>,
+    handlers: &Sexp,
+    options: &ParseOptions,
+) -> Fallible<Sexp> {
+    detached_to_robj(&mut node.clone(), handlers, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn owns_subtrees_after_the_source_is_gone() {
+        let text = String::from("a: !t {b: [1, x]}\n'c': 'y'\n");
+        let document = YamlDocument::parse(&text).unwrap();
+        drop(text);
+
+        let a = lookup(&document.root, &["a"]).unwrap();
+        assert_eq!(node_kind(a), "mapping");
+        assert_eq!(node_length(a), 1);
+        let b = lookup(&document.root, &["a", "b"]).unwrap();
+        assert_eq!(node_kind(b), "sequence");
+        assert_eq!(node_length(b), 2);
+        let x = lookup(&document.root, &["a", "b", "1"]).unwrap();
+        assert!(matches!(x, Yaml::Representation(value, _, _) if value == "x"));
+        assert_eq!(node_kind(x), "scalar");
+        assert!(lookup(&document.root, &["c"]).is_some());
+        assert!(lookup(&document.root, &["a", "b", "2"]).is_none());
+        assert_eq!(child_at(&document.root, 1), lookup(&document.root, &["c"]));
+        assert_eq!(child_at(b, 1), Some(x));
+        assert!(child_at(b, 2).is_none());
+        assert!(child_at(x, 0).is_none());
+    }
+
+    #[test]
+    fn empty_input_is_a_null_document() {
+        let document = YamlDocument::parse("").unwrap();
+        assert_eq!(node_kind(&document.root), "scalar");
+        assert_eq!(document.root, Yaml::Value(Scalar::Null));
+    }
+}
@@ -0,0 +1,1209 @@
+//! Surgical edits: set or delete one value in YAML text, leaving every other
+//! character as written.
+//!
+//! The parser's events give the position of each node of the first document,
+//! and the `:`, `-`, or flow punctuation before each value is found in the
+//! text. A new value is emitted on its own, re-indented to where it goes, and
+//! spliced over the old value's text; a deleted entry's lines are cut out.
+
+use crate::emitter::{escape_str, YamlEmitter};
+use crate::events::{parse_events, EventKind};
+use crate::query::{Query, Step};
+use crate::r_to_yaml::yaml_body;
+use crate::reformat::quoted_end;
+use crate::tree::{resolve_tree, yaml_equal};
+use crate::yaml_to_r::scan_yaml_documents;
+use crate::{api_other, Fallible};
+use saphyr::{Mapping, Scalar, Yaml, YamlLoader};
+use saphyr_parser::{Event, Parser, ScanError, Span, SpannedEventReceiver};
+use std::ops::Range;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Scalar,
+    Empty,
+    Alias,
+    Sequence,
+    Mapping,
+}
+
+struct Node {
+    kind: Kind,
+    /// The value of a scalar, which keys are matched by, or the anchor an
+    /// alias refers to.
+    value: String,
+    anchor: Option<String>,
+    flow: bool,
+    /// Whether the node is a literal or folded scalar.
+    block_scalar: bool,
+    /// Whether the node is a flow collection in brackets, rather than a
+    /// single pair in a flow sequence, as in `[a: b]`.
+    bracketed: bool,
+    /// Character offsets of the node's content, after its properties.
+    start: usize,
+    end: usize,
+    /// The offset just after the indicator before a value or item: `:`,
+    /// `-`, or in a flow sequence, `[` or `,`.
+    after_indicator: Option<usize>,
+    /// Items of a sequence, or keys and values of a mapping.
+    children: Vec<usize>,
+    /// The node's events, as indices into the events of the text.
+    events: Range<usize>,
+}
+
+impl Node {
+    fn is_collection(&self) -> bool {
+        matches!(self.kind, Kind::Sequence | Kind::Mapping)
+    }
+}
+
+/// Where a query leads.
+enum Target {
+    /// The value at `children[slot]` of collection `parent`.
+    Existing { parent: usize, slot: usize },
+    /// A key missing from the mapping `parent`.
+    NewKey { parent: usize, key: String },
+}
+
+/// What an edit means to change, for checking the edited text.
+enum Change<'v> {
+    /// Node `index` becomes `value`.
+    Replace { index: usize, value: &'v Yaml<'v> },
+    /// `key: value` is added at the end of the mapping `parent`.
+    Add {
+        parent: usize,
+        key: String,
+        value: &'v Yaml<'v>,
+    },
+    /// The entry `slot` of the collection `parent` is removed.
+    Remove { parent: usize, slot: usize },
+}
+
+/// The nodes of the first document of some YAML text, with their positions.
+struct Tree {
+    chars: Vec<char>,
+    nodes: Vec<Node>,
+    newline: &'static str,
+}
+
+/// Render `value` as a root node.
+fn emit(value: &Yaml) -> Fallible<String> {
+    let mut out = String::new();
+    let mut emitter = YamlEmitter::new(&mut out);
+    emitter.multiline_strings(true);
+    emitter
+        .dump(value)
+        .map_err(|err| api_other(err.to_string()))?;
+    Ok(yaml_body(&out, false).to_string())
+}
+
+fn emit_key(key: &str) -> Fallible<String> {
+    let mut out = String::new();
+    YamlEmitter::new(&mut out)
+        .emit_string(key, -1, true)
+        .map_err(|err| api_other(err.to_string()))?;
+    Ok(out)
+}
+
+fn is_blank(ch: char) -> bool {
+    ch == ' ' || ch == '\t'
+}
+
+impl Tree {
+    fn parse(text: &str) -> Fallible<Self> {
+        let events =
+            parse_events(text).map_err(|err| api_other(format!("YAML parse error: {err}")))?;
+        let chars: Vec<char> = text.chars().collect();
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut open: Vec<usize> = Vec::new();
+        for (position, event) in events.iter().enumerate() {
+            let (kind, end) = match event.kind {
+                EventKind::StreamStart | EventKind::DocumentStart => continue,
+                EventKind::DocumentEnd | EventKind::StreamEnd => break,
+                EventKind::MappingEnd | EventKind::SequenceEnd => {
+                    if let Some(index) = open.pop() {
+                        if nodes[index].flow {
+                            nodes[index].end = event.span.start + 1;
+                        }
+                        nodes[index].events.end = position + 1;
+                    }
+                    continue;
+                }
+                EventKind::MappingStart => (Kind::Mapping, event.span.start),
+                EventKind::SequenceStart => (Kind::Sequence, event.span.start),
+                EventKind::Alias => {
+                    let name = event.anchor.as_deref().unwrap_or_default();
+                    (Kind::Alias, event.span.start + 1 + name.chars().count())
+                }
+                EventKind::Scalar => match (event.style, event.value.as_deref()) {
+                    (Some("plain"), Some("")) => (Kind::Empty, event.span.start),
+                    (Some("single_quoted" | "double_quoted"), _) => {
+                        (Kind::Scalar, quoted_end(&chars, event.span.start))
+                    }
+                    // The span of a block scalar takes in the blank lines
+                    // after it.
+                    (Some("literal" | "folded"), _) => {
+                        let mut end = event.span.end.min(chars.len());
+                        while end > event.span.start && chars[end - 1].is_whitespace() {
+                            end -= 1;
+                        }
+                        (Kind::Scalar, end)
+                    }
+                    _ => (Kind::Scalar, event.span.end),
+                },
+            };
+            let index = nodes.len();
+            nodes.push(Node {
+                kind,
+                value: match kind {
+                    Kind::Alias => event.anchor.clone(),
+                    _ => event.value.clone(),
+                }
+                .unwrap_or_default(),
+                anchor: match kind {
+                    Kind::Alias => None,
+                    _ => event.anchor.clone(),
+                },
+                flow: event.style == Some("flow"),
+                block_scalar: matches!(event.style, Some("literal" | "folded")),
+                bracketed: false,
+                start: event.span.start,
+                end,
+                after_indicator: None,
+                children: Vec::new(),
+                events: position..position + 1,
+            });
+            if let Some(&parent) = open.last() {
+                nodes[parent].children.push(index);
+            }
+            if nodes[index].is_collection() {
+                open.push(index);
+            }
+        }
+        let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        let mut tree = Tree {
+            chars,
+            nodes,
+            newline,
+        };
+        if let Some(root) = tree.nodes.first() {
+            if root.kind == Kind::Sequence && !root.flow {
+                if let Some(dash) = tree.first_dash(root.start) {
+                    tree.nodes[0].start = dash;
+                }
+            }
+            tree.locate(0);
+        }
+        Ok(tree)
+    }
+
+    /// Find the indicators before the children of the collection at `index`,
+    /// place its empty children after them, and find where the collection
+    /// ends: at its closing bracket, or the end of its last child.
+    fn locate(&mut self, index: usize) {
+        if !self.nodes[index].is_collection() {
+            return;
+        }
+        let children = self.nodes[index].children.clone();
+        let (kind, flow, start) = {
+            let node = &self.nodes[index];
+            (node.kind, node.flow, node.start)
+        };
+        let bracketed = self.is_bracketed(index);
+        self.nodes[index].bracketed = bracketed;
+        let mut cursor = start;
+        for (position, &child) in children.iter().enumerate() {
+            let is_key = kind == Kind::Mapping && position % 2 == 0;
+            let (from, after) = if is_key {
+                // Only an explicit key has an indicator, `?`.
+                let from = match bracketed {
+                    true => {
+                        let separator = if position == 0 { '{' } else { ',' };
+                        self.find_indicator(cursor, separator)
+                            .map_or(cursor, |at| at + 1)
+                    }
+                    false => cursor,
+                };
+                (from, self.find_indicator(from, '?').map(|at| at + 1))
+            } else {
+                let (from, indicator) = match kind {
+                    Kind::Mapping => (self.nodes[children[position - 1]].end, ':'),
+                    _ if !flow => (cursor, '-'),
+                    _ if position == 0 => (cursor, '['),
+                    _ => (cursor, ','),
+                };
+                (from, self.find_indicator(from, indicator).map(|at| at + 1))
+            };
+            self.nodes[child].after_indicator = after;
+            let node = &self.nodes[child];
+            // A block sequence starts at its first `-`, which for a
+            // sequence in a compact sequence is not where its span starts.
+            if node.kind == Kind::Sequence && !node.flow {
+                let dash = match after {
+                    Some(after) => self.find_indicator(self.skip_props(after, false), '-'),
+                    None => None,
+                };
+                if let Some(dash) = dash.or_else(|| self.first_dash(node.start)) {
+                    self.nodes[child].start = dash;
+                }
+            }
+            let node = &self.nodes[child];
+            if node.kind == Kind::Empty {
+                // An empty node goes after its indicator and properties.
+                let at = self.skip_props(after.unwrap_or(from), flow);
+                self.nodes[child].start = at;
+                self.nodes[child].end = at;
+            } else if node.block_scalar {
+                // A block scalar starts at its header, and without content
+                // its span starts at the next token.
+                let header = after.map(|after| self.skip_blank(self.skip_props(after, false)));
+                if let Some(header) =
+                    header.filter(|&at| matches!(self.chars.get(at), Some('|' | '>')))
+                {
+                    let header_end = self.line_end(header);
+                    let mut end = node.end.max(header_end);
+                    while end > header_end && self.chars[end - 1].is_whitespace() {
+                        end -= 1;
+                    }
+                    self.nodes[child].start = header;
+                    self.nodes[child].end = end;
+                }
+            }
+            self.locate(child);
+            cursor = self.nodes[child].end;
+        }
+        if bracketed {
+            let from = if children.is_empty() {
+                start + 1
+            } else {
+                cursor
+            };
+            if let Some(end) = self.closing_bracket(from) {
+                self.nodes[index].end = end;
+            }
+        } else {
+            self.nodes[index].end = cursor;
+        }
+    }
+
+    /// Whether the flow collection at `index` is written in brackets, before
+    /// its children are located.
+    fn is_bracketed(&self, index: usize) -> bool {
+        let node = &self.nodes[index];
+        let bracket = match node.kind {
+            Kind::Sequence => '[',
+            _ => '{',
+        };
+        node.flow
+            && self.chars.get(node.start) == Some(&bracket)
+            && !(node.kind == Kind::Mapping
+                && node
+                    .children
+                    .first()
+                    .is_some_and(|&key| self.nodes[key].start == node.start))
+    }
+
+    fn skip_blank(&self, mut at: usize) -> usize {
+        while at < self.chars.len() && is_blank(self.chars[at]) {
+            at += 1;
+        }
+        at
+    }
+
+    /// The offset after white space and comments from `from`.
+    fn skip_space(&self, from: usize) -> usize {
+        let mut at = from;
+        while at < self.chars.len() {
+            match self.chars[at] {
+                '#' if at == 0 || self.chars[at - 1].is_whitespace() => {
+                    while at < self.chars.len() && self.chars[at] != '\n' {
+                        at += 1;
+                    }
+                }
+                ch if ch.is_whitespace() => at += 1,
+                _ => break,
+            }
+        }
+        at
+    }
+
+    /// The offset of `indicator` after `from`, past white space and comments.
+    fn find_indicator(&self, from: usize, indicator: char) -> Option<usize> {
+        let at = self.skip_space(from);
+        (self.chars.get(at) == Some(&indicator)).then_some(at)
+    }
+
+    /// The offset after the anchor and tag written from `at` on its line,
+    /// or `at` if there are none.
+    fn skip_props(&self, at: usize, flow: bool) -> usize {
+        let mut end = at;
+        loop {
+            let mut token = self.skip_blank(end);
+            if !matches!(self.chars.get(token), Some('&' | '!')) {
+                return end;
+            }
+            while token < self.chars.len()
+                && !self.chars[token].is_whitespace()
+                && !(flow && matches!(self.chars[token], ',' | '[' | ']' | '{' | '}'))
+            {
+                token += 1;
+            }
+            end = token;
+        }
+    }
+
+    /// The offset after the `]` or `}` closing a flow collection whose last
+    /// child ends at `from`, past a trailing comma.
+    fn closing_bracket(&self, from: usize) -> Option<usize> {
+        let mut at = self.skip_space(from);
+        if self.chars.get(at) == Some(&',') {
+            at = self.skip_space(at + 1);
+        }
+        matches!(self.chars.get(at), Some(']' | '}')).then_some(at + 1)
+    }
+
+    /// The first `-` of a block sequence whose span starts at `start`: at
+    /// `start`, or for a sequence at the indentation of its mapping's keys,
+    /// before its first item.
+    fn first_dash(&self, start: usize) -> Option<usize> {
+        let is_dash = |at: usize| {
+            self.chars[at] == '-' && self.chars.get(at + 1).map_or(true, |ch| ch.is_whitespace())
+        };
+        if start < self.chars.len() && is_dash(start) {
+            return Some(start);
+        }
+        let mut at = start;
+        while at > 0 {
+            at -= 1;
+            let ch = self.chars[at];
+            if ch.is_whitespace() {
+                continue;
+            }
+            // Step back over a comment between the dash and the item.
+            let line = self.line_start(at);
+            if let Some(comment) = (line..=at)
+                .find(|&i| self.chars[i] == '#' && (i == line || self.chars[i - 1].is_whitespace()))
+            {
+                at = comment;
+                continue;
+            }
+            return is_dash(at).then_some(at);
+        }
+        None
+    }
+
+    fn line_start(&self, at: usize) -> usize {
+        self.chars[..at.min(self.chars.len())]
+            .iter()
+            .rposition(|&ch| ch == '\n')
+            .map_or(0, |newline| newline + 1)
+    }
+
+    /// The offset of the line break ending the line at `at`, or the end.
+    fn line_end(&self, at: usize) -> usize {
+        let end = self.chars[at.min(self.chars.len())..]
+            .iter()
+            .position(|&ch| ch == '\n')
+            .map_or(self.chars.len(), |newline| at + newline);
+        match end.checked_sub(1) {
+            Some(before) if self.chars.get(before) == Some(&'\r') => before,
+            _ => end,
+        }
+    }
+
+    /// The offset after the line break ending the line at `at`.
+    fn next_line(&self, at: usize) -> usize {
+        self.chars[at.min(self.chars.len())..]
+            .iter()
+            .position(|&ch| ch == '\n')
+            .map_or(self.chars.len(), |newline| at + newline + 1)
+    }
+
+    fn column(&self, at: usize) -> usize {
+        at - self.line_start(at)
+    }
+
+    /// Whether only blanks come before `at` on its line.
+    fn starts_line(&self, at: usize) -> bool {
+        self.chars[self.line_start(at)..at]
+            .iter()
+            .all(|&ch| is_blank(ch))
+    }
+
+    /// The start of the entry `slot` of collection `parent`: its `?`, its
+    /// key with the key's properties, or its `-`.
+    fn entry_start(&self, parent: usize, slot: usize) -> usize {
+        let node = &self.nodes[parent];
+        let child = &self.nodes[node.children[slot]];
+        if node.kind == Kind::Sequence {
+            return match (node.flow, child.after_indicator) {
+                (false, Some(after)) => after - 1,
+                _ => child.start,
+            };
+        }
+        let key = &self.nodes[node.children[slot - 1]];
+        if let Some(after) = key.after_indicator {
+            return after - 1;
+        }
+        // Step back over an anchor or tag written before the key.
+        let mut start = key.start;
+        loop {
+            let mut at = start;
+            while at > 0 && is_blank(self.chars[at - 1]) {
+                at -= 1;
+            }
+            let mut token = at;
+            while token > 0 && !self.chars[token - 1].is_whitespace() {
+                token -= 1;
+            }
+            if token < at && matches!(self.chars[token], '&' | '!') {
+                start = token;
+            } else {
+                return start;
+            }
+        }
+    }
+
+    /// The index after the last node of the subtree at `index`.
+    fn subtree_end(&self, index: usize) -> usize {
+        match self.nodes[index].children.last() {
+            Some(&last) => self.subtree_end(last),
+            None => index + 1,
+        }
+    }
+
+    /// An anchor defined by the nodes `removed` that an alias after them
+    /// refers to, skipping the first node's anchor when `keep_first`.
+    fn aliased_anchor(&self, removed: Range<usize>, keep_first: bool) -> Option<&str> {
+        let skip = usize::from(keep_first);
+        removed
+            .clone()
+            .skip(skip)
+            .filter_map(|index| self.nodes[index].anchor.as_deref())
+            .find(|&anchor| {
+                self.nodes[removed.end..]
+                    .iter()
+                    .take_while(|node| node.anchor.as_deref() != Some(anchor))
+                    .any(|node| node.kind == Kind::Alias && node.value == anchor)
+            })
+    }
+
+    /// The slot of the value in each entry of collection `parent`.
+    fn value_slots(&self, parent: usize) -> Vec<usize> {
+        let node = &self.nodes[parent];
+        match node.kind {
+            Kind::Mapping => (1..node.children.len()).step_by(2).collect(),
+            _ => (0..node.children.len()).collect(),
+        }
+    }
+
+    fn resolve(&self, source: &str, steps: &[Step]) -> Fallible<Target> {
+        if self.nodes.is_empty() || steps.is_empty() {
+            return Err(api_other(format!(
+                "`{source}` must select a value inside the document"
+            )));
+        }
+        let mut current = 0;
+        let mut found = None;
+        for (depth, step) in steps.iter().enumerate() {
+            let node = &self.nodes[current];
+            if node.kind == Kind::Alias {
+                return Err(api_other(format!(
+                    "`{source}` passes through an alias; edit the anchored node instead"
+                )));
+            }
+            let slot = match (step, node.kind) {
+                (Step::Name(name), Kind::Mapping) => {
+                    let slot = node.children.chunks(2).position(|pair| {
+                        let key = &self.nodes[pair[0]];
+                        key.kind == Kind::Scalar && key.value == *name
+                    });
+                    if slot.is_none() && depth + 1 == steps.len() {
+                        return Ok(Target::NewKey {
+                            parent: current,
+                            key: name.clone(),
+                        });
+                    }
+                    slot.map(|slot| 2 * slot + 1)
+                }
+                (Step::Name(name), Kind::Sequence) => name.parse::<usize>().ok().filter(|&index| {
+                    (name == "0" || !name.starts_with('0')) && index < node.children.len()
+                }),
+                (Step::Index(index), Kind::Sequence) => {
+                    let len = node.children.len() as i64;
+                    let index = if *index < 0 { len + index } else { *index };
+                    (0..len).contains(&index).then_some(index as usize)
+                }
+                (Step::Wildcard | Step::Descendants, _) => {
+                    return Err(api_other(format!(
+                        "`{source}` must select a single value, without `*` or `..`"
+                    )))
+                }
+                _ => None,
+            };
+            let Some(slot) = slot else {
+                return Err(api_other(format!("Nothing matches `{source}`")));
+            };
+            found = Some(Target::Existing {
+                parent: current,
+                slot,
+            });
+            current = node.children[slot];
+        }
+        Ok(found.expect("a query with steps finds a value or fails"))
+    }
+
+    /// The text that puts `value` after the indicator of an entry whose key
+    /// or `-` is at column `entry_column`, replacing `old` if there is one.
+    fn place(
+        &self,
+        value: &Yaml,
+        entry_column: usize,
+        in_sequence: bool,
+        old: Option<usize>,
+    ) -> Fallible<String> {
+        let newline = self.newline;
+        let anchor = old.and_then(|old| self.nodes[old].anchor.as_ref());
+        let mut props: Vec<String> = anchor
+            .map(|anchor| format!("&{anchor}"))
+            .into_iter()
+            .collect();
+        let inner = match value {
+            Yaml::Tagged(tag, inner) if inner.is_non_empty_collection() => {
+                props.push(crate::emitter::format_tag(tag));
+                inner.as_ref()
+            }
+            other => other,
+        };
+        let fragment = emit(inner)?;
+        let mut lines = fragment.split('\n');
+        let first = lines.next().unwrap_or_default();
+        let mut out = String::new();
+        for prop in &props {
+            out.push(' ');
+            out.push_str(prop);
+        }
+
+        let indent = if !inner.is_non_empty_collection() {
+            entry_column
+        } else if in_sequence {
+            entry_column + 2
+        } else {
+            // A new block keeps the indentation of the block it replaces.
+            let old_column = old
+                .filter(|&old| self.nodes[old].is_collection() && !self.nodes[old].flow)
+                .map(|old| self.block_column(old));
+            match (old_column, inner) {
+                (Some(column), Yaml::Sequence(_)) => column,
+                (Some(column), _) if column > entry_column => column,
+                _ => entry_column + 2,
+            }
+        };
+        let inline = !inner.is_non_empty_collection() || (in_sequence && props.is_empty());
+        if inline {
+            out.push(' ');
+            out.push_str(first);
+        } else {
+            out.push_str(newline);
+            out.extend(std::iter::repeat(' ').take(indent));
+            out.push_str(first);
+        }
+        for line in lines {
+            out.push_str(newline);
+            if !line.is_empty() {
+                out.extend(std::iter::repeat(' ').take(indent));
+            }
+            out.push_str(line);
+        }
+        Ok(out)
+    }
+
+    /// The column of the entries of the block collection at `index`.
+    fn block_column(&self, index: usize) -> usize {
+        let node = &self.nodes[index];
+        match (node.kind, node.children.first()) {
+            (Kind::Sequence, Some(&first)) => match self.nodes[first].after_indicator {
+                Some(after) => self.column(after - 1),
+                None => self.column(node.start),
+            },
+            _ => self.column(node.start),
+        }
+    }
+
+    /// `value` as a scalar in a flow collection, double-quoted when it
+    /// would otherwise take more than one line or hold flow indicators.
+    fn flow_scalar(&self, source: &str, value: &Yaml) -> Fallible<String> {
+        if value.is_non_empty_collection() {
+            return Err(api_other(format!(
+                "Cannot set `{source}`: a collection inside a flow collection must be empty"
+            )));
+        }
+        let fragment = emit(value)?;
+        match value {
+            Yaml::Value(Scalar::String(text))
+                if fragment.contains('\n') || text.contains([',', '[', ']', '{', '}']) =>
+            {
+                let mut quoted = String::new();
+                escape_str(&mut quoted, text).map_err(|err| api_other(err.to_string()))?;
+                Ok(quoted)
+            }
+            _ => Ok(fragment),
+        }
+    }
+
+    fn splice(&self, start: usize, end: usize, text: &str) -> String {
+        let mut out: String = self.chars[..start].iter().collect();
+        out.push_str(text);
+        out.extend(&self.chars[end..]);
+        out
+    }
+
+    fn set<'v>(&self, source: &str, value: &'v Yaml<'v>) -> Fallible<(String, Change<'v>)> {
+        let steps = Query::parse(source)?.steps;
+        match self.resolve(source, &steps)? {
+            Target::Existing { parent, slot } => {
+                let parent_node = &self.nodes[parent];
+                let index = parent_node.children[slot];
+                let node = &self.nodes[index];
+                let change = Change::Replace { index, value };
+                // The node's own anchor is kept, but not those inside it.
+                let replaced = index..self.subtree_end(index);
+                if let Some(anchor) = self.aliased_anchor(replaced, true) {
+                    return Err(api_other(format!(
+                        "Cannot set `{source}`: an alias later in the document refers to the anchor `&{anchor}` inside it"
+                    )));
+                }
+                if parent_node.flow {
+                    let mut text = self.flow_scalar(source, value)?;
+                    if let Some(anchor) = &node.anchor {
+                        text = format!("&{anchor} {text}");
+                    }
+                    let start = match node.after_indicator {
+                        Some(after) if node.kind == Kind::Empty => {
+                            text.insert(0, ' ');
+                            after
+                        }
+                        Some(after) => self.skip_blank(after),
+                        // A key without a value, as in `{a, b: c}`.
+                        None if node.kind == Kind::Empty && parent_node.kind == Kind::Mapping => {
+                            text.insert_str(0, ": ");
+                            node.start
+                        }
+                        None => node.start,
+                    };
+                    return Ok((self.splice(start, node.end, &text), change));
+                }
+                let in_sequence = parent_node.kind == Kind::Sequence;
+                let entry_column = self.column(self.entry_start(parent, slot));
+                let Some(after) = node.after_indicator else {
+                    // An explicit key without a value, as in `? a`, takes
+                    // a `:` line after it.
+                    let key = &self.nodes[parent_node.children[slot - 1]];
+                    if in_sequence || key.after_indicator.is_none() {
+                        return Err(api_other(format!(
+                            "Cannot find where `{source}` is written"
+                        )));
+                    }
+                    let mut text = String::from(self.newline);
+                    text.extend(std::iter::repeat(' ').take(entry_column));
+                    text.push(':');
+                    text.push_str(&self.place(value, entry_column, false, Some(index))?);
+                    let at = self.line_end(key.end);
+                    return Ok((self.splice(at, at, &text), change));
+                };
+                let mut text = self.place(value, entry_column, in_sequence, Some(index))?;
+                let mut start = after;
+                // Keep the spacing before an inline value that stays inline,
+                // unless it holds a tab, which the parser only accepts
+                // before some values.
+                let gap = self.skip_blank(after);
+                if text.starts_with(' ')
+                    && node.kind != Kind::Empty
+                    && gap > after
+                    && gap == node.start
+                    && !self.chars[after..gap].contains(&'\t')
+                    && node.start < node.end
+                {
+                    text.remove(0);
+                    start = gap;
+                }
+                // Comments between the indicator and a value on a later line
+                // are kept, with a block value's first line before them. A
+                // comment line would join a block scalar's text, so below
+                // one a string is double-quoted.
+                let value_line = self.line_start(node.start);
+                let props_end = self.skip_props(after, false);
+                if value_line > after && self.chars[props_end..value_line].contains(&'#') {
+                    let kept_end = self.line_end(value_line - 1);
+                    if self.chars[props_end..kept_end].contains(&'\n')
+                        && !value.is_non_empty_collection()
+                        && text.contains(self.newline)
+                    {
+                        text = format!(" {}", self.flow_scalar(source, value)?);
+                        if let Some(anchor) = &node.anchor {
+                            text.insert_str(0, &format!(" &{anchor}"));
+                        }
+                    }
+                    let (head, body) = text.split_at(text.find(self.newline).unwrap_or(text.len()));
+                    let mut out: String = self.chars[..after].iter().collect();
+                    out.push_str(head);
+                    out.extend(&self.chars[props_end..kept_end]);
+                    out.push_str(body);
+                    out.extend(&self.chars[node.end..]);
+                    return Ok((out, change));
+                }
+                // A comment after the old value stays on the indicator's
+                // line, before the lines of a block value.
+                if let Some(newline) = text.find(self.newline) {
+                    let (head, body) = text.split_at(newline);
+                    let line_end = self.line_end(node.end);
+                    let mut out: String = self.chars[..start].iter().collect();
+                    out.push_str(head);
+                    out.extend(&self.chars[node.end..line_end]);
+                    out.push_str(body);
+                    out.extend(&self.chars[line_end..]);
+                    return Ok((out, change));
+                }
+                Ok((self.splice(start, node.end, &text), change))
+            }
+            Target::NewKey { parent, key } => {
+                let node = &self.nodes[parent];
+                if node.flow && !node.bracketed {
+                    return Err(api_other(format!(
+                        "Cannot set `{source}`: a single pair in a flow sequence cannot take another key"
+                    )));
+                }
+                let change = Change::Add {
+                    parent,
+                    key: key.clone(),
+                    value,
+                };
+                if node.flow {
+                    let mut text =
+                        format!("{}: {}", emit_key(&key)?, self.flow_scalar(source, value)?);
+                    let at = match node.children.last() {
+                        Some(&last) => {
+                            text.insert_str(0, ", ");
+                            self.nodes[last].end
+                        }
+                        None => node.end - 1,
+                    };
+                    return Ok((self.splice(at, at, &text), change));
+                }
+                let column = self.column(node.start);
+                let mut text = String::from(self.newline);
+                text.extend(std::iter::repeat(' ').take(column));
+                text.push_str(&emit_key(&key)?);
+                text.push(':');
+                text.push_str(&self.place(value, column, false, None)?);
+                let at = self.line_end(node.end);
+                Ok((self.splice(at, at, &text), change))
+            }
+        }
+    }
+
+    fn delete(&self, source: &str) -> Fallible<(String, Change<'static>)> {
+        let steps = Query::parse(source)?.steps;
+        match self.resolve(source, &steps)? {
+            Target::Existing { parent, slot } => {
+                let node = &self.nodes[parent];
+                let first = match node.kind {
+                    Kind::Mapping => node.children[slot - 1],
+                    _ => node.children[slot],
+                };
+                let removed = first..self.subtree_end(node.children[slot]);
+                if let Some(anchor) = self.aliased_anchor(removed, false) {
+                    return Err(api_other(format!(
+                        "Cannot delete `{source}`: an alias later in the document refers to its anchor `&{anchor}`"
+                    )));
+                }
+                Ok((self.cut(parent, slot), Change::Remove { parent, slot }))
+            }
+            Target::NewKey { .. } => Err(api_other(format!("Nothing matches `{source}`"))),
+        }
+    }
+
+    /// The text without the entry `slot` of collection `parent`.
+    fn cut(&self, parent: usize, slot: usize) -> String {
+        let node = &self.nodes[parent];
+        let slots = self.value_slots(parent);
+        let entry = slots.iter().position(|&s| s == slot).unwrap_or_default();
+        let end = self.nodes[node.children[slot]].end;
+
+        // Deleting the only entry leaves an empty collection.
+        if slots.len() == 1 {
+            let empty = if node.kind == Kind::Mapping {
+                "{}"
+            } else {
+                "[]"
+            };
+            if node.flow {
+                return self.splice(node.start, node.end, empty);
+            }
+            // The entry's trailing comment goes with it.
+            let end = if self.starts_line(node.end) {
+                node.end
+            } else {
+                self.line_end(node.end)
+            };
+            return match node.after_indicator {
+                Some(after) => {
+                    let mut text = String::new();
+                    if let Some(anchor) = &node.anchor {
+                        text.push_str(&format!(" &{anchor}"));
+                    }
+                    text.push(' ');
+                    text.push_str(empty);
+                    self.splice(after, end, &text)
+                }
+                None => self.splice(self.entry_start(parent, slot), end, empty),
+            };
+        }
+
+        let start = self.entry_start(parent, slot);
+        let next = slots
+            .get(entry + 1)
+            .map(|&next| self.entry_start(parent, next));
+        if node.flow {
+            return match next {
+                Some(next) => self.splice(start, next, ""),
+                None => {
+                    let previous = self.nodes[node.children[slots[entry - 1]]].end;
+                    self.splice(previous, end, "")
+                }
+            };
+        }
+        if self.starts_line(start) {
+            return self.splice(self.line_start(start), self.next_line(end), "");
+        }
+        // An entry after `-` on the item's line: the next entry moves up.
+        match next {
+            Some(next) => self.splice(start, next, ""),
+            None => self.splice(start, self.next_line(end), ""),
+        }
+    }
+}
+
+/// Every event of `text` with its span, in the order of `parse_events()`.
+#[derive(Default)]
+struct EventLog<'input>(Vec<(Event<'input>, Span)>);
+
+impl<'input> SpannedEventReceiver<'input> for EventLog<'input> {
+    fn on_event(&mut self, ev: Event<'input>, span: Span) {
+        if ev != Event::Nothing {
+            self.0.push((ev, span));
+        }
+    }
+}
+
+fn event_log(text: &str) -> Result<Vec<(Event<'_>, Span)>, ScanError> {
+    let mut log = EventLog::default();
+    Parser::new_from_str(text).load(&mut log, true)?;
+    Ok(log.0)
+}
+
+/// The events of `value` written on its own, without the stream and
+/// document events around them.
+fn value_events(text: &str) -> Fallible<Vec<(Event<'_>, Span)>> {
+    let mut events = event_log(text).map_err(|err| api_other(err.to_string()))?;
+    events.truncate(events.len().saturating_sub(2));
+    events.drain(..2.min(events.len()));
+    Ok(events)
+}
+
+fn load_events<'a>(events: Vec<(Event<'a>, Span)>) -> Vec<Yaml<'a>> {
+    let mut loader: YamlLoader<'a, Yaml<'a>> = YamlLoader::default();
+    loader.early_parse(false);
+    for (event, span) in events {
+        loader.on_event(event, span);
+    }
+    let mut docs = loader.into_documents();
+    docs.iter_mut().for_each(resolve_tree);
+    docs
+}
+
+/// Whether `edited` reads back as the documents of `text` with only
+/// `change` made: the original events with the changed node's events
+/// replaced, loaded as the parser's would be, aliases included.
+fn edit_is_faithful(tree: &Tree, text: &str, edited: &str, change: &Change<'_>) -> Fallible<bool> {
+    let Ok(mut actual) = scan_yaml_documents(edited, true) else {
+        return Ok(false);
+    };
+    actual.iter_mut().for_each(resolve_tree);
+
+    let mut events = event_log(text).map_err(|err| api_other(err.to_string()))?;
+    let fragment;
+    match change {
+        Change::Replace { index, value } => {
+            fragment = emit(value)?;
+            let mut replacement = value_events(&fragment)?;
+            let node = &tree.nodes[*index];
+            // The new value keeps the old one's anchor, and so its aliases.
+            if let (Some((old, _)), Some((new, _))) =
+                (events.get(node.events.start), replacement.first_mut())
+            {
+                let old_anchor = match old {
+                    Event::Scalar(_, _, anchor, _)
+                    | Event::MappingStart(anchor, _)
+                    | Event::SequenceStart(anchor, _) => *anchor,
+                    _ => 0,
+                };
+                match new {
+                    Event::Scalar(_, _, anchor, _)
+                    | Event::MappingStart(anchor, _)
+                    | Event::SequenceStart(anchor, _) => *anchor = old_anchor,
+                    _ => {}
+                }
+            }
+            events.splice(node.events.clone(), replacement);
+        }
+        Change::Add { parent, key, value } => {
+            let mut entry = Mapping::new();
+            entry.insert(
+                Yaml::Value(Scalar::String(key.as_str().into())),
+                (*value).clone(),
+            );
+            fragment = emit(&Yaml::Mapping(entry))?;
+            let mut entry = value_events(&fragment)?;
+            entry.truncate(entry.len().saturating_sub(1));
+            entry.drain(..1.min(entry.len()));
+            let at = tree.nodes[*parent].events.end - 1;
+            events.splice(at..at, entry);
+        }
+        Change::Remove { parent, slot } => {
+            let node = &tree.nodes[*parent];
+            let value = &tree.nodes[node.children[*slot]];
+            let first = match node.kind {
+                Kind::Mapping => tree.nodes[node.children[slot - 1]].events.start,
+                _ => value.events.start,
+            };
+            events.drain(first..value.events.end);
+        }
+    }
+    let expected = load_events(events);
+    Ok(expected.len() == actual.len()
+        && expected.iter().zip(&actual).all(|(a, b)| yaml_equal(a, b)))
+}
+
+/// Check an edit before it is returned, so that text the edit could not
+/// place correctly is never written back.
+fn checked(
+    tree: &Tree,
+    text: &str,
+    (edited, change): (String, Change<'_>),
+    source: &str,
+) -> Fallible<String> {
+    if edit_is_faithful(tree, text, &edited, &change)? {
+        return Ok(edited);
+    }
+    Err(api_other(format!(
+        "Cannot edit `{source}` in place: the edited text would not read back as intended"
+    )))
+}
+
+/// Set the value `query` selects in the first document of `text`, or add it
+/// as a new key of an existing mapping.
+pub(crate) fn set_value(text: &str, query: &str, value: &Yaml) -> Fallible<String> {
+    let tree = Tree::parse(text)?;
+    let edit = tree.set(query, value)?;
+    checked(&tree, text, edit, query)
+}
+
+/// Delete the mapping entry or sequence item `query` selects in the first
+/// document of `text`.
+pub(crate) fn delete_value(text: &str, query: &str) -> Fallible<String> {
+    let tree = Tree::parse(text)?;
+    let edit = tree.delete(query)?;
+    checked(&tree, text, edit, query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use saphyr::LoadableYamlNode;
+
+    const DOC: &str = "# app\nspec:\n  replicas: 1   # scale\n  ports:\n  - 80\n  - 443 # tls\n  labels: {app: web, tier: x}\n  env: &e\n    a: 1\nlast: [1, 2]\n";
+
+    fn set(text: &str, query: &str, value: &str) -> String {
+        let value = Yaml::load_from_str(value).unwrap().remove(0);
+        set_value(text, query, &value).unwrap()
+    }
+
+    fn error(result: Fallible<String>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn sets_values_in_place() {
+        let replaced = |from: &str, to: &str| DOC.replace(from, to);
+        assert_eq!(
+            set(DOC, "spec.replicas", "3"),
+            replaced("replicas: 1", "replicas: 3")
+        );
+        assert_eq!(
+            set(DOC, "spec.ports[1]", "8443"),
+            replaced("443 #", "8443 #")
+        );
+        assert_eq!(
+            set(DOC, "spec.labels.app", "'a, b'"),
+            replaced("app: web", "app: \"a, b\"")
+        );
+        assert_eq!(set(DOC, "spec.env", "{b: 2}"), replaced("a: 1", "b: 2"));
+        assert_eq!(
+            set(DOC, "spec.ports[0]", "{host: a, port: 1}"),
+            replaced("- 80", "- host: a\n    port: 1")
+        );
+        assert_eq!(set(DOC, "last[0]", "9"), replaced("[1, 2]", "[9, 2]"));
+        assert_eq!(set(DOC, "spec", "1"), "# app\nspec: 1\nlast: [1, 2]\n");
+        assert_eq!(
+            set("s: |\n  run\n\nt: 1\n", "s", "\"x\\ny\\n\""),
+            "s: |\n  x\n  y\n\nt: 1\n"
+        );
+        assert_eq!(
+            set("items:\n-\n- b\n", "items[0]", "{k: v}"),
+            "items:\n- k: v\n- b\n"
+        );
+    }
+
+    #[test]
+    fn adds_new_keys() {
+        assert_eq!(
+            set(DOC, "spec.labels.new", "1"),
+            DOC.replace("tier: x}", "tier: x, new: 1}")
+        );
+        assert_eq!(
+            set(DOC, "spec.new", "{x: [1]}"),
+            DOC.replace("    a: 1\n", "    a: 1\n  new:\n    x:\n      - 1\n")
+        );
+        assert_eq!(set(DOC, "new", "text"), format!("{DOC}new: text\n"));
+        assert_eq!(set("a: {}\n", "a.b", "1"), "a: {b: 1}\n");
+    }
+
+    #[test]
+    fn deletes_entries_and_items() {
+        let removed = |line: &str| DOC.replace(line, "");
+        assert_eq!(
+            delete_value(DOC, "spec.replicas").unwrap(),
+            removed("  replicas: 1   # scale\n")
+        );
+        assert_eq!(
+            delete_value(DOC, "spec.ports[-1]").unwrap(),
+            removed("  - 443 # tls\n")
+        );
+        assert_eq!(
+            delete_value(DOC, "spec.labels.app").unwrap(),
+            removed("app: web, ")
+        );
+        assert_eq!(delete_value(DOC, "last[1]").unwrap(), removed(", 2"));
+        assert_eq!(
+            delete_value(DOC, "spec.env").unwrap(),
+            removed("  env: &e\n    a: 1\n")
+        );
+        assert_eq!(
+            delete_value(DOC, "spec.env.a").unwrap(),
+            DOC.replace("&e\n    a: 1", "&e {}")
+        );
+        assert_eq!(
+            delete_value("a:\n- x # c\nb: 1\n", "a[0]").unwrap(),
+            "a: []\nb: 1\n"
+        );
+        assert_eq!(
+            delete_value("items:\n- name: a\n  size: 1\n", "items[0].name").unwrap(),
+            "items:\n- size: 1\n"
+        );
+    }
+
+    #[test]
+    fn keeps_explicit_keys_and_flow_collections_intact() {
+        assert_eq!(
+            delete_value("? a\n: 1.3\nfifteen: d\n", "/a").unwrap(),
+            "fifteen: d\n"
+        );
+        assert_eq!(
+            delete_value("a: 4.2\n? d\n: 23\n", "/d").unwrap(),
+            "a: 4.2\n"
+        );
+        assert_eq!(set("? a\nb: 1\n", "/a", "x"), "? a\n: x\nb: 1\n");
+        assert_eq!(set("- { one : two , three: four , }\n", "/0", "x"), "- x\n");
+        assert_eq!(
+            delete_value("- a\n- {five: six,seven : eight}\n", "/1").unwrap(),
+            "- a\n"
+        );
+        assert_eq!(
+            set("strip: >-\n\nclip: >\n", "strip", "x"),
+            "strip: x\n\nclip: >\n"
+        );
+        assert_eq!(
+            set("{ \"single line\", a: b}\n", "/single line", "x"),
+            "{ \"single line\": x, a: b}\n"
+        );
+        assert_eq!(set("{foo : !!str,}\n", "/foo", "x"), "{foo : x,}\n");
+    }
+
+    #[test]
+    fn moves_trailing_comments_before_block_values() {
+        let text = "a: 1 # c\nb: 2\n";
+        assert_eq!(set(text, "a", "\"x\\ny\\n\""), "a: | # c\n  x\n  y\nb: 2\n");
+        assert_eq!(set(text, "a", "[1, 2]"), "a: # c\n  - 1\n  - 2\nb: 2\n");
+        assert_eq!(
+            set("- 1 # c\n- 2\n", "[0]", "{x: 1, y: 2}"),
+            "- x: 1 # c\n  y: 2\n- 2\n"
+        );
+    }
+
+    #[test]
+    fn keeps_comments_before_block_values() {
+        let text = "a:   # comment\n  b: 1\nc: 2\n";
+        assert_eq!(set(text, "a", "2"), "a: 2   # comment\nc: 2\n");
+        assert_eq!(
+            set(text, "a", "{x: 1, y: 2}"),
+            "a:   # comment\n  x: 1\n  y: 2\nc: 2\n"
+        );
+        assert_eq!(
+            set(text, "a", "\"x\\ny\\n\""),
+            "a: |   # comment\n  x\n  y\nc: 2\n"
+        );
+        assert_eq!(
+            set("a: &x # one\n  # two\n  - 1\n", "a", "\"p\\nq\\n\""),
+            "a: &x \"p\\nq\\n\" # one\n  # two\n"
+        );
+    }
+
+    #[test]
+    fn refuses_edits_that_break_aliases() {
+        assert_eq!(
+            error(delete_value("a: &x 1\nc: *x\n", "a")),
+            "Cannot delete `a`: an alias later in the document refers to its anchor `&x`"
+        );
+        assert_eq!(
+            error(set_value(
+                "a: {b: &x 1}\nc: *x\n",
+                "a",
+                &Yaml::Value(Scalar::Null)
+            )),
+            "Cannot set `a`: an alias later in the document refers to the anchor `&x` inside it"
+        );
+    }
+
+    #[test]
+    fn reports_unusable_queries() {
+        assert_eq!(error(delete_value(DOC, "nope")), "Nothing matches `nope`");
+        assert_eq!(
+            error(delete_value(DOC, "spec.*")),
+            "`spec.*` must select a single value, without `*` or `..`"
+        );
+        assert_eq!(
+            error(set_value(DOC, "", &Yaml::Value(Scalar::Null))),
+            "`` must select a value inside the document"
+        );
+        assert_eq!(
+            error(set_value(
+                "a: &x {b: 1}\nc: *x\n",
+                "c.b",
+                &Yaml::Value(Scalar::Null)
+            )),
+            "`c.b` passes through an alias; edit the anchored node instead"
+        );
+        assert!(error(delete_value("a: [1", "a")).starts_with("YAML parse error"));
+    }
+}
@@ -9,12 +9,17 @@
 //!   breaks exactly.
 //! - Mapping keys never use block styles. Keys longer than YAML's simple-key
 //!   limit use explicit mapping syntax.
+//! - Tags are rendered from their resolved handle and suffix as shorthand
+//!   (`!local`, `!!core`) or verbatim (`!<uri>`) tags, percent-encoding any
+//!   character the tag grammar does not allow.
 
 use core::fmt::{self, Write as _};
 use std::borrow::Cow;
 
 use saphyr::{EmitError, Mapping, Scalar, ScalarStyle, Tag, Yaml};
 
+use crate::handlers::YAML_CORE_HANDLE;
+
 struct ColumnTrackingWriter<'a> {
     writer: &'a mut dyn fmt::Write,
     column: usize,
@@ -111,7 +116,7 @@ fn escape_sequence(byte: u8) -> Option<&'static str> {
 }
 
 // from serialize::json
-fn escape_str(wr: &mut dyn fmt::Write, v: &str) -> Result<(), fmt::Error> {
+pub(crate) fn escape_str(wr: &mut dyn fmt::Write, v: &str) -> Result<(), fmt::Error> {
     wr.write_str("\"")?;
 
     let mut start = 0;
@@ -196,6 +201,16 @@ impl<'a> YamlEmitter<'a> {
         Ok(())
     }
 
+    /// Emit a lone string scalar, as a mapping key or as the value of an
+    /// entry in a collection nested `level` deep (`-1` for a document root).
+    pub(crate) fn emit_string(&mut self, value: &str, level: isize, key: bool) -> EmitResult {
+        self.level = level;
+        self.emitting_key = key;
+        let result = self.emit_node(&Yaml::Value(Scalar::String(Cow::Borrowed(value))));
+        self.emitting_key = false;
+        result
+    }
+
     fn write_indent(&mut self) -> EmitResult {
         if self.level <= 0 {
             return Ok(());
@@ -248,7 +263,7 @@ impl<'a> YamlEmitter<'a> {
             Yaml::Value(Scalar::Null) | Yaml::BadValue => Ok(write!(self.writer, "~")?),
             Yaml::Representation(ref v, style, ref tag) => {
                 if let Some(tag) = tag {
-                    write!(self.writer, "{} ", tag.as_ref())?;
+                    write!(self.writer, "{} ", format_tag(tag))?;
                 }
                 match style {
                     saphyr::ScalarStyle::Plain => write!(self.writer, "{v}")?,
@@ -261,7 +276,7 @@ impl<'a> YamlEmitter<'a> {
                 Ok(())
             }
             Yaml::Tagged(ref tag, ref node) => {
-                write!(self.writer, "{} ", tag.as_ref())?;
+                write!(self.writer, "{} ", format_tag(tag))?;
                 // We need to insert a newline after the tag when followed by a
                 // non-empty sequence or mapping. `emit_sequence` and
                 // `emit_mapping` do not add that extra newline at the beginning.
@@ -630,6 +645,15 @@ fn is_core_schema_integer(string: &str) -> bool {
     false
 }
 
+/// Whether plain `string` resolves to a string under the core schema.
+pub(crate) fn resolves_to_string(string: &str) -> bool {
+    !is_core_schema_integer(string)
+        && matches!(
+            Scalar::parse_from_cow(Cow::Borrowed(string)),
+            Scalar::String(_)
+        )
+}
+
 /// Check if the string must be double-quoted rather than emitted as a plain
 /// scalar.
 ///
@@ -645,13 +669,7 @@ fn need_quotes(string: &str) -> bool {
         return true;
     };
 
-    // Would be resolved as a non-string by the core schema.
-    if is_core_schema_integer(string)
-        || !matches!(
-            Scalar::parse_from_cow(Cow::Borrowed(string)),
-            Scalar::String(_)
-        )
-    {
+    if !resolves_to_string(string) {
         return true;
     }
 
@@ -699,12 +717,62 @@ fn rendered_string_length(string: &str) -> usize {
         }
 }
 
-fn rendered_tag_length(tag: &Tag) -> usize {
-    if tag.handle == "!" {
-        1 + tag.suffix.chars().count()
-    } else {
-        tag.handle.chars().count() + 1 + tag.suffix.chars().count()
+/// Check for YAML 1.2 `ns-uri-char`, excluding `%`, which is always escaped
+/// because the parser decodes escapes in tags.
+fn is_uri_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-#;/?:@&=+$,_.!~*'()[]".contains(c)
+}
+
+/// Check for YAML 1.2 `ns-tag-char`: a URI character that cannot end a tag
+/// handle or a flow collection entry.
+fn is_tag_char(c: char) -> bool {
+    is_uri_char(c) && !matches!(c, '!' | ',' | '[' | ']' | '{' | '}')
+}
+
+fn push_percent_encoded(out: &mut String, value: &str, allowed: fn(char) -> bool) {
+    for c in value.chars() {
+        if allowed(c) {
+            out.push(c);
+        } else {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                // Writing to a `String` cannot fail.
+                let _ = write!(out, "%{byte:02X}");
+            }
+        }
+    }
+}
+
+/// Render a resolved tag, as produced by the parser, in YAML syntax.
+///
+/// Local (`!`) and core (`tag:yaml.org,2002:`) tags use the shorthand forms;
+/// every other tag is written verbatim as `!<uri>`. The parser applies no
+/// `%TAG` directives to verbatim tags, so re-parsing the output yields the
+/// same handle and suffix.
+pub(crate) fn format_tag(tag: &Tag) -> String {
+    let mut out = String::with_capacity(tag.handle.len() + tag.suffix.len() + 3);
+    match (tag.handle.as_str(), tag.suffix.as_str()) {
+        ("", "!") | ("!", "") => out.push('!'),
+        ("!", suffix) => {
+            out.push('!');
+            push_percent_encoded(&mut out, suffix, is_tag_char);
+        }
+        (YAML_CORE_HANDLE, suffix) if !suffix.is_empty() => {
+            out.push_str("!!");
+            push_percent_encoded(&mut out, suffix, is_tag_char);
+        }
+        (handle, suffix) => {
+            out.push_str("!<");
+            push_percent_encoded(&mut out, handle, is_uri_char);
+            push_percent_encoded(&mut out, suffix, is_uri_char);
+            out.push('>');
+        }
     }
+    out
+}
+
+fn rendered_tag_length(tag: &Tag) -> usize {
+    format_tag(tag).len()
 }
 
 /// Return the length of an implicit key's emitted representation, or `None`
@@ -754,8 +822,10 @@ fn requires_explicit_key(key: &Yaml<'_>) -> bool {
 
 #[cfg(test)]
 mod test {
-    use super::{folded_lines, YamlEmitter};
-    use saphyr::{LoadableYamlNode, Scalar, Yaml};
+    use super::{folded_lines, format_tag, YamlEmitter};
+    use saphyr::{LoadableYamlNode, Scalar, Tag, Yaml, YamlLoader};
+    use saphyr_parser::Parser;
+    use std::borrow::Cow;
 
     fn emit_wrapped(doc: &Yaml) -> String {
         let mut output = String::new();
@@ -880,4 +950,57 @@ mod test {
         // Already fits.
         assert_eq!(folded_lines("aa bb", 10), None);
     }
+
+    #[test]
+    fn tags_round_trip_through_the_parser() {
+        let cases = [
+            ("!", "local", "!local"),
+            ("!", "a!b,c d%", "!a%21b%2Cc%20d%25"),
+            ("tag:yaml.org,2002:", "binary", "!!binary"),
+            ("", "!", "!"),
+            ("", "!local", "!<!local>"),
+            (
+                "",
+                "tag:example.com,2000:thing",
+                "!<tag:example.com,2000:thing>",
+            ),
+            (
+                "",
+                "tag:example.com,2000:a b>",
+                "!<tag:example.com,2000:a%20b%3E>",
+            ),
+            (
+                "tag:example.com,2000:",
+                "app/thing",
+                "!<tag:example.com,2000:app/thing>",
+            ),
+        ];
+
+        for (handle, suffix, rendered) in cases {
+            let tag = Tag {
+                handle: handle.to_string(),
+                suffix: suffix.to_string(),
+            };
+            assert_eq!(format_tag(&tag), rendered);
+
+            let doc = Yaml::Tagged(
+                Cow::Owned(tag),
+                Box::new(Yaml::Value(Scalar::String("x".into()))),
+            );
+            let output = emit_wrapped(&doc);
+            let mut parser = Parser::new_from_str(&output);
+            let mut loader = YamlLoader::default();
+            loader.early_parse(false);
+            parser.load(&mut loader, false).unwrap();
+            let parsed = loader.into_documents().remove(0);
+            match parsed {
+                Yaml::Representation(_, _, Some(parsed)) => assert_eq!(
+                    format!("{}{}", parsed.handle, parsed.suffix),
+                    format!("{handle}{suffix}"),
+                    "{output}"
+                ),
+                other => panic!("expected a tagged node from {output:?}, got {other:?}"),
+            }
+        }
+    }
 }
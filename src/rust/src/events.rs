@@ -0,0 +1,444 @@
+//! The parser event stream, in the form used by the yaml-test-suite's
+//! `test.event` files.
+//!
+//! saphyr numbers anchors and does not report the style of collections or
+//! whether a document marker was written, so those are recovered from the
+//! source text around each event, as `RecordingLoader` does.
+
+use crate::emitter::format_tag;
+use crate::handlers::render_tag;
+use crate::loader::{find_properties, CharOffsets, NodeStyle, Properties};
+use crate::r_ext;
+use crate::{api_other, Fallible};
+use saphyr::Yaml;
+use saphyr_parser::{Event, Parser, ScalarStyle, ScanError, Span, SpannedEventReceiver, Tag};
+use savvy::{NotAvailableValue, OwnedIntegerSexp, OwnedListSexp, Sexp};
+use std::borrow::Cow;
+use std::ops::Range;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum EventKind {
+    StreamStart,
+    StreamEnd,
+    DocumentStart,
+    DocumentEnd,
+    MappingStart,
+    MappingEnd,
+    SequenceStart,
+    SequenceEnd,
+    Scalar,
+    Alias,
+}
+
+impl EventKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::StreamStart => "+STR",
+            Self::StreamEnd => "-STR",
+            Self::DocumentStart => "+DOC",
+            Self::DocumentEnd => "-DOC",
+            Self::MappingStart => "+MAP",
+            Self::MappingEnd => "-MAP",
+            Self::SequenceStart => "+SEQ",
+            Self::SequenceEnd => "-SEQ",
+            Self::Scalar => "=VAL",
+            Self::Alias => "=ALI",
+        }
+    }
+}
+
+/// One parser event with the properties written in the source.
+#[derive(Clone, Debug)]
+pub(crate) struct ParseEvent {
+    pub(crate) kind: EventKind,
+    /// The anchor of a node, or the anchor an alias refers to.
+    pub(crate) anchor: Option<String>,
+    pub(crate) tag: Option<String>,
+    /// `NodeStyle::as_str()` of a node, or `"explicit"` or `"implicit"` for
+    /// a document marker.
+    pub(crate) style: Option<&'static str>,
+    pub(crate) value: Option<String>,
+    pub(crate) line: usize,
+    /// 1-based.
+    pub(crate) column: usize,
+    /// The tag as written in the source, such as `!e!foo` under a `%TAG`
+    /// directive.
+    pub(crate) written_tag: Option<String>,
+    /// The tag as the parser resolved it, for resolving scalars.
+    pub(crate) parsed_tag: Option<Tag>,
+    /// The character offsets of the event's span, and the line it ends on.
+    pub(crate) span: Range<usize>,
+    pub(crate) end_line: usize,
+}
+
+impl ParseEvent {
+    /// A scalar event as the unresolved node the loader builds from it.
+    pub(crate) fn representation(&self) -> Option<Yaml<'_>> {
+        if self.kind != EventKind::Scalar {
+            return None;
+        }
+        let style = match self.style {
+            Some("single_quoted") => ScalarStyle::SingleQuoted,
+            Some("double_quoted") => ScalarStyle::DoubleQuoted,
+            Some("literal") => ScalarStyle::Literal,
+            Some("folded") => ScalarStyle::Folded,
+            _ => ScalarStyle::Plain,
+        };
+        let value = self.value.as_deref().unwrap_or_default();
+        Some(Yaml::Representation(
+            Cow::Borrowed(value),
+            style,
+            self.parsed_tag.as_ref().map(Cow::Borrowed),
+        ))
+    }
+
+    /// The event as a `test.event` line, such as `=VAL &a <!t> 'text`.
+    pub(crate) fn test_event_line(&self) -> String {
+        let mut line = self.kind.as_str().to_string();
+        match (self.kind, self.style) {
+            (EventKind::DocumentStart, Some("explicit")) => line.push_str(" ---"),
+            (EventKind::DocumentEnd, Some("explicit")) => line.push_str(" ..."),
+            (EventKind::MappingStart, Some("flow")) => line.push_str(" {}"),
+            (EventKind::SequenceStart, Some("flow")) => line.push_str(" []"),
+            _ => {}
+        }
+        if self.kind == EventKind::Alias {
+            line.push_str(" *");
+            line.push_str(self.anchor.as_deref().unwrap_or_default());
+            return line;
+        }
+        if let Some(anchor) = &self.anchor {
+            line.push_str(" &");
+            line.push_str(anchor);
+        }
+        if let Some(tag) = &self.tag {
+            line.push_str(" <");
+            line.push_str(tag);
+            line.push('>');
+        }
+        if let Some(value) = &self.value {
+            line.push(' ');
+            line.push(match self.style {
+                Some("single_quoted") => '\'',
+                Some("double_quoted") => '"',
+                Some("literal") => '|',
+                Some("folded") => '>',
+                _ => ':',
+            });
+            push_escaped(&mut line, value);
+        }
+        line
+    }
+}
+
+/// Escape `value` as `test.event` does: backslash and the control
+/// characters that would break a line.
+fn push_escaped(out: &mut String, value: &str) {
+    for ch in value.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\x08' => out.push_str("\\b"),
+            ch => out.push(ch),
+        }
+    }
+}
+
+struct EventRecorder<'input> {
+    source: &'input str,
+    events: Vec<ParseEvent>,
+    /// Whether each open collection is in flow style.
+    open: Vec<bool>,
+    /// A collection starting with `[` or `{` whose style is known once the
+    /// next event shows whether that bracket opened it or its first child.
+    unsettled: Option<(usize, usize)>,
+    last_end: usize,
+    chars: CharOffsets,
+}
+
+impl<'input> EventRecorder<'input> {
+    fn text_at(&mut self, index: usize) -> &'input str {
+        let offset = self.chars.byte_offset(self.source, index);
+        &self.source[offset..]
+    }
+
+    /// The properties written before a node starting at `start`.
+    fn properties(&mut self, start: usize) -> Properties<'input> {
+        let from = self
+            .chars
+            .byte_offset(self.source, self.last_end.min(start));
+        let to = self.chars.byte_offset(self.source, start);
+        find_properties(&self.source[from..to])
+    }
+
+    /// Record the anchor and tag of a node on `event`.
+    fn set_properties(
+        &mut self,
+        event: &mut ParseEvent,
+        anchor_id: usize,
+        tag: Option<&Tag>,
+        span: Span,
+    ) {
+        if anchor_id == 0 && tag.is_none() {
+            return;
+        }
+        let written = self.properties(span.start.index());
+        if anchor_id > 0 {
+            event.anchor = written.anchor.map(str::to_string);
+        }
+        if let Some(tag) = tag {
+            event.tag = Some(render_tag(tag));
+            event.written_tag = Some(written.tag.map_or_else(|| format_tag(tag), str::to_string));
+            event.parsed_tag = Some(tag.clone());
+        }
+    }
+}
+
+impl<'input> SpannedEventReceiver<'input> for EventRecorder<'input> {
+    fn on_event(&mut self, ev: Event<'input>, span: Span) {
+        if let Some((event, start)) = self.unsettled.take() {
+            if span.start.index() == start {
+                self.events[event].style = Some(NodeStyle::Block.as_str());
+                if let Some(flow) = self.open.last_mut() {
+                    *flow = false;
+                }
+            }
+        }
+        let mut event = ParseEvent {
+            kind: EventKind::StreamStart,
+            anchor: None,
+            tag: None,
+            style: None,
+            value: None,
+            line: span.start.line(),
+            column: span.start.col() + 1,
+            written_tag: None,
+            parsed_tag: None,
+            span: span.start.index()..span.end.index(),
+            end_line: span.end.line(),
+        };
+        match &ev {
+            Event::Nothing => return,
+            Event::StreamStart => {}
+            Event::StreamEnd => event.kind = EventKind::StreamEnd,
+            Event::DocumentStart(explicit) => {
+                event.kind = EventKind::DocumentStart;
+                event.style = Some(if *explicit { "explicit" } else { "implicit" });
+            }
+            Event::DocumentEnd => {
+                event.kind = EventKind::DocumentEnd;
+                let explicit =
+                    span.start != span.end && self.text_at(span.start.index()).starts_with("...");
+                event.style = Some(if explicit { "explicit" } else { "implicit" });
+            }
+            Event::MappingStart(anchor_id, tag) | Event::SequenceStart(anchor_id, tag) => {
+                event.kind = if matches!(ev, Event::MappingStart(..)) {
+                    EventKind::MappingStart
+                } else {
+                    EventKind::SequenceStart
+                };
+                self.set_properties(&mut event, *anchor_id, tag.as_deref(), span);
+                // A collection inside a flow collection is in flow style too,
+                // like the single-pair mapping in `[a: b]`.
+                let flow = self.open.last().copied().unwrap_or(false) || {
+                    let bracket = matches!(
+                        self.text_at(span.start.index()).chars().next(),
+                        Some('[' | '{')
+                    );
+                    if bracket {
+                        self.unsettled = Some((self.events.len(), span.start.index()));
+                    }
+                    bracket
+                };
+                self.open.push(flow);
+                let style = if flow {
+                    NodeStyle::Flow
+                } else {
+                    NodeStyle::Block
+                };
+                event.style = Some(style.as_str());
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                event.kind = if matches!(ev, Event::MappingEnd) {
+                    EventKind::MappingEnd
+                } else {
+                    EventKind::SequenceEnd
+                };
+                self.open.pop();
+            }
+            Event::Scalar(value, style, anchor_id, tag) => {
+                event.kind = EventKind::Scalar;
+                self.set_properties(&mut event, *anchor_id, tag.as_deref(), span);
+                event.style = Some(NodeStyle::Scalar(*style).as_str());
+                // An empty node is reported as a plain `~` whose span is
+                // empty or covers the token that follows it.
+                let empty = *style == ScalarStyle::Plain
+                    && value == "~"
+                    && (span.start == span.end
+                        || !self.text_at(span.start.index()).starts_with('~'));
+                event.value = Some(if empty {
+                    String::new()
+                } else {
+                    value.to_string()
+                });
+            }
+            Event::Alias(_) => {
+                event.kind = EventKind::Alias;
+                let name = self.text_at(span.start.index());
+                let name = name.strip_prefix('*').unwrap_or(name);
+                let end = name
+                    .find(|ch: char| {
+                        ch.is_whitespace() || matches!(ch, ',' | '[' | ']' | '{' | '}')
+                    })
+                    .unwrap_or(name.len());
+                event.anchor = Some(name[..end].to_string());
+            }
+        }
+        // An implicit document start spans the properties of the root node.
+        if !matches!(ev, Event::DocumentStart(false)) {
+            self.last_end = self.last_end.max(span.end.index());
+        }
+        self.events.push(event);
+    }
+}
+
+/// The events of every document in `text`.
+pub(crate) fn parse_events(text: &str) -> Result<Vec<ParseEvent>, ScanError> {
+    match parse_events_until_error(text) {
+        (events, None) => Ok(events),
+        (_, Some(err)) => Err(err),
+    }
+}
+
+/// The events of `text` up to its first syntax error, and that error.
+pub(crate) fn parse_events_until_error(text: &str) -> (Vec<ParseEvent>, Option<ScanError>) {
+    let mut parser = Parser::new_from_str(text);
+    let mut recorder = EventRecorder {
+        source: text,
+        events: Vec::new(),
+        open: Vec::new(),
+        unsettled: None,
+        last_end: 0,
+        chars: CharOffsets::default(),
+    };
+    let error = parser.load(&mut recorder, true).err();
+    (recorder.events, error)
+}
+
+const COLUMN_NAMES: [&str; 7] = ["event", "anchor", "tag", "style", "value", "line", "column"];
+
+fn string_column<'a>(
+    events: &'a [ParseEvent],
+    field: impl Fn(&'a ParseEvent) -> Option<&'a str>,
+) -> Fallible<Sexp> {
+    let values = events
+        .iter()
+        .map(|event| r_ext::string_data(field(event).unwrap_or(<&str>::na())))
+        .collect::<Fallible<Vec<_>>>()?;
+    r_ext::materialize_string_vector(&values)
+}
+
+fn integer_column(events: &[ParseEvent], field: impl Fn(&ParseEvent) -> usize) -> Fallible<Sexp> {
+    let mut column = OwnedIntegerSexp::new(events.len())?;
+    for (index, event) in events.iter().enumerate() {
+        column.set_elt(index, i32::try_from(field(event)).unwrap_or(i32::na()))?;
+    }
+    Ok(column.into())
+}
+
+/// The events of `text` as a list of columns, or as `test.event` lines when
+/// `text_format` is set.
+pub(crate) fn yaml_events_impl(text: &str, text_format: bool) -> Fallible<Sexp> {
+    let events = parse_events(text).map_err(|err| api_other(format!("YAML parse error: {err}")))?;
+
+    if text_format {
+        let lines: Vec<String> = events.iter().map(ParseEvent::test_event_line).collect();
+        let lines = lines
+            .iter()
+            .map(|line| r_ext::string_data(line))
+            .collect::<Fallible<Vec<_>>>()?;
+        return r_ext::materialize_string_vector(&lines);
+    }
+
+    let mut columns = OwnedListSexp::new(COLUMN_NAMES.len(), true)?;
+    columns.set_value(
+        0,
+        string_column(&events, |event| Some(event.kind.as_str()))?,
+    )?;
+    columns.set_value(1, string_column(&events, |event| event.anchor.as_deref())?)?;
+    columns.set_value(2, string_column(&events, |event| event.tag.as_deref())?)?;
+    columns.set_value(3, string_column(&events, |event| event.style)?)?;
+    columns.set_value(4, string_column(&events, |event| event.value.as_deref())?)?;
+    columns.set_value(5, integer_column(&events, |event| event.line)?)?;
+    columns.set_value(6, integer_column(&events, |event| event.column)?)?;
+    for (index, name) in COLUMN_NAMES.iter().enumerate() {
+        columns.set_name(index, name)?;
+    }
+    Ok(columns.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        parse_events(text)
+            .unwrap()
+            .iter()
+            .map(ParseEvent::test_event_line)
+            .collect()
+    }
+
+    #[test]
+    fn matches_the_test_suite_format() {
+        assert_eq!(
+            lines("--- &m !t\na: &x [b, 'c']\n? *x\n: |\n  d\n...\n"),
+            [
+                "+STR",
+                "+DOC ---",
+                "+MAP &m <!t>",
+                "=VAL :a",
+                "+SEQ [] &x",
+                "=VAL :b",
+                "=VAL 'c",
+                "-SEQ",
+                "=ALI *x",
+                "=VAL |d\\n",
+                "-MAP",
+                "-DOC ...",
+                "-STR",
+            ]
+        );
+        assert_eq!(
+            lines("{a: , !!str : ~}"),
+            [
+                "+STR",
+                "+DOC",
+                "+MAP {}",
+                "=VAL :a",
+                "=VAL :",
+                "=VAL <tag:yaml.org,2002:str> :",
+                "=VAL :~",
+                "-MAP",
+                "-DOC",
+                "-STR",
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_collection_styles() {
+        assert_eq!(
+            lines(
+                "[a: b]: {}
+"
+            ),
+            [
+                "+STR", "+DOC", "+MAP", "+SEQ []", "+MAP {}", "=VAL :a", "=VAL :b", "-MAP", "-SEQ",
+                "+MAP {}", "-MAP", "-MAP", "-DOC", "-STR",
+            ]
+        );
+    }
+}
@@ -1,31 +1,42 @@
-use crate::r_ext;
+use crate::r_ext::{self, PreservedSexp};
 use crate::{api_other, Fallible};
 use saphyr::Tag;
-use savvy::{FunctionSexp, ListSexp, NotAvailableValue, Sexp};
+use savvy::{FunctionSexp, ListSexp, LogicalSexp, NotAvailableValue, Sexp};
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::mem;
 
 const HASHMAP_MIN_LEN: usize = 8;
+pub(crate) const YAML_CORE_HANDLE: &str = "tag:yaml.org,2002:";
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
-struct HandlerKey<'a> {
+/// A tag in the form used for `yaml_tag` attributes: the resolved handle
+/// followed by the suffix.
+///
+/// The parser splits the same tag differently depending on how it was
+/// written (`!e!gizmo` under a `%TAG` directive versus the verbatim
+/// `!<tag:example.com,2024:gizmo>`), so equality and hashing only look at
+/// the concatenated bytes.
+#[derive(Copy, Clone)]
+struct TagName<'a> {
     handle: &'a str,
     suffix: &'a str,
 }
 
-impl HandlerKey<'_> {
-    fn matches(&self, key: TagKeyRef<'_>) -> bool {
-        self.handle == key.handle && self.suffix == key.suffix
+impl<'a> TagName<'a> {
+    fn len(&self) -> usize {
+        self.handle.len() + self.suffix.len()
     }
-}
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
-struct TagKeyRef<'a> {
-    handle: &'a str,
-    suffix: &'a str,
+    fn bytes(&self) -> impl Iterator<Item = u8> + 'a {
+        self.handle.bytes().chain(self.suffix.bytes())
+    }
+
+    fn starts_with(&self, prefix: TagName<'_>) -> bool {
+        self.len() >= prefix.len() && prefix.bytes().eq(self.bytes().take(prefix.len()))
+    }
 }
 
-impl<'a> From<&'a Tag> for TagKeyRef<'a> {
+impl<'a> From<&'a Tag> for TagName<'a> {
     fn from(tag: &'a Tag) -> Self {
         Self {
             handle: tag.handle.as_str(),
@@ -34,18 +45,164 @@ impl<'a> From<&'a Tag> for TagKeyRef<'a> {
     }
 }
 
+impl PartialEq for TagName<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.bytes().eq(other.bytes())
+    }
+}
+
+impl Eq for TagName<'_> {}
+
+impl Hash for TagName<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Hash byte by byte so that every split of the same tag agrees.
+        state.write_usize(self.len());
+        for byte in self.bytes() {
+            state.write_u8(byte);
+        }
+    }
+}
+
+struct HandlerFunction {
+    function: FunctionSexp,
+    /// Whether the function declares a `context` argument.
+    wants_context: bool,
+    /// Whether the function can take the tag as a second argument, which
+    /// prefix and catch-all handlers receive.
+    wants_tag: bool,
+    /// Marked by `raw_handler()`: called with the source text of scalars.
+    raw: bool,
+    /// Whether a raw handler declares a `style` argument.
+    wants_style: bool,
+}
+
 struct HandlerEntry<'a> {
-    key: HandlerKey<'a>,
-    handler: FunctionSexp,
+    key: TagName<'a>,
+    handler: HandlerFunction,
 }
 
 enum HandlerStore<'a> {
     Small(Vec<HandlerEntry<'a>>),
-    Large(HashMap<HandlerKey<'a>, FunctionSexp>),
+    Large(HashMap<TagName<'a>, HandlerFunction>),
+}
+
+/// A handler selected for a tag. Prefix and catch-all handlers also receive
+/// the full tag, since a single function serves a family of tags, when the
+/// function takes a second argument.
+#[derive(Copy, Clone)]
+pub(crate) struct Handler<'r> {
+    function: &'r HandlerFunction,
+    pass_tag: bool,
+}
+
+impl Handler<'_> {
+    pub(crate) fn wants_context(&self) -> bool {
+        self.function.wants_context
+    }
+
+    pub(crate) fn is_raw(&self) -> bool {
+        self.function.raw
+    }
+
+    /// Whether the handler was registered for exactly this tag, rather than
+    /// by a prefix pattern or the catch-all.
+    pub(crate) fn is_exact(&self) -> bool {
+        !self.pass_tag
+    }
+
+    /// Call the handler on `arg`. `style` and `context` are passed only to
+    /// handlers that want them; `context` must already be rooted by the
+    /// caller.
+    pub(crate) fn call(
+        &self,
+        tag: &Tag,
+        arg: Sexp,
+        style: Option<&str>,
+        context: Option<Sexp>,
+    ) -> Fallible<Sexp> {
+        let function = &self.function.function;
+        let style = style.filter(|_| self.function.wants_style);
+        let context = context.filter(|_| self.wants_context());
+        let pass_tag = self.pass_tag && self.function.wants_tag;
+        if !pass_tag && style.is_none() && context.is_none() {
+            return r_ext::call1(function, arg);
+        }
+        let arg = PreservedSexp::new(arg);
+        let tag_value = if pass_tag {
+            Some(PreservedSexp::new(r_ext::string_scalar(&render_tag(tag))?))
+        } else {
+            None
+        };
+        let style_value = match style {
+            Some(style) => Some(PreservedSexp::new(r_ext::string_scalar(style)?)),
+            None => None,
+        };
+        r_ext::call_handler(
+            function,
+            arg.value(),
+            tag_value.as_ref().map(PreservedSexp::value),
+            style_value.as_ref().map(PreservedSexp::value),
+            context,
+        )
+    }
+}
+
+/// Core-schema types that an untagged node can resolve to.
+#[derive(Copy, Clone)]
+pub(crate) enum CoreType {
+    Null,
+    Bool,
+    Int,
+    Float,
+    Str,
+    Seq,
+    Map,
+}
+
+impl CoreType {
+    const ALL: [CoreType; 7] = [
+        CoreType::Null,
+        CoreType::Bool,
+        CoreType::Int,
+        CoreType::Float,
+        CoreType::Str,
+        CoreType::Seq,
+        CoreType::Map,
+    ];
+
+    fn suffix(self) -> &'static str {
+        match self {
+            CoreType::Null => "null",
+            CoreType::Bool => "bool",
+            CoreType::Int => "int",
+            CoreType::Float => "float",
+            CoreType::Str => "str",
+            CoreType::Seq => "seq",
+            CoreType::Map => "map",
+        }
+    }
+}
+
+/// Handlers looked up once per core type, for applying to untagged nodes.
+pub(crate) struct CoreHandlers<'r> {
+    tags: [Tag; 7],
+    handlers: [Option<Handler<'r>>; 7],
+}
+
+impl<'r> CoreHandlers<'r> {
+    pub(crate) fn get(&self, core_type: CoreType) -> Option<(Handler<'r>, &Tag)> {
+        let index = core_type as usize;
+        self.handlers[index].map(|handler| (handler, &self.tags[index]))
+    }
 }
 
 pub(crate) struct HandlerRegistry<'a> {
     store: HandlerStore<'a>,
+    // Sorted by decreasing prefix length so the most specific pattern wins;
+    // the catch-all `*` has an empty prefix and always comes last.
+    prefixes: Vec<HandlerEntry<'a>>,
+    wants_context: bool,
+    has_raw: bool,
 }
 
 impl<'a> HandlerRegistry<'a> {
@@ -66,100 +223,232 @@ impl<'a> HandlerRegistry<'a> {
         };
 
         let len = list.len();
-        let use_hash_map = len >= HASHMAP_MIN_LEN;
-
-        if use_hash_map {
-            let mut handlers_map = HashMap::with_capacity(len);
-            for i in 0..len {
-                let name = r_ext::string_elt(&names_attr, i)?;
-                let value = unsafe { list.get_by_index_unchecked(i) };
-                let entry = handler_entry_from_parts(name, &value)?;
-                if handlers_map.insert(entry.key, entry.handler).is_some() {
-                    return Err(api_other(format!(
-                        "Duplicate handler `{name}`; handler names must be unique"
-                    )));
-                }
-            }
-            return Ok(Some(Self {
-                store: HandlerStore::Large(handlers_map),
-            }));
-        }
-
-        let mut entries: Vec<HandlerEntry<'a>> = Vec::with_capacity(len);
+        let mut exact: Vec<HandlerEntry<'a>> = Vec::with_capacity(len);
+        let mut prefixes: Vec<HandlerEntry<'a>> = Vec::new();
         for i in 0..len {
             let name = r_ext::string_elt(&names_attr, i)?;
             let value = unsafe { list.get_by_index_unchecked(i) };
-            let entry = handler_entry_from_parts(name, &value)?;
-            if entries.iter().any(|existing| existing.key == entry.key) {
-                return Err(api_other(format!(
-                    "Duplicate handler `{name}`; handler names must be unique"
-                )));
+            let (entry, is_prefix) = handler_entry_from_parts(name, &value)?;
+            let entries = if is_prefix { &mut prefixes } else { &mut exact };
+            // Large exact sets are checked for duplicates on map insertion.
+            if (is_prefix || len < HASHMAP_MIN_LEN)
+                && entries.iter().any(|existing| existing.key == entry.key)
+            {
+                return Err(duplicate_handler_error(name));
             }
             entries.push(entry);
         }
+        prefixes.sort_by_key(|entry| std::cmp::Reverse(entry.key.len()));
+        let wants_context = exact
+            .iter()
+            .chain(&prefixes)
+            .any(|entry| entry.handler.wants_context);
+        let has_raw = exact.iter().chain(&prefixes).any(|entry| entry.handler.raw);
+
+        let store = if len >= HASHMAP_MIN_LEN {
+            let mut handlers_map = HashMap::with_capacity(exact.len());
+            for entry in exact {
+                if handlers_map.insert(entry.key, entry.handler).is_some() {
+                    let name = format!("{}{}", entry.key.handle, entry.key.suffix);
+                    return Err(duplicate_handler_error(&name));
+                }
+            }
+            HandlerStore::Large(handlers_map)
+        } else {
+            HandlerStore::Small(exact)
+        };
 
         Ok(Some(Self {
-            store: HandlerStore::Small(entries),
+            store,
+            prefixes,
+            wants_context,
+            has_raw,
         }))
     }
 
-    pub(crate) fn get_for_tag(&self, tag: &Tag) -> Option<&FunctionSexp> {
-        let key_ref = TagKeyRef::from(tag);
-        match &self.store {
+    /// Whether any handler takes a `context` argument, which needs source
+    /// positions recorded while loading.
+    pub(crate) fn wants_context(&self) -> bool {
+        self.wants_context
+    }
+
+    /// Whether any handler was marked by `raw_handler()`.
+    pub(crate) fn has_raw(&self) -> bool {
+        self.has_raw
+    }
+
+    /// Find the handler for `tag`: an exact match first, then the longest
+    /// matching prefix pattern, then the catch-all.
+    pub(crate) fn get_for_tag(&self, tag: &Tag) -> Option<Handler<'_>> {
+        let key = TagName::from(tag);
+        let exact = match &self.store {
             HandlerStore::Small(entries) => entries
                 .iter()
-                .find(|entry| entry.key.matches(key_ref))
+                .find(|entry| entry.key == key)
                 .map(|entry| &entry.handler),
             HandlerStore::Large(map) => {
-                let lookup_key = HandlerKey {
-                    handle: key_ref.handle,
-                    suffix: key_ref.suffix,
-                };
                 // HashMap::get does not store the borrowed lookup key.
-                let lookup_key: &HandlerKey<'a> = unsafe { mem::transmute(&lookup_key) };
+                let lookup_key: &TagName<'a> = unsafe { mem::transmute(&key) };
                 map.get(lookup_key)
             }
+        };
+        if let Some(function) = exact {
+            return Some(Handler {
+                function,
+                pass_tag: false,
+            });
+        }
+
+        self.prefixes
+            .iter()
+            .find(|entry| key.starts_with(entry.key))
+            .map(|entry| Handler {
+                function: &entry.handler,
+                pass_tag: true,
+            })
+    }
+
+    /// The handlers that apply to untagged nodes of each core type, or
+    /// `None` when no handler matches any core tag.
+    pub(crate) fn core_handlers(&self) -> Option<CoreHandlers<'_>> {
+        let tags = CoreType::ALL.map(|core_type| Tag {
+            handle: YAML_CORE_HANDLE.to_string(),
+            suffix: core_type.suffix().to_string(),
+        });
+        let handlers = CoreType::ALL.map(|core_type| self.get_for_tag(&tags[core_type as usize]));
+        handlers
+            .iter()
+            .any(Option::is_some)
+            .then_some(CoreHandlers { tags, handlers })
+    }
+}
+
+/// The tags accepted in strict mode on top of handled and core-schema tags.
+/// Entries use the handler-name spelling, including `*` prefix patterns.
+pub(crate) struct StrictTags<'a> {
+    allowed: Vec<(TagName<'a>, bool)>,
+}
+
+impl<'a> StrictTags<'a> {
+    /// Read `strict_tags`: `FALSE` or `NULL` turns strict mode off, `TRUE`
+    /// turns it on, and a character vector turns it on with an allow-list.
+    pub(crate) fn from_robj(strict_tags: &'a Sexp) -> Fallible<Option<Self>> {
+        let invalid =
+            || api_other("`strict_tags` must be TRUE, FALSE, or a character vector of tags");
+        if strict_tags.is_null() {
+            return Ok(None);
+        }
+        if let Some(tags) = r_ext::string_sexp(strict_tags) {
+            let mut allowed = Vec::with_capacity(tags.len());
+            for i in 0..tags.len() {
+                let name = r_ext::string_elt(&tags, i)?;
+                if name.is_na() || name.is_empty() {
+                    return Err(invalid());
+                }
+                allowed.push(parse_handler_name(name).map_err(|_| invalid())?);
+            }
+            return Ok(Some(Self { allowed }));
+        }
+        let flag = LogicalSexp::try_from(Sexp(strict_tags.0)).map_err(|_| invalid())?;
+        match flag.as_slice_raw() {
+            [value] if *value == i32::na() => Err(invalid()),
+            [0] => Ok(None),
+            [_] => Ok(Some(Self {
+                allowed: Vec::new(),
+            })),
+            _ => Err(invalid()),
         }
     }
 
-    pub(crate) fn apply(&self, handler: &FunctionSexp, arg: Sexp) -> Fallible<Sexp> {
-        r_ext::call1(handler, arg)
+    /// Whether `tag` is listed, ignoring handlers and the core schema.
+    pub(crate) fn allows(&self, tag: &Tag) -> bool {
+        let key = TagName::from(tag);
+        self.allowed.iter().any(|(allowed, is_prefix)| {
+            if *is_prefix {
+                key.starts_with(*allowed)
+            } else {
+                key == *allowed
+            }
+        })
     }
 }
 
-fn handler_entry_from_parts<'a>(name: &'a str, value: &Sexp) -> Fallible<HandlerEntry<'a>> {
+/// Spell `tag` the way `yaml_tag` attributes and handler arguments do.
+pub(crate) fn render_tag(tag: &Tag) -> String {
+    let mut rendered = String::with_capacity(tag.handle.len() + tag.suffix.len());
+    rendered.push_str(tag.handle.as_str());
+    rendered.push_str(tag.suffix.as_str());
+    rendered
+}
+
+/// Whether two tags render the same, however the parser split them into a
+/// handle and a suffix.
+pub(crate) fn same_tag(a: &Tag, b: &Tag) -> bool {
+    TagName::from(a) == TagName::from(b)
+}
+
+fn duplicate_handler_error(name: &str) -> savvy::Error {
+    api_other(format!(
+        "Duplicate handler `{name}`; handler names must be unique"
+    ))
+}
+
+fn handler_entry_from_parts<'a>(name: &'a str, value: &Sexp) -> Fallible<(HandlerEntry<'a>, bool)> {
     if name.is_na() || name.is_empty() {
         return Err(api_other("`handlers` must be a named list of functions"));
     }
-    let key = parse_handler_name(name)?;
-    let handler = FunctionSexp::try_from(Sexp(value.0)).map_err(|_| {
+    let (key, is_prefix) = parse_handler_name(name)?;
+    let function = FunctionSexp::try_from(Sexp(value.0)).map_err(|_| {
         api_other(format!(
             "Handler `{name}` must be a function (closure or primitive)"
         ))
     })?;
-    Ok(HandlerEntry { key, handler })
+    let raw = r_ext::inherits(value, "yaml12_raw_handler")?;
+    let handler = HandlerFunction {
+        wants_context: r_ext::has_formal(&function, "context")?,
+        wants_tag: r_ext::takes_second_argument(&function),
+        raw,
+        wants_style: raw && r_ext::has_formal(&function, "style")?,
+        function,
+    };
+    Ok((HandlerEntry { key, handler }, is_prefix))
 }
 
-fn parse_handler_name<'a>(name: &'a str) -> Fallible<HandlerKey<'a>> {
-    if let Some((handle, suffix)) = split_tag_name(name) {
-        Ok(HandlerKey { handle, suffix })
-    } else {
-        Err(api_other("`handlers` names must be valid YAML tag strings"))
+/// Resolve a handler name to the tag it matches. Names use the `yaml_tag`
+/// spelling, and `!!suffix` or `!<uri>` are accepted as well. A trailing `*`
+/// turns the name into a prefix pattern; `"*"` alone matches every tag. A
+/// verbatim `!<uri>` name is always exact, so it can name a tag ending in `*`.
+fn parse_handler_name(name: &str) -> Fallible<(TagName<'_>, bool)> {
+    let invalid = || api_other("`handlers` names must be valid YAML tag strings");
+    if let Some(uri) = name.strip_prefix("!<") {
+        let uri = uri.strip_suffix('>').filter(|uri| !uri.is_empty());
+        let key = TagName {
+            handle: "",
+            suffix: uri.ok_or_else(invalid)?,
+        };
+        return Ok((key, false));
     }
-}
-
-fn split_tag_name(name: &str) -> Option<(&str, &str)> {
-    if let Some(pos) = name.rfind('!') {
-        if pos + 1 < name.len() {
-            let (handle, suffix) = name.split_at(pos + 1);
-            return Some((handle, suffix));
+    let key = if let Some(suffix) = name.strip_prefix("!!") {
+        TagName {
+            handle: YAML_CORE_HANDLE,
+            suffix,
         }
-    }
-    if let Some(pos) = name.rfind(':') {
-        if pos + 1 < name.len() {
-            let (handle, suffix) = name.split_at(pos + 1);
-            return Some((handle, suffix));
+    } else {
+        TagName {
+            handle: "",
+            suffix: name,
         }
+    };
+
+    match key.suffix.strip_suffix('*') {
+        Some(prefix) => Ok((
+            TagName {
+                handle: key.handle,
+                suffix: prefix,
+            },
+            true,
+        )),
+        None if key.suffix.is_empty() => Err(invalid()),
+        None => Ok((key, false)),
     }
-    None
 }
@@ -0,0 +1,200 @@
+use crate::r_ext;
+use crate::{api_other, Fallible};
+use saphyr::Tag;
+use savvy::{LogicalSexp, NotAvailableValue, Sexp};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How many files deep `!include` may nest before it is treated as runaway.
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Settings for `read_yaml(include = )`.
+pub(crate) struct IncludeOptions {
+    /// Included files must resolve inside this directory.
+    root: Option<PathBuf>,
+}
+
+impl IncludeOptions {
+    /// Read `include`: `FALSE` or `NULL` disables `!include`, `TRUE` enables
+    /// it, and a directory path enables it with that root.
+    pub(crate) fn from_robj(include: &Sexp) -> Fallible<Option<Self>> {
+        let invalid = || api_other("`include` must be TRUE, FALSE, or a single directory path");
+        if include.is_null() {
+            return Ok(None);
+        }
+        if let Some(root) = r_ext::string_sexp(include) {
+            if root.len() != 1 {
+                return Err(invalid());
+            }
+            let root = r_ext::string_elt(&root, 0)?;
+            if root.is_na() || root.is_empty() {
+                return Err(invalid());
+            }
+            let root = fs::canonicalize(root).map_err(|err| {
+                api_other(format!("Failed to resolve include root `{root}`: {err}"))
+            })?;
+            return Ok(Some(Self { root: Some(root) }));
+        }
+        let flag = LogicalSexp::try_from(Sexp(include.0)).map_err(|_| invalid())?;
+        match flag.as_slice_raw() {
+            [value] if *value == i32::na() => Err(invalid()),
+            [0] => Ok(None),
+            [_] => Ok(Some(Self { root: None })),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The frame for the file passed to `read_yaml()`.
+    pub(crate) fn top_frame(&self, path: &str) -> Fallible<IncludeFrame<'_>> {
+        let file = fs::canonicalize(path)
+            .map_err(|err| api_other(format!("Failed to read `{path}`: {err}")))?;
+        Ok(IncludeFrame {
+            file,
+            root: self.root.as_deref(),
+            parent: None,
+            depth: 0,
+        })
+    }
+}
+
+/// A file being read, linked to the file that included it.
+pub(crate) struct IncludeFrame<'p> {
+    file: PathBuf,
+    root: Option<&'p Path>,
+    parent: Option<&'p IncludeFrame<'p>>,
+    depth: usize,
+}
+
+impl<'p> IncludeFrame<'p> {
+    pub(crate) fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Resolve `target` relative to this file and check it against the root,
+    /// the depth limit, and the files already being read.
+    pub(crate) fn include(&'p self, target: &str) -> Fallible<IncludeFrame<'p>> {
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(api_other(format!(
+                "`!include {target}` in `{file}` exceeds the maximum include depth of {MAX_INCLUDE_DEPTH}",
+                file = self.file.display()
+            )));
+        }
+        let dir = self.file.parent().unwrap_or_else(|| Path::new(""));
+        let file = fs::canonicalize(dir.join(target)).map_err(|err| {
+            api_other(format!(
+                "Failed to resolve `!include {target}` in `{file}`: {err}",
+                file = self.file.display()
+            ))
+        })?;
+        if let Some(root) = self.root.filter(|root| !file.starts_with(root)) {
+            return Err(api_other(format!(
+                "`!include {target}` in `{file}` resolves outside the include root `{root}`",
+                file = self.file.display(),
+                root = root.display()
+            )));
+        }
+        if self.frames().any(|frame| frame.file == file) {
+            let mut chain: Vec<String> = self
+                .frames()
+                .map(|frame| frame.file.display().to_string())
+                .collect();
+            chain.reverse();
+            chain.push(file.display().to_string());
+            return Err(api_other(format!(
+                "`!include` cycle detected: {}",
+                chain.join(" -> ")
+            )));
+        }
+        Ok(IncludeFrame {
+            file,
+            root: self.root,
+            parent: Some(self),
+            depth: self.depth + 1,
+        })
+    }
+
+    fn frames(&self) -> impl Iterator<Item = &IncludeFrame<'p>> {
+        std::iter::successors(Some(self), |frame| frame.parent)
+    }
+}
+
+pub(crate) fn is_include_tag(tag: &Tag) -> bool {
+    tag.handle.as_str() == "!" && tag.suffix.as_str() == "include"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(file: PathBuf, root: Option<&Path>) -> IncludeFrame<'_> {
+        IncludeFrame {
+            file,
+            root,
+            parent: None,
+            depth: 0,
+        }
+    }
+
+    fn write(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "x: 1\n").unwrap();
+        fs::canonicalize(path).unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("yaml12-include-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::canonicalize(dir).unwrap()
+    }
+
+    #[test]
+    fn resolves_relative_to_the_including_file() {
+        let dir = temp_dir("relative");
+        let top = write(&dir, "a.yaml");
+        let nested = write(&dir, "sub/b.yaml");
+        let sibling = write(&dir, "sub/c.yaml");
+
+        let top = frame(top, None);
+        let b = top.include("sub/b.yaml").unwrap();
+        assert_eq!(b.file(), nested);
+        let c = b.include("c.yaml").unwrap();
+        assert_eq!(c.file(), sibling);
+        assert_eq!(c.depth, 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_cycles_escapes_and_deep_nesting() {
+        let dir = temp_dir("errors");
+        let top = write(&dir, "conf/a.yaml");
+        write(&dir, "conf/b.yaml");
+        write(&dir, "outside.yaml");
+        let root = dir.join("conf");
+
+        let a = frame(top, Some(&root));
+        let b = a.include("b.yaml").unwrap();
+        let Err(savvy::Error::GeneralError(cycle)) = b.include("a.yaml") else {
+            panic!("expected a cycle error");
+        };
+        assert!(cycle.contains("cycle detected"), "{cycle}");
+        assert!(cycle.ends_with("a.yaml"), "{cycle}");
+
+        let Err(savvy::Error::GeneralError(escape)) = a.include("../outside.yaml") else {
+            panic!("expected a root error");
+        };
+        assert!(escape.contains("outside the include root"), "{escape}");
+
+        let deep = IncludeFrame {
+            depth: MAX_INCLUDE_DEPTH,
+            ..frame(dir.join("conf/a.yaml"), None)
+        };
+        let Err(savvy::Error::GeneralError(depth)) = deep.include("b.yaml") else {
+            panic!("expected a depth error");
+        };
+        assert!(depth.contains("maximum include depth"), "{depth}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+use crate::loader::SourceMap;
+use crate::{api_other, Fallible};
+use saphyr::Yaml;
+use saphyr_parser::ScalarStyle;
+use std::borrow::Cow;
+use std::env;
+
+/// Why a scalar could not be expanded.
+#[derive(Debug, PartialEq)]
+enum ExpandError {
+    /// `${` without a closing brace, or with an invalid variable name.
+    Invalid(String),
+    /// `${NAME:?message}` with `NAME` unset or empty.
+    Required { name: String, message: String },
+    /// The variable is set but is not valid UTF-8.
+    NotUnicode(String),
+}
+
+/// Expand `${NAME}`, `${NAME:-default}`, and `${NAME:?message}` in `text`;
+/// `$${` stands for a literal `${`. Returns `None` when nothing changes.
+fn expand(
+    text: &str,
+    lookup: impl Fn(&str) -> Result<Option<String>, ExpandError>,
+) -> Result<Option<String>, ExpandError> {
+    if !text.contains("${") {
+        return Ok(None);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start..];
+        if let Some(escaped) = after.strip_prefix("$${") {
+            out.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let Some(body) = after.strip_prefix("${") else {
+            out.push('$');
+            rest = &after[1..];
+            continue;
+        };
+        let Some(end) = body.find('}') else {
+            return Err(ExpandError::Invalid(after.to_string()));
+        };
+        let reference = &body[..end];
+        rest = &body[end + 1..];
+
+        let (name, operator) = match reference.find(':') {
+            Some(colon) => (&reference[..colon], Some(&reference[colon + 1..])),
+            None => (reference, None),
+        };
+        if !is_variable_name(name) {
+            return Err(ExpandError::Invalid(format!("${{{reference}}}")));
+        }
+        let value = lookup(name)?.filter(|value| !value.is_empty());
+        match (value, operator) {
+            (Some(value), _) => out.push_str(&value),
+            (None, None) => {}
+            (None, Some(operator)) => {
+                if let Some(default) = operator.strip_prefix('-') {
+                    out.push_str(default);
+                } else if let Some(message) = operator.strip_prefix('?') {
+                    return Err(ExpandError::Required {
+                        name: name.to_string(),
+                        message: message.to_string(),
+                    });
+                } else {
+                    return Err(ExpandError::Invalid(format!("${{{reference}}}")));
+                }
+            }
+        }
+    }
+    out.push_str(rest);
+    Ok(Some(out))
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first == '_' || first.is_ascii_alphabetic())
+        && chars.all(|ch| ch == '_' || ch.is_ascii_alphanumeric())
+}
+
+fn lookup_env(name: &str) -> Result<Option<String>, ExpandError> {
+    match env::var(name) {
+        Ok(value) => Ok(Some(value)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(env::VarError::NotUnicode(_)) => Err(ExpandError::NotUnicode(name.to_string())),
+    }
+}
+
+/// Expand environment variable references in the plain and quoted scalars of
+/// `docs`. Mapping keys are left as written.
+pub(crate) fn interpolate_documents(docs: &mut [Yaml<'_>], source_map: &SourceMap) -> Fallible<()> {
+    for (index, doc) in docs.iter_mut().enumerate() {
+        interpolate_node(doc, source_map.root(index), source_map)?;
+    }
+    Ok(())
+}
+
+fn interpolate_node(node: &mut Yaml<'_>, ordinal: usize, source_map: &SourceMap) -> Fallible<()> {
+    match node {
+        Yaml::Representation(value, style, _) => {
+            if matches!(style, ScalarStyle::Literal | ScalarStyle::Folded) {
+                return Ok(());
+            }
+            match expand(value, lookup_env) {
+                Ok(Some(expanded)) => *value = Cow::Owned(expanded),
+                Ok(None) => {}
+                Err(err) => return Err(expand_error(err, ordinal, source_map)),
+            }
+        }
+        Yaml::Sequence(items) => {
+            let mut child = ordinal + 1;
+            for item in items.iter_mut() {
+                interpolate_node(item, child, source_map)?;
+                child = source_map.next_sibling(child);
+            }
+        }
+        Yaml::Mapping(entries) => {
+            let mut key = ordinal + 1;
+            for (_, value) in entries.iter_mut() {
+                let value_ordinal = source_map.next_sibling(key);
+                interpolate_node(value, value_ordinal, source_map)?;
+                key = source_map.next_sibling(value_ordinal);
+            }
+        }
+        Yaml::Tagged(_, inner) => interpolate_node(inner, ordinal, source_map)?,
+        Yaml::Value(_) | Yaml::Alias(_) | Yaml::BadValue => {}
+    }
+    Ok(())
+}
+
+fn expand_error(err: ExpandError, ordinal: usize, source_map: &SourceMap) -> savvy::Error {
+    let position = source_map
+        .node(ordinal)
+        .map(|info| {
+            format!(
+                " at line {}, column {}",
+                info.start.line(),
+                info.start.col() + 1
+            )
+        })
+        .unwrap_or_default();
+    api_other(match err {
+        ExpandError::Invalid(reference) => {
+            format!("Invalid environment variable reference `{reference}`{position}")
+        }
+        ExpandError::Required { name, message } if message.is_empty() => {
+            format!("Required environment variable `{name}` is not set{position}")
+        }
+        ExpandError::Required { name, message } => {
+            format!("Required environment variable `{name}` is not set{position}: {message}")
+        }
+        ExpandError::NotUnicode(name) => {
+            format!("Environment variable `{name}` is not valid UTF-8{position}")
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_with_source_map;
+
+    fn lookup(name: &str) -> Result<Option<String>, ExpandError> {
+        Ok(match name {
+            "HOST" => Some("db.local".to_string()),
+            "PORT" => Some("5432".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        })
+    }
+
+    fn expanded(text: &str) -> Result<Option<String>, ExpandError> {
+        expand(text, lookup)
+    }
+
+    #[test]
+    fn expands_references_defaults_and_escapes() {
+        assert_eq!(expanded("plain"), Ok(None));
+        assert_eq!(expanded("costs $5"), Ok(None));
+        assert_eq!(expanded("${HOST}"), Ok(Some("db.local".into())));
+        assert_eq!(
+            expanded("${HOST}:${PORT}/x"),
+            Ok(Some("db.local:5432/x".into()))
+        );
+        assert_eq!(expanded("[${UNSET}]"), Ok(Some("[]".into())));
+        assert_eq!(expanded("${UNSET:-5433}"), Ok(Some("5433".into())));
+        assert_eq!(expanded("${EMPTY:-fallback}"), Ok(Some("fallback".into())));
+        assert_eq!(expanded("${PORT:-1}"), Ok(Some("5432".into())));
+        assert_eq!(
+            expanded("$${HOST} ${HOST}"),
+            Ok(Some("${HOST} db.local".into()))
+        );
+        assert_eq!(
+            expanded("$$ and $x ${HOST}"),
+            Ok(Some("$$ and $x db.local".into()))
+        );
+    }
+
+    #[test]
+    fn reports_required_and_invalid_references() {
+        assert_eq!(
+            expanded("${UNSET:?set it}"),
+            Err(ExpandError::Required {
+                name: "UNSET".into(),
+                message: "set it".into()
+            })
+        );
+        assert_eq!(expanded("${HOST:?unused}"), Ok(Some("db.local".into())));
+        assert_eq!(
+            expanded("${HOST"),
+            Err(ExpandError::Invalid("${HOST".into()))
+        );
+        assert_eq!(expanded("${1X}"), Err(ExpandError::Invalid("${1X}".into())));
+        assert_eq!(
+            expanded("${X:+y}"),
+            Err(ExpandError::Invalid("${X:+y}".into()))
+        );
+    }
+
+    #[test]
+    fn reports_positions_of_scalars() {
+        let text =
+            "a: 1\nb:\n  - ok\n  - '${YAML12_TEST_SURELY_UNSET:?needed}'\nc: |\n  ${literal}\n";
+        let (mut docs, source_map) = load_with_source_map(text, false).unwrap();
+        let Err(savvy::Error::GeneralError(message)) =
+            interpolate_documents(&mut docs, &source_map)
+        else {
+            panic!("expected an error");
+        };
+        assert_eq!(
+            message,
+            "Required environment variable `YAML12_TEST_SURELY_UNSET` is not set at line 4, column 5: needed"
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::document::into_static;
+use crate::handlers::StrictTags;
+use crate::r_ext::{self, PreservedSexp};
+use crate::yaml_to_r::{
+    detached_to_robj, resolve_representation, scan_yaml_documents, ParseOptions,
+};
+use crate::{api_other, Fallible};
+use saphyr::{Scalar, Yaml};
+use savvy::{ExternalPointerSexp, IntoExtPtrSexp, OwnedListSexp, Sexp};
+use savvy_ffi as ffi;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+
+/// A parsed stream shared by every lazy list made from it, with the options
+/// its elements are converted with.
+struct LazyTree {
+    docs: Vec<Yaml<'static>>,
+    handlers: PreservedSexp,
+    simplify: bool,
+}
+
+impl LazyTree {
+    /// Convert `node` to R: a lazy list when it is a mapping or sequence that
+    /// would become a plain R list anyway, and otherwise eagerly.
+    fn to_robj(self: &Rc<Self>, node: &Yaml<'static>) -> Fallible<Sexp> {
+        let Some(children) = lazy_children(node, self.simplify) else {
+            let options = ParseOptions::basic(self.simplify);
+            return detached_to_robj(&mut node.clone(), &self.handlers.value(), &options);
+        };
+        let length = children.items.len();
+        let state = PreservedSexp::new(
+            LazyList {
+                tree: Rc::clone(self),
+                items: children.items,
+            }
+            .into_external_pointer(),
+        );
+        let names = match children.names {
+            Some(names) => {
+                let names = names
+                    .iter()
+                    .map(|name| r_ext::string_data(name))
+                    .collect::<Fallible<Vec<_>>>()?;
+                r_ext::materialize_string_vector(&names)?
+            }
+            None => r_ext::null(),
+        };
+        r_ext::new_lazy_list(&state.value(), names, length)
+    }
+}
+
+/// The state behind one lazy list: the values of a mapping or the items of a
+/// sequence in `tree`.
+pub(crate) struct LazyList {
+    tree: Rc<LazyTree>,
+    // Point into `tree.docs`, which is never modified while `tree` is alive.
+    items: Vec<*const Yaml<'static>>,
+}
+
+impl IntoExtPtrSexp for LazyList {}
+
+struct LazyChildren {
+    items: Vec<*const Yaml<'static>>,
+    names: Option<Vec<String>>,
+}
+
+/// The children of `node` if it converts to a plain list: a non-empty,
+/// untagged mapping whose keys are all strings, or a non-empty, untagged
+/// sequence that `simplify` cannot turn into a vector.
+fn lazy_children(node: &Yaml<'static>, simplify: bool) -> Option<LazyChildren> {
+    match node {
+        Yaml::Mapping(map) if !map.is_empty() => {
+            let mut names = Vec::with_capacity(map.len());
+            for key in map.keys() {
+                let mut key = key.clone();
+                resolve_representation(&mut key, simplify);
+                let Yaml::Value(Scalar::String(name)) = key else {
+                    return None;
+                };
+                names.push(name.into_owned());
+            }
+            Some(LazyChildren {
+                items: map.values().map(|value| value as *const _).collect(),
+                names: Some(names),
+            })
+        }
+        Yaml::Sequence(items) if !items.is_empty() => {
+            let is_collection = |item: &Yaml<'_>| {
+                matches!(item, Yaml::Mapping(_) | Yaml::Sequence(_))
+                    || matches!(item, Yaml::Tagged(_, inner)
+                        if matches!(**inner, Yaml::Mapping(_) | Yaml::Sequence(_)))
+            };
+            if simplify && !items.iter().any(is_collection) {
+                return None;
+            }
+            Some(LazyChildren {
+                items: items.iter().map(|item| item as *const _).collect(),
+                names: None,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Parse `text` and return its first document, or with `multi` a list of
+/// all documents, converting mappings and sequences to lazy lists.
+pub(crate) fn parse_lazy(text: &str, handlers: Sexp, options: &ParseOptions) -> Fallible<Sexp> {
+    let strict = StrictTags::from_robj(&options.strict_tags)?.is_some();
+    if options.handle_untagged || options.interpolate || strict {
+        return Err(api_other(
+            "`lazy = TRUE` cannot be combined with `handle_untagged`, `strict_tags`, or `interpolate`",
+        ));
+    }
+    let docs = scan_yaml_documents(text, options.multi)
+        .map_err(|err| api_other(format!("YAML parse error: {err}")))?;
+    let tree = Rc::new(LazyTree {
+        docs: docs.into_iter().map(into_static).collect(),
+        handlers: PreservedSexp::new(handlers),
+        simplify: options.simplify,
+    });
+    if !options.multi {
+        return match tree.docs.first() {
+            Some(doc) => tree.to_robj(doc),
+            None => Ok(r_ext::null()),
+        };
+    }
+    let mut list = OwnedListSexp::new(tree.docs.len(), false)?;
+    for (index, doc) in tree.docs.iter().enumerate() {
+        list.set_value(index, tree.to_robj(doc)?)?;
+    }
+    Ok(list.into())
+}
+
+/// Convert element `index` of the lazy list whose state is `state`. Called
+/// by the ALTREP `Elt` method in `yaml12_lazy.c`, which raises the error of a
+/// tagged result.
+#[no_mangle]
+pub unsafe extern "C" fn yaml12_lazy_list_elt(state: ffi::SEXP, index: ffi::R_xlen_t) -> ffi::SEXP {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let pointer = ExternalPointerSexp::try_from(Sexp(state))?;
+        let list = unsafe { pointer.cast_unchecked::<LazyList>().as_ref() }
+            .ok_or_else(|| api_other("Lazy YAML list is no longer valid"))?;
+        let node = unsafe { &*list.items[index as usize] };
+        list.tree.to_robj(node)
+    }));
+    match result {
+        Ok(Ok(value)) => value.0,
+        Ok(Err(err)) => savvy::handle_error(err),
+        Err(_) => savvy::handle_error(api_other("Converting a lazy YAML element panicked")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_lazy(text: &str, simplify: bool) -> bool {
+        let doc = into_static(scan_yaml_documents(text, false).unwrap().remove(0));
+        lazy_children(&doc, simplify).is_some()
+    }
+
+    #[test]
+    fn only_plain_lists_are_lazy() {
+        assert!(is_lazy("{a: 1, 'b': [1, 2]}", true));
+        assert!(is_lazy("[{a: 1}, 2]", true));
+        assert!(is_lazy("[1, 2]", false));
+        assert!(!is_lazy("[1, 2]", true));
+        assert!(!is_lazy("{1: a, b: c}", true));
+        assert!(!is_lazy("{!k a: 1}", true));
+        assert!(!is_lazy("!set {a: 1}", true));
+        assert!(!is_lazy("{}", true));
+        assert!(!is_lazy("[]", false));
+        assert!(!is_lazy("a", true));
+    }
+}
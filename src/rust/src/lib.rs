@@ -1,15 +1,32 @@
+mod canonical;
+mod config;
+mod diff;
+mod document;
+mod edit;
 mod emitter;
+mod events;
 mod handlers;
+mod include;
+mod interpolate;
+mod lazy;
+mod lint;
+mod loader;
+mod merge;
+mod patch;
+mod query;
 mod r_ext;
 mod r_to_yaml;
+mod recover;
+mod reformat;
 mod timestamp;
-mod warning;
+mod tree;
+mod validate;
 mod yaml_to_r;
 
 use crate::r_ext::null;
 use crate::r_to_yaml::yaml_body;
 use saphyr::{LoadableYamlNode, Yaml};
-use savvy::{savvy, savvy_init, NotAvailableValue, NumericScalar, Sexp, StringSexp};
+use savvy::{savvy, savvy_init, ListSexp, NotAvailableValue, NumericScalar, Sexp, StringSexp};
 use savvy_ffi::DllInfo;
 
 pub(crate) type Fallible<T> = savvy::Result<T>;
@@ -62,14 +79,44 @@ fn format_yaml_native(value: Sexp, multi: bool, width: Sexp) -> savvy::Result<Se
     r_ext::string_scalar(body)
 }
 
+#[allow(clippy::too_many_arguments)]
 #[savvy]
 fn parse_yaml_native(
     text: StringSexp,
     multi: bool,
     simplify: bool,
     handlers: Sexp,
+    handle_untagged: bool,
+    strict_tags: Sexp,
+    interpolate: bool,
+    lazy: bool,
+    recover: bool,
+    all_errors: bool,
 ) -> savvy::Result<Sexp> {
-    yaml_to_r::parse_yaml_impl(text, multi, simplify, handlers)
+    let options = yaml_to_r::ParseOptions {
+        multi,
+        simplify,
+        handle_untagged,
+        strict_tags,
+        interpolate,
+    };
+    if recover {
+        let text = r_ext::text_arg(&text, "text")?;
+        return recover::parse_recovering(&text, multi, all_errors, |text| {
+            if lazy {
+                lazy::parse_lazy(text, handlers, &options)
+            } else {
+                yaml_to_r::parse_text_impl(text, handlers, options)
+            }
+        });
+    }
+    if !lazy {
+        return yaml_to_r::parse_yaml_impl(text, handlers, options);
+    }
+    if text.is_empty() {
+        return Ok(null());
+    }
+    lazy::parse_lazy(&r_ext::text_arg(&text, "text")?, handlers, &options)
 }
 
 #[savvy]
@@ -121,15 +168,272 @@ fn optional_path_arg(path: Sexp) -> savvy::Result<Option<String>> {
     path_arg(&path, "path").map(Some)
 }
 
+#[allow(clippy::too_many_arguments)]
 #[savvy]
 fn read_yaml_native(
     path: StringSexp,
     multi: bool,
     simplify: bool,
     handlers: Sexp,
+    handle_untagged: bool,
+    strict_tags: Sexp,
+    interpolate: bool,
+    include: Sexp,
+    lazy: bool,
+    recover: bool,
+    all_errors: bool,
+) -> savvy::Result<Sexp> {
+    let path = path_arg(&path, "path")?;
+    let options = yaml_to_r::ParseOptions {
+        multi,
+        simplify,
+        handle_untagged,
+        strict_tags,
+        interpolate,
+    };
+    if !lazy && !recover {
+        return yaml_to_r::read_yaml_impl(&path, handlers, options, include);
+    }
+    if lazy && include::IncludeOptions::from_robj(&include)?.is_some() {
+        return Err(api_other("`lazy = TRUE` cannot be combined with `include`"));
+    }
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|err| api_other(format!("Failed to read `{path}`: {err}")))?;
+    let load = |text: &str| {
+        if lazy {
+            lazy::parse_lazy(text, handlers, &options)
+        } else {
+            yaml_to_r::read_text_impl(&path, text, handlers, options, include)
+        }
+    };
+    if recover {
+        return recover::parse_recovering(&contents, multi, all_errors, load);
+    }
+    load(&contents)
+}
+
+#[savvy]
+fn read_config_native(
+    path: StringSexp,
+    config: StringSexp,
+    simplify: bool,
+    handlers: Sexp,
+    interpolate: bool,
 ) -> savvy::Result<Sexp> {
     let path = path_arg(&path, "path")?;
-    yaml_to_r::read_yaml_impl(&path, multi, simplify, handlers)
+    let config = path_arg(&config, "config")?;
+    let options = yaml_to_r::ParseOptions {
+        multi: false,
+        simplify,
+        handle_untagged: false,
+        strict_tags: null(),
+        interpolate,
+    };
+    yaml_to_r::read_config_impl(&path, &config, handlers, options)
+}
+
+#[savvy]
+fn yaml_equal_native(
+    a: StringSexp,
+    b: StringSexp,
+    ignore_tags: bool,
+    ignore_order: bool,
+    multi: bool,
+) -> savvy::Result<Sexp> {
+    let options = canonical::CanonicalOptions {
+        ignore_tags,
+        ignore_order,
+    };
+    r_ext::logical_scalar(canonical::yaml_equal_impl(a, b, multi, options)?)
+}
+
+#[savvy]
+fn yaml_hash_native(
+    x: StringSexp,
+    ignore_tags: bool,
+    ignore_order: bool,
+    multi: bool,
+) -> savvy::Result<Sexp> {
+    let options = canonical::CanonicalOptions {
+        ignore_tags,
+        ignore_order,
+    };
+    r_ext::string_scalar(&canonical::yaml_hash_impl(x, multi, options)?)
+}
+
+#[savvy]
+fn yaml_merge_native(
+    values: ListSexp,
+    text: bool,
+    sequences: &str,
+    nulls: &str,
+    simplify: bool,
+) -> savvy::Result<Sexp> {
+    let options = merge::MergeOptions::from_args(sequences, nulls)?;
+    merge::yaml_merge_impl(values, text, options, simplify)
+}
+
+#[savvy]
+fn yaml_diff_native(
+    old: StringSexp,
+    new: StringSexp,
+    ignore_order: bool,
+    report: bool,
+    simplify: bool,
+) -> savvy::Result<Sexp> {
+    diff::yaml_diff_impl(old, new, ignore_order, report, simplify)
+}
+
+/// The YAML text of `x`: the contents of the file it names when `is_file`
+/// is set, and otherwise its elements joined with newlines.
+fn yaml_input_arg(x: &StringSexp, is_file: bool) -> savvy::Result<String> {
+    if !is_file {
+        return r_ext::text_arg(x, "x");
+    }
+    let path = path_arg(x, "x")?;
+    std::fs::read_to_string(&path)
+        .map_err(|err| api_other(format!("Failed to read `{path}`: {err}")))
+}
+
+#[savvy]
+fn yaml_get_native(
+    x: StringSexp,
+    is_file: bool,
+    query: &str,
+    simplify: bool,
+    handlers: Sexp,
+) -> savvy::Result<Sexp> {
+    let input = yaml_input_arg(&x, is_file)?;
+    query::yaml_get_impl(
+        &input,
+        query,
+        handlers,
+        yaml_to_r::ParseOptions::basic(simplify),
+    )
+}
+
+/// Write `edited` back to the file `x` names when `is_file` is set, and
+/// return it as a string.
+fn edited_output(x: &StringSexp, is_file: bool, edited: &str) -> savvy::Result<Sexp> {
+    if is_file {
+        let path = path_arg(x, "x")?;
+        std::fs::write(&path, edited)
+            .map_err(|err| api_other(format!("Failed to write `{path}`: {err}")))?;
+    }
+    r_ext::string_scalar(edited)
+}
+
+#[savvy]
+fn yaml_set_native(x: StringSexp, is_file: bool, query: &str, value: Sexp) -> savvy::Result<Sexp> {
+    let input = yaml_input_arg(&x, is_file)?;
+    let value = r_to_yaml::robj_to_yaml(&value)?;
+    let edited = edit::set_value(&input, query, &value)?;
+    edited_output(&x, is_file, &edited)
+}
+
+#[savvy]
+fn yaml_delete_native(x: StringSexp, is_file: bool, query: &str) -> savvy::Result<Sexp> {
+    let input = yaml_input_arg(&x, is_file)?;
+    let edited = edit::delete_value(&input, query)?;
+    edited_output(&x, is_file, &edited)
+}
+
+#[savvy]
+fn yaml_document_native(x: StringSexp, is_file: bool) -> savvy::Result<Sexp> {
+    let input = yaml_input_arg(&x, is_file)?;
+    Ok(document::YamlDocument::parse(&input)?.into_handle())
+}
+
+#[savvy]
+fn yaml_document_kind_native(doc: Sexp, path: ListSexp) -> savvy::Result<Sexp> {
+    let doc = document::YamlDocument::from_handle(&doc)?;
+    match doc.get(&path)? {
+        Some(node) => r_ext::string_scalar(document::node_kind(node)),
+        None => Ok(null()),
+    }
+}
+
+#[savvy]
+fn yaml_document_length_native(doc: Sexp, path: ListSexp) -> savvy::Result<Sexp> {
+    let doc = document::YamlDocument::from_handle(&doc)?;
+    let length = document::node_length(doc.node(&path)?);
+    let length = i32::try_from(length)
+        .map_err(|_| api_other("YAML node has too many entries for an R length"))?;
+    r_ext::integer_scalar(length)
+}
+
+#[savvy]
+fn yaml_document_names_native(doc: Sexp, path: ListSexp) -> savvy::Result<Sexp> {
+    let doc = document::YamlDocument::from_handle(&doc)?;
+    document::node_names(doc.node(&path)?)
+}
+
+#[savvy]
+fn yaml_document_convert_native(
+    doc: Sexp,
+    path: ListSexp,
+    simplify: bool,
+    handlers: Sexp,
+) -> savvy::Result<Sexp> {
+    let doc = document::YamlDocument::from_handle(&doc)?;
+    document::node_to_robj(
+        doc.node(&path)?,
+        &handlers,
+        &yaml_to_r::ParseOptions::basic(simplify),
+    )
+}
+
+#[savvy]
+fn yaml_events_native(text: StringSexp, text_format: bool) -> savvy::Result<Sexp> {
+    let text = r_ext::text_arg(&text, "text")?;
+    events::yaml_events_impl(&text, text_format)
+}
+
+#[savvy]
+fn yaml_validate_native(
+    x: StringSexp,
+    text: bool,
+    duplicate_keys: bool,
+    known_tags: Sexp,
+) -> savvy::Result<Sexp> {
+    let options = validate::ValidateOptions::from_args(duplicate_keys, &known_tags)?;
+    validate::yaml_validate_impl(x, text, &options)
+}
+
+#[savvy]
+fn yaml_lint_native(
+    x: StringSexp,
+    text: bool,
+    rules: StringSexp,
+    severities: StringSexp,
+    max_line_length: i32,
+    indent_spaces: i32,
+    document_start: bool,
+) -> savvy::Result<Sexp> {
+    let options = lint::LintOptions::from_args(
+        &rules,
+        &severities,
+        max_line_length,
+        indent_spaces,
+        document_start,
+    )?;
+    lint::yaml_lint_impl(x, text, &options)
+}
+
+#[savvy]
+fn yaml_reformat_native(text: StringSexp) -> savvy::Result<Sexp> {
+    let text = r_ext::text_arg(&text, "text")?;
+    reformat::yaml_reformat_impl(&text)
+}
+
+#[savvy]
+fn yaml_format_file_native(path: StringSexp, check: bool) -> savvy::Result<Sexp> {
+    reformat::yaml_format_file_impl(&path, check)
+}
+
+#[savvy]
+fn yaml_patch_native(doc: StringSexp, ops: Sexp) -> savvy::Result<Sexp> {
+    patch::yaml_patch_impl(doc, ops)
 }
 
 #[savvy]
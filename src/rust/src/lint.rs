@@ -0,0 +1,608 @@
+//! Style checks in the manner of yamllint.
+//!
+//! Line rules look at the raw text, so they apply even past a syntax error.
+//! The other rules run on the parser events up to the first error, with
+//! duplicate keys found as `yaml_validate()` finds them.
+
+use crate::emitter::resolves_to_string;
+use crate::events::{parse_events_until_error, EventKind, ParseEvent};
+use crate::r_ext;
+use crate::validate::{duplicate_keys, usize_column, Diagnostic};
+use crate::{api_other, Fallible};
+use savvy::{NotAvailableValue, OwnedListSexp, Sexp, StringSexp};
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Rule {
+    LineLength,
+    TrailingSpaces,
+    Indentation,
+    Tabs,
+    DuplicateKeys,
+    Truthy,
+    EmptyValues,
+    KeyOrdering,
+    DocumentStart,
+}
+
+impl Rule {
+    const ALL: [Rule; 9] = [
+        Rule::LineLength,
+        Rule::TrailingSpaces,
+        Rule::Indentation,
+        Rule::Tabs,
+        Rule::DuplicateKeys,
+        Rule::Truthy,
+        Rule::EmptyValues,
+        Rule::KeyOrdering,
+        Rule::DocumentStart,
+    ];
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Rule::LineLength => "line_length",
+            Rule::TrailingSpaces => "trailing_spaces",
+            Rule::Indentation => "indentation",
+            Rule::Tabs => "tabs",
+            Rule::DuplicateKeys => "duplicate_keys",
+            Rule::Truthy => "truthy",
+            Rule::EmptyValues => "empty_values",
+            Rule::KeyOrdering => "key_ordering",
+            Rule::DocumentStart => "document_start",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rule| rule.as_str() == name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// The enabled rules with their severities and settings.
+pub(crate) struct LintOptions {
+    pub(crate) rules: HashMap<Rule, Severity>,
+    pub(crate) max_line_length: usize,
+    /// The indentation step; `None` takes the first step found.
+    pub(crate) indent_spaces: Option<usize>,
+    /// Whether `document_start` requires `---` rather than forbidding it.
+    pub(crate) document_start: bool,
+}
+
+impl LintOptions {
+    pub(crate) fn from_args(
+        rules: &StringSexp,
+        severities: &StringSexp,
+        max_line_length: i32,
+        indent_spaces: i32,
+        document_start: bool,
+    ) -> Fallible<Self> {
+        if rules.len() != severities.len() {
+            return Err(api_other(
+                "`rules` and `severities` must have the same length",
+            ));
+        }
+        let mut enabled = HashMap::new();
+        for index in 0..rules.len() {
+            let name = r_ext::string_elt(rules, index)?;
+            let rule = Rule::from_name(name)
+                .ok_or_else(|| api_other(format!("Unknown lint rule `{name}`")))?;
+            let severity = match r_ext::string_elt(severities, index)? {
+                "error" => Severity::Error,
+                "warning" => Severity::Warning,
+                other => {
+                    return Err(api_other(format!(
+                        "Severity of `{name}` must be \"error\" or \"warning\", not `{other}`"
+                    )))
+                }
+            };
+            enabled.insert(rule, severity);
+        }
+        let max_line_length = usize::try_from(max_line_length)
+            .ok()
+            .filter(|max| *max > 0)
+            .ok_or_else(|| api_other("`max` of `line_length` must be a positive number"))?;
+        let indent_spaces = if indent_spaces.is_na() {
+            None
+        } else {
+            let spaces = usize::try_from(indent_spaces)
+                .ok()
+                .filter(|spaces| *spaces > 0)
+                .ok_or_else(|| {
+                    api_other(
+                        "`spaces` of `indentation` must be \"consistent\" or a positive number",
+                    )
+                })?;
+            Some(spaces)
+        };
+        Ok(Self {
+            rules: enabled,
+            max_line_length,
+            indent_spaces,
+            document_start,
+        })
+    }
+}
+
+/// A problem found by a rule, or a syntax error when `rule` is `None`.
+#[derive(Debug)]
+pub(crate) struct LintDiagnostic {
+    pub(crate) rule: Option<Rule>,
+    pub(crate) severity: Severity,
+    pub(crate) diagnostic: Diagnostic,
+}
+
+/// Whether plain `value` is a YAML 1.1 boolean, such as `yes` or `Off`, that
+/// the core schema the emitter quotes by reads as a string. YAML 1.1
+/// booleans are `y`, `yes`, `n`, `no`, `on`, `off`, `true`, and `false`, in
+/// lower, title, or upper case.
+fn is_truthy(value: &str) -> bool {
+    let lower = value.to_ascii_lowercase();
+    let title = lower
+        .get(..1)
+        .map(|first| first.to_ascii_uppercase() + &lower[1..])
+        .unwrap_or_default();
+    matches!(
+        lower.as_str(),
+        "y" | "yes" | "n" | "no" | "on" | "off" | "true" | "false"
+    ) && (value == lower || value == title || value == value.to_ascii_uppercase())
+        && resolves_to_string(value)
+}
+
+struct Linter<'a> {
+    options: &'a LintOptions,
+    lines: Vec<&'a str>,
+    found: Vec<LintDiagnostic>,
+    /// The indentation step in use, once known.
+    indent_step: Option<usize>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, rule: Rule, line: usize, column: usize, message: String) {
+        if let Some(&severity) = self.options.rules.get(&rule) {
+            self.found.push(LintDiagnostic {
+                rule: Some(rule),
+                severity,
+                diagnostic: Diagnostic {
+                    line: Some(line),
+                    column: Some(column),
+                    message,
+                },
+            });
+        }
+    }
+
+    fn enabled(&self, rule: Rule) -> bool {
+        self.options.rules.contains_key(&rule)
+    }
+
+    fn check_lines(&mut self) {
+        let max = self.options.max_line_length;
+        for index in 0..self.lines.len() {
+            let line = self.lines[index]
+                .strip_suffix('\r')
+                .unwrap_or(self.lines[index]);
+            let number = index + 1;
+
+            let length = line.chars().count();
+            if length > max && !is_single_word(line) {
+                let message = format!("Line is too long ({length} > {max} characters)");
+                self.report(Rule::LineLength, number, max + 1, message);
+            }
+
+            let content = line.trim_end_matches([' ', '\t']);
+            if content.len() < line.len() {
+                let column = content.chars().count() + 1;
+                self.report(
+                    Rule::TrailingSpaces,
+                    number,
+                    column,
+                    "Trailing whitespace".to_string(),
+                );
+            }
+
+            let indentation = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+            if let Some(tab) = indentation.find('\t') {
+                self.report(
+                    Rule::Tabs,
+                    number,
+                    tab + 1,
+                    "Tab character in indentation".to_string(),
+                );
+            }
+        }
+    }
+
+    /// The indentation of a block collection: the column of its `-` for a
+    /// sequence, whose event starts at the `-` or, when not indented, at its
+    /// first item. Also reports whether the collection is on the same line as
+    /// the `-` of its parent item, as in `- - a` or `- a: 1`.
+    fn block_indentation(&self, event: &ParseEvent) -> (usize, bool) {
+        let line = self.lines.get(event.line - 1).copied().unwrap_or_default();
+        let before: Vec<char> = line.chars().take(event.column - 1).collect();
+        let mut indent = before.len();
+        if event.kind == EventKind::SequenceStart && line.chars().nth(indent) != Some('-') {
+            let dash = before.iter().rposition(|ch| *ch != ' ');
+            if let Some(dash) = dash.filter(|&dash| before[dash] == '-') {
+                indent = dash;
+            }
+        }
+        let compact = before[..indent].contains(&'-');
+        (indent, compact)
+    }
+
+    fn check_indentation(&mut self, event: &ParseEvent, parent: Option<&Open>) -> usize {
+        let (indent, compact) = self.block_indentation(event);
+        if compact {
+            return indent;
+        }
+        let expected = match parent {
+            None => 0,
+            Some(parent) if parent.flow => return indent,
+            // A sequence may sit at the indentation of its mapping's keys.
+            Some(parent)
+                if parent.mapping.is_some()
+                    && event.kind == EventKind::SequenceStart
+                    && indent == parent.indent =>
+            {
+                return indent;
+            }
+            Some(parent) => {
+                let step = self
+                    .options
+                    .indent_spaces
+                    .or(self.indent_step)
+                    .unwrap_or_else(|| indent.saturating_sub(parent.indent).max(1));
+                self.indent_step.get_or_insert(step);
+                parent.indent + step
+            }
+        };
+        if indent != expected {
+            self.report(
+                Rule::Indentation,
+                event.line,
+                event.column.min(indent + 1),
+                format!("Wrong indentation: expected {expected} spaces but found {indent}"),
+            );
+        }
+        indent
+    }
+
+    fn check_events(&mut self, events: &[ParseEvent]) {
+        let mut open: Vec<Open> = Vec::new();
+        for event in events {
+            match event.kind {
+                EventKind::DocumentStart => {
+                    let explicit = event.style == Some("explicit");
+                    if !explicit && self.options.document_start {
+                        let message = "Missing document start `---`".to_string();
+                        self.report(Rule::DocumentStart, event.line, event.column, message);
+                    } else if explicit && !self.options.document_start {
+                        let message = "Found forbidden document start `---`".to_string();
+                        self.report(Rule::DocumentStart, event.line, event.column, message);
+                    }
+                }
+                EventKind::MappingStart | EventKind::SequenceStart => {
+                    let flow = event.style == Some("flow");
+                    let indent = if flow || !self.enabled(Rule::Indentation) {
+                        0
+                    } else {
+                        self.check_indentation(event, open.last())
+                    };
+                    let mapping = (event.kind == EventKind::MappingStart).then_some(MappingState {
+                        expecting_key: true,
+                        key: None,
+                        previous_key: None,
+                    });
+                    open.push(Open {
+                        flow: flow || open.last().is_some_and(|parent| parent.flow),
+                        indent,
+                        mapping,
+                    });
+                }
+                EventKind::MappingEnd | EventKind::SequenceEnd => {
+                    open.pop();
+                    self.complete(open.last_mut(), None);
+                }
+                EventKind::Scalar => {
+                    let value = event.value.as_deref().unwrap_or_default();
+                    if event.style == Some("plain") && event.tag.is_none() && is_truthy(value) {
+                        let message = format!(
+                            "Truthy value `{value}` is a string in YAML 1.2 but a boolean in YAML 1.1"
+                        );
+                        self.report(Rule::Truthy, event.line, event.column, message);
+                    }
+                    self.complete(open.last_mut(), Some(event));
+                }
+                EventKind::Alias => self.complete(open.last_mut(), None),
+                _ => {}
+            }
+        }
+    }
+
+    /// Record a finished node in the collection it belongs to; `scalar` is
+    /// its event when it is a scalar.
+    fn complete(&mut self, parent: Option<&mut Open>, scalar: Option<&ParseEvent>) {
+        let Some(mapping) = parent.and_then(|parent| parent.mapping.as_mut()) else {
+            return;
+        };
+        mapping.expecting_key = !mapping.expecting_key;
+        if !mapping.expecting_key {
+            let key = scalar.map(|event| {
+                let text = event.value.clone().unwrap_or_default();
+                (text, event.line, event.column)
+            });
+            if let (Some((text, line, column)), Some(previous)) = (&key, &mapping.previous_key) {
+                if text < previous {
+                    let message = format!("Key `{text}` should come before `{previous}`");
+                    let (line, column) = (*line, *column);
+                    self.report(Rule::KeyOrdering, line, column, message);
+                }
+            }
+            if let Some((text, ..)) = &key {
+                mapping.previous_key = Some(text.clone());
+            }
+            mapping.key = key;
+            return;
+        }
+        let empty = scalar.is_some_and(|event| {
+            event.style == Some("plain")
+                && event.tag.is_none()
+                && event.value.as_deref() == Some("")
+        });
+        if let (true, Some((key, line, column))) = (empty, mapping.key.take()) {
+            let message = format!("Empty value for key `{key}`");
+            self.report(Rule::EmptyValues, line, column, message);
+        }
+    }
+}
+
+/// An open collection.
+struct Open {
+    flow: bool,
+    indent: usize,
+    /// `None` for a sequence.
+    mapping: Option<MappingState>,
+}
+
+struct MappingState {
+    expecting_key: bool,
+    /// The current scalar key, with its line and column.
+    key: Option<(String, usize, usize)>,
+    previous_key: Option<String>,
+}
+
+/// Whether `line` is a single word after its indentation and any `- ` or
+/// comment marker, like a long URL, which cannot be broken to fit.
+fn is_single_word(line: &str) -> bool {
+    let mut rest = line.trim_start_matches(' ');
+    if let Some(comment) = rest.strip_prefix('#') {
+        rest = comment.trim_start_matches('#');
+    } else if let Some(item) = rest.strip_prefix('-') {
+        rest = item;
+    }
+    let rest = rest.strip_prefix(' ').unwrap_or(rest);
+    !rest.is_empty() && !rest.contains(' ')
+}
+
+/// Apply the rules enabled in `options` to `text`. A syntax error is
+/// reported too, always as an error.
+pub(crate) fn lint_text(text: &str, options: &LintOptions) -> Vec<LintDiagnostic> {
+    let mut linter = Linter {
+        options,
+        lines: text.split('\n').collect(),
+        found: Vec::new(),
+        indent_step: None,
+    };
+    if linter.lines.last() == Some(&"") {
+        linter.lines.pop();
+    }
+    linter.check_lines();
+
+    let (events, syntax_error) = parse_events_until_error(text);
+    linter.check_events(&events);
+
+    if linter.enabled(Rule::DuplicateKeys) {
+        for duplicate in duplicate_keys(&events) {
+            let (line, column) = (duplicate.line.unwrap_or(0), duplicate.column.unwrap_or(0));
+            linter.report(Rule::DuplicateKeys, line, column, duplicate.message);
+        }
+    }
+    linter.found.extend(syntax_error.map(|err| LintDiagnostic {
+        rule: None,
+        severity: Severity::Error,
+        diagnostic: Diagnostic::from_scan_error(&err),
+    }));
+
+    let mut found = linter.found;
+    found.sort_by_key(|found| (found.diagnostic.line, found.diagnostic.column));
+    found
+}
+
+/// Lint each element of `x`, a file path or, with `text`, YAML text.
+/// Returns a list of the `input`, `file`, `line`, `column`, `rule`,
+/// `severity`, and `message` columns of the diagnostics.
+pub(crate) fn yaml_lint_impl(x: StringSexp, text: bool, options: &LintOptions) -> Fallible<Sexp> {
+    let mut found: Vec<(usize, LintDiagnostic)> = Vec::new();
+    for index in 0..x.len() {
+        let input = r_ext::string_elt(&x, index)?;
+        if input.is_na() {
+            return Err(api_other("`x` must not contain NA strings"));
+        }
+        let diagnostics = if text {
+            lint_text(input, options)
+        } else {
+            match std::fs::read_to_string(input) {
+                Ok(contents) => lint_text(&contents, options),
+                Err(err) => vec![LintDiagnostic {
+                    rule: None,
+                    severity: Severity::Error,
+                    diagnostic: Diagnostic {
+                        line: None,
+                        column: None,
+                        message: format!("Failed to read file: {err}"),
+                    },
+                }],
+            }
+        };
+        found.extend(diagnostics.into_iter().map(|found| (index, found)));
+    }
+
+    let string_column = |value: &dyn Fn(usize, &LintDiagnostic) -> Fallible<&str>| {
+        let values = found
+            .iter()
+            .map(|(index, found)| r_ext::string_data(value(*index, found)?))
+            .collect::<Fallible<Vec<_>>>()?;
+        r_ext::materialize_string_vector(&values)
+    };
+    let files = string_column(&|index, _| {
+        if text {
+            Ok(<&str>::na())
+        } else {
+            r_ext::string_elt(&x, index)
+        }
+    })?;
+    let rules = string_column(&|_, found| Ok(found.rule.map_or("syntax", Rule::as_str)))?;
+    let severities = string_column(&|_, found| Ok(found.severity.as_str()))?;
+    let messages = string_column(&|_, found| Ok(found.diagnostic.message.as_str()))?;
+
+    let mut columns = OwnedListSexp::new(7, true)?;
+    columns.set_name_and_value(
+        0,
+        "input",
+        usize_column(found.iter().map(|(index, _)| Some(index + 1)))?,
+    )?;
+    columns.set_name_and_value(1, "file", files)?;
+    columns.set_name_and_value(
+        2,
+        "line",
+        usize_column(found.iter().map(|(_, found)| found.diagnostic.line))?,
+    )?;
+    columns.set_name_and_value(
+        3,
+        "column",
+        usize_column(found.iter().map(|(_, found)| found.diagnostic.column))?,
+    )?;
+    columns.set_name_and_value(4, "rule", rules)?;
+    columns.set_name_and_value(5, "severity", severities)?;
+    columns.set_name_and_value(6, "message", messages)?;
+    Ok(columns.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(rules: &[Rule]) -> LintOptions {
+        LintOptions {
+            rules: rules.iter().map(|rule| (*rule, Severity::Error)).collect(),
+            max_line_length: 20,
+            indent_spaces: None,
+            document_start: true,
+        }
+    }
+
+    fn found(text: &str, rules: &[Rule]) -> Vec<(&'static str, usize, usize)> {
+        lint_text(text, &options(rules))
+            .iter()
+            .map(|found| {
+                (
+                    found.rule.map_or("syntax", Rule::as_str),
+                    found.diagnostic.line.unwrap(),
+                    found.diagnostic.column.unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn checks_lines() {
+        let text = "a: 1 \nb: 12345678901234567890\n# https://example.com/a/long/url\n\tc: 2\r\n";
+        assert_eq!(
+            found(text, &[Rule::LineLength, Rule::TrailingSpaces, Rule::Tabs]),
+            [
+                ("trailing_spaces", 1, 5),
+                ("line_length", 2, 21),
+                ("tabs", 4, 1),
+                ("syntax", 4, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_indentation() {
+        let rules = [Rule::Indentation];
+        let good = "a:\n  b:\n  - c\n  - - d\n    - e: 1\n      f:\n        g: 2\nh:\n- i\n";
+        assert!(found(good, &rules).is_empty());
+        let bad = "a:\n    b: 1\nc:\n  d: 2\ne:\n    - 3\nf:\n  - 4\n";
+        assert_eq!(
+            found(bad, &rules),
+            [("indentation", 4, 3), ("indentation", 8, 3)]
+        );
+        let mut fixed = options(&rules);
+        fixed.indent_spaces = Some(2);
+        let messages: Vec<String> = lint_text("a:\n    b: 1\n", &fixed)
+            .into_iter()
+            .map(|found| found.diagnostic.message)
+            .collect();
+        assert_eq!(
+            messages,
+            ["Wrong indentation: expected 2 spaces but found 4"]
+        );
+        assert!(found("a: [\n      1]\nb: {c: {\n d: 2}}\n", &rules).is_empty());
+    }
+
+    #[test]
+    fn checks_keys_and_values() {
+        let text = "--- \nb: yes\na: 'no'\nc:\nb: [on, !!str off]\nd: {e: }\n";
+        assert_eq!(
+            found(
+                text,
+                &[
+                    Rule::DuplicateKeys,
+                    Rule::Truthy,
+                    Rule::EmptyValues,
+                    Rule::KeyOrdering,
+                    Rule::DocumentStart,
+                ]
+            ),
+            [
+                ("truthy", 2, 4),
+                ("key_ordering", 3, 1),
+                ("empty_values", 4, 1),
+                ("key_ordering", 5, 1),
+                ("duplicate_keys", 5, 1),
+                ("truthy", 5, 5),
+                ("empty_values", 6, 5),
+            ]
+        );
+        assert_eq!(
+            found("[True, false, Off, oN, Y, yess]\n", &[Rule::Truthy]),
+            [("truthy", 1, 15), ("truthy", 1, 24)]
+        );
+        assert_eq!(
+            found("a: 1\n!!str 1: 2\n\"a\": [\n", &[Rule::DuplicateKeys]),
+            [("duplicate_keys", 3, 1), ("syntax", 4, 1)]
+        );
+        assert_eq!(
+            found("a: 1\n---\nb: 2\n", &[Rule::DocumentStart]),
+            [("document_start", 1, 1)]
+        );
+        let mut forbid = options(&[Rule::DocumentStart]);
+        forbid.document_start = false;
+        assert_eq!(lint_text("---\na: 1\n", &forbid).len(), 1);
+    }
+}
@@ -0,0 +1,570 @@
+//! Document loading with optional per-node source metadata.
+//!
+//! saphyr's `YamlLoader` builds `Yaml` trees without spans or anchor names.
+//! `RecordingLoader` forwards every event to a `YamlLoader` and records a
+//! `NodeInfo` for each node in pre-order alongside it, so the tree is
+//! unchanged and a node's metadata is found from its position in the tree:
+//! a node's first child follows it directly, and each later sibling follows
+//! the previous sibling's subtree.
+
+use saphyr::{LoadableYamlNode, Mapping, Tag, Yaml, YamlLoader};
+use saphyr_parser::{Event, Marker, Parser, ScalarStyle, ScanError, Span, SpannedEventReceiver};
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// How a node was written in the source.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum NodeStyle {
+    Scalar(ScalarStyle),
+    Block,
+    Flow,
+}
+
+impl NodeStyle {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            NodeStyle::Scalar(ScalarStyle::Plain) => "plain",
+            NodeStyle::Scalar(ScalarStyle::SingleQuoted) => "single_quoted",
+            NodeStyle::Scalar(ScalarStyle::DoubleQuoted) => "double_quoted",
+            NodeStyle::Scalar(ScalarStyle::Literal) => "literal",
+            NodeStyle::Scalar(ScalarStyle::Folded) => "folded",
+            NodeStyle::Block => "block",
+            NodeStyle::Flow => "flow",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct NodeInfo {
+    pub(crate) start: Marker,
+    pub(crate) style: NodeStyle,
+    pub(crate) anchor: Option<String>,
+    /// Number of nodes in this subtree, including the node itself.
+    size: usize,
+}
+
+/// Pre-order metadata for every node of the loaded documents. Alias nodes
+/// are expanded like the tree itself: each copy repeats the metadata of the
+/// anchored node it was copied from.
+#[derive(Debug, Default)]
+pub(crate) struct SourceMap {
+    nodes: Vec<NodeInfo>,
+    roots: Vec<usize>,
+}
+
+impl SourceMap {
+    pub(crate) fn root(&self, doc: usize) -> usize {
+        self.roots.get(doc).copied().unwrap_or(self.nodes.len())
+    }
+
+    pub(crate) fn node(&self, ordinal: usize) -> Option<&NodeInfo> {
+        self.nodes.get(ordinal)
+    }
+
+    /// Position of the node following the subtree at `ordinal`.
+    pub(crate) fn next_sibling(&self, ordinal: usize) -> usize {
+        ordinal + self.nodes.get(ordinal).map_or(1, |node| node.size)
+    }
+}
+
+/// Identity of a mapping key: the node as `YamlLoader` builds it, so keys
+/// compare, scalars and collections alike, the way the loaded mapping
+/// compares them.
+type KeyId<'input> = Yaml<'input>;
+
+struct OpenNode<'input> {
+    ordinal: usize,
+    anchor_id: usize,
+    mapping: Option<MappingState<'input>>,
+    /// The collection as `YamlLoader` builds it, kept while it may be needed
+    /// as a key: when it is a key, is anchored, or is inside such a node.
+    value: Option<ValueBuilder<'input>>,
+}
+
+#[derive(Default)]
+struct MappingState<'input> {
+    key: Option<(usize, Option<KeyId<'input>>)>,
+    /// Start ordinal of each entry's value.
+    entries: Vec<usize>,
+    /// Entry index of each key seen so far.
+    index: HashMap<KeyId<'input>, usize>,
+}
+
+struct ValueBuilder<'input> {
+    node: Yaml<'input>,
+    tag: Option<Cow<'input, Tag>>,
+    /// The pending key of a mapping, `BadValue` when none.
+    key: Yaml<'input>,
+}
+
+impl<'input> ValueBuilder<'input> {
+    fn new(node: Yaml<'input>, tag: Option<Cow<'input, Tag>>) -> Self {
+        Self {
+            node,
+            tag,
+            key: Yaml::BadValue,
+        }
+    }
+
+    /// Add a child the way `YamlLoader` does.
+    fn push(&mut self, child: Yaml<'input>) {
+        match &mut self.node {
+            Yaml::Sequence(items) => items.push(child),
+            Yaml::Mapping(_) if self.key.is_badvalue() => self.key = child,
+            Yaml::Mapping(entries) => {
+                entries.insert(self.key.take(), child);
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(self) -> Yaml<'input> {
+        match self.tag {
+            Some(tag)
+                if !tag.is_yaml_core_schema() || !matches!(tag.suffix.as_str(), "seq" | "map") =>
+            {
+                self.node.into_tagged(tag)
+            }
+            _ => self.node,
+        }
+    }
+}
+
+pub(crate) struct RecordingLoader<'input> {
+    loader: YamlLoader<'input, Yaml<'input>>,
+    source: &'input str,
+    map: SourceMap,
+    open: Vec<OpenNode<'input>>,
+    anchors: HashMap<usize, (Vec<NodeInfo>, Option<KeyId<'input>>)>,
+    doc_start: usize,
+    last_end: usize,
+    chars: CharOffsets,
+}
+
+impl<'input> RecordingLoader<'input> {
+    pub(crate) fn new(source: &'input str) -> Self {
+        let mut loader = YamlLoader::default();
+        loader.early_parse(false);
+        Self {
+            loader,
+            source,
+            map: SourceMap::default(),
+            open: Vec::new(),
+            anchors: HashMap::new(),
+            doc_start: 0,
+            last_end: 0,
+            chars: CharOffsets::default(),
+        }
+    }
+
+    pub(crate) fn into_parts(self) -> (Vec<Yaml<'input>>, SourceMap) {
+        (self.loader.into_documents(), self.map)
+    }
+
+    fn push_node(&mut self, span: Span, style: NodeStyle, anchor_id: usize) -> usize {
+        let ordinal = self.map.nodes.len();
+        let anchor = (anchor_id > 0)
+            .then(|| self.anchor_name(span.start.index()))
+            .flatten();
+        self.map.nodes.push(NodeInfo {
+            start: span.start,
+            style,
+            anchor,
+            size: 1,
+        });
+        ordinal
+    }
+
+    fn is_mapping_key(&self) -> bool {
+        self.open
+            .last()
+            .and_then(|parent| parent.mapping.as_ref())
+            .is_some_and(|mapping| mapping.key.is_none())
+    }
+
+    /// Whether a node starting now needs its `KeyId`: when it is a mapping
+    /// key, is anchored, or is part of a collection that needs one.
+    fn needs_key_id(&self, anchor_id: usize) -> bool {
+        anchor_id > 0
+            || self.is_mapping_key()
+            || self
+                .open
+                .last()
+                .is_some_and(|parent| parent.value.is_some())
+    }
+
+    /// Attach a finished node to its parent, mirroring `YamlLoader`: a
+    /// repeated mapping key keeps its first position and takes the new value.
+    fn complete(&mut self, ordinal: usize, key_id: Option<KeyId<'input>>) {
+        let Some(parent) = self.open.last_mut() else {
+            return;
+        };
+        let is_key = parent
+            .mapping
+            .as_ref()
+            .is_some_and(|mapping| mapping.key.is_none());
+        let key_id = match (&mut parent.value, key_id) {
+            (Some(value), Some(key_id)) if is_key => {
+                value.push(key_id.clone());
+                Some(key_id)
+            }
+            (Some(value), key_id) => {
+                value.push(key_id.unwrap_or(Yaml::BadValue));
+                None
+            }
+            (None, key_id) => key_id,
+        };
+        let Some(mapping) = parent.mapping.as_mut() else {
+            return;
+        };
+        let Some((key_ordinal, key_id_of_entry)) = mapping.key.take() else {
+            mapping.key = Some((ordinal, key_id));
+            return;
+        };
+        let existing = match key_id_of_entry {
+            Some(id) => match mapping.index.entry(id) {
+                Entry::Occupied(entry) => Some(*entry.get()),
+                Entry::Vacant(entry) => {
+                    entry.insert(mapping.entries.len());
+                    None
+                }
+            },
+            None => None,
+        };
+        let Some(index) = existing else {
+            mapping.entries.push(ordinal);
+            return;
+        };
+        let old_start = mapping.entries[index];
+        let old_end = old_start + self.map.nodes[old_start].size;
+        let value: Vec<NodeInfo> = self
+            .map
+            .nodes
+            .drain(key_ordinal..)
+            .skip(ordinal - key_ordinal)
+            .collect();
+        let new_len = value.len();
+        self.map.nodes.splice(old_start..old_end, value);
+        for entry in &mut mapping.entries[index + 1..] {
+            *entry = *entry + new_len - (old_end - old_start);
+        }
+    }
+
+    fn push_alias(&mut self, span: Span, anchor_id: usize) {
+        let ordinal = self.map.nodes.len();
+        match self.anchors.get(&anchor_id) {
+            Some((nodes, key_id)) => {
+                let key_id = key_id.clone();
+                self.map.nodes.extend_from_slice(nodes);
+                self.complete(ordinal, key_id);
+            }
+            // An alias inside its own anchored collection loads as a bad value.
+            None => {
+                self.push_node(span, NodeStyle::Scalar(ScalarStyle::Plain), 0);
+                self.complete(ordinal, None);
+            }
+        }
+    }
+
+    /// Find the name of an anchor written between the previous event and a
+    /// node starting at char index `start`.
+    fn anchor_name(&mut self, start: usize) -> Option<String> {
+        let from = self
+            .chars
+            .byte_offset(self.source, self.last_end.min(start));
+        let to = self.chars.byte_offset(self.source, start);
+        find_properties(&self.source[from..to])
+            .anchor
+            .map(str::to_string)
+    }
+
+    fn collection_style(&mut self, span: Span) -> NodeStyle {
+        let offset = self.chars.byte_offset(self.source, span.start.index());
+        match self.source[offset..].chars().next() {
+            Some('[' | '{') => NodeStyle::Flow,
+            _ => NodeStyle::Block,
+        }
+    }
+}
+
+impl<'input> SpannedEventReceiver<'input> for RecordingLoader<'input> {
+    fn on_event(&mut self, ev: Event<'input>, span: Span) {
+        match &ev {
+            Event::DocumentStart(_) => self.doc_start = self.map.nodes.len(),
+            Event::DocumentEnd => {
+                if self.map.nodes.len() == self.doc_start {
+                    // An empty document loads as a single bad value.
+                    self.push_node(span, NodeStyle::Scalar(ScalarStyle::Plain), 0);
+                }
+                self.map.roots.push(self.doc_start);
+            }
+            Event::Scalar(value, style, anchor_id, tag) => {
+                let ordinal = self.push_node(span, NodeStyle::Scalar(*style), *anchor_id);
+                let key_id = self
+                    .needs_key_id(*anchor_id)
+                    .then(|| Yaml::Representation(value.clone(), *style, tag.clone()));
+                if *anchor_id > 0 {
+                    let node = self.map.nodes[ordinal].clone();
+                    self.anchors
+                        .insert(*anchor_id, (vec![node], key_id.clone()));
+                }
+                self.complete(ordinal, key_id);
+            }
+            Event::SequenceStart(anchor_id, tag) | Event::MappingStart(anchor_id, tag) => {
+                let style = self.collection_style(span);
+                let is_mapping = matches!(ev, Event::MappingStart(..));
+                let value = self.needs_key_id(*anchor_id).then(|| {
+                    let node = if is_mapping {
+                        Yaml::Mapping(Mapping::new())
+                    } else {
+                        Yaml::Sequence(Vec::new())
+                    };
+                    ValueBuilder::new(node, tag.clone())
+                });
+                let ordinal = self.push_node(span, style, *anchor_id);
+                self.open.push(OpenNode {
+                    ordinal,
+                    anchor_id: *anchor_id,
+                    mapping: is_mapping.then(MappingState::default),
+                    value,
+                });
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some(node) = self.open.pop() {
+                    let size = self.map.nodes.len() - node.ordinal;
+                    self.map.nodes[node.ordinal].size = size;
+                    let key_id = node.value.map(ValueBuilder::finish);
+                    if node.anchor_id > 0 {
+                        let nodes = self.map.nodes[node.ordinal..].to_vec();
+                        self.anchors.insert(node.anchor_id, (nodes, key_id.clone()));
+                    }
+                    self.complete(node.ordinal, key_id);
+                }
+            }
+            Event::Alias(anchor_id) => self.push_alias(span, *anchor_id),
+            Event::Nothing | Event::StreamStart | Event::StreamEnd => {}
+        }
+        // An implicit document start spans the properties of the root node.
+        if !matches!(ev, Event::DocumentStart(false)) {
+            self.last_end = self.last_end.max(span.end.index());
+        }
+        self.loader.on_event(ev, span);
+    }
+}
+
+/// Converts parser char indices to byte offsets, resuming from the previous
+/// lookup since events arrive in source order.
+#[derive(Default)]
+pub(crate) struct CharOffsets {
+    chars: usize,
+    bytes: usize,
+}
+
+impl CharOffsets {
+    pub(crate) fn byte_offset(&mut self, source: &str, index: usize) -> usize {
+        if index < self.chars {
+            *self = Self::default();
+        }
+        let rest = &source[self.bytes..];
+        let skipped = rest
+            .char_indices()
+            .nth(index - self.chars)
+            .map_or(rest.len(), |(offset, _)| offset);
+        self.chars = index;
+        self.bytes += skipped;
+        self.bytes
+    }
+}
+
+/// The properties of a node as written: the anchor name and the tag.
+#[derive(Default)]
+pub(crate) struct Properties<'a> {
+    pub(crate) anchor: Option<&'a str>,
+    /// The tag token, such as `!e!foo` or `!<tag:x:foo>`.
+    pub(crate) tag: Option<&'a str>,
+}
+
+/// Find the properties of a node in the text between the previous event and
+/// the node. That text holds only indicators, node properties, comments, and
+/// white space, so it is read token by token the way the scanner reads it:
+/// a tag runs to white space or a flow indicator (a verbatim tag to its
+/// closing `>`), and an anchor name likewise. The last properties belong to
+/// the node, since they directly precede it.
+pub(crate) fn find_properties(gap: &str) -> Properties<'_> {
+    let is_token_end = |ch: char| ch.is_whitespace() || matches!(ch, ',' | '[' | ']' | '{' | '}');
+    let mut properties = Properties::default();
+    let mut rest = gap;
+    let mut after_space = true;
+    while let Some(ch) = rest.chars().next() {
+        let token_len = match ch {
+            // A comment runs to the end of the line.
+            '#' if after_space => rest.find('\n').unwrap_or(rest.len()),
+            '!' => {
+                let len = if rest[1..].starts_with('<') {
+                    rest.find('>').map_or(rest.len(), |end| end + 1)
+                } else {
+                    rest[1..]
+                        .find(is_token_end)
+                        .map_or(rest.len(), |end| end + 1)
+                };
+                properties.tag = Some(&rest[..len]);
+                len
+            }
+            '&' => {
+                let name = &rest[1..];
+                let name = &name[..name.find(is_token_end).unwrap_or(name.len())];
+                properties.anchor = Some(name);
+                name.len() + 1
+            }
+            ch => ch.len_utf8(),
+        };
+        after_space = ch.is_whitespace();
+        rest = &rest[token_len..];
+    }
+    properties
+}
+
+pub(crate) fn load_with_source_map(
+    text: &str,
+    multi: bool,
+) -> Result<(Vec<Yaml<'_>>, SourceMap), ScanError> {
+    let mut parser = Parser::new_from_str(text);
+    let mut loader = RecordingLoader::new(text);
+    parser.load(&mut loader, multi)?;
+    Ok(loader.into_parts())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(text: &str) -> SourceMap {
+        load_with_source_map(text, true).unwrap().1
+    }
+
+    fn anchors(map: &SourceMap) -> Vec<Option<&str>> {
+        map.nodes
+            .iter()
+            .map(|node| node.anchor.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn records_nodes_in_pre_order() {
+        let map = load("a: [1, 'two']\nb:\n  c: |\n    text\n");
+        let styles: Vec<_> = map.nodes.iter().map(|node| node.style.as_str()).collect();
+        assert_eq!(
+            styles,
+            [
+                "block",
+                "plain",
+                "flow",
+                "plain",
+                "single_quoted",
+                "plain",
+                "block",
+                "plain",
+                "literal"
+            ]
+        );
+        assert_eq!(map.root(0), 0);
+        assert_eq!(map.next_sibling(0), 9);
+        assert_eq!(map.next_sibling(2), 5);
+        let lines: Vec<_> = map.nodes.iter().map(|node| node.start.line()).collect();
+        // Block scalars start at their content.
+        assert_eq!(lines, [1, 1, 1, 1, 1, 2, 3, 3, 4]);
+    }
+
+    #[test]
+    fn finds_anchor_names() {
+        let map = load("&root\nkey: &k !tag value # &not\nseq: &s\n  - [&x 1, *x]\n");
+        assert_eq!(
+            anchors(&map),
+            [
+                Some("root"),
+                None,
+                Some("k"),
+                None,
+                Some("s"),
+                None,
+                Some("x"),
+                Some("x")
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_anchors_after_tags_and_comments() {
+        let map = load(concat!(
+            "a: !<tag:x,&y> &b # &c\n",
+            "  v\n",
+            "d: &e\n",
+            "  # &f\n",
+            "  [&g !t , &h {i: &j !<tag:k,&l> m}]\n",
+        ));
+        assert_eq!(
+            anchors(&map),
+            [
+                None,
+                None,
+                Some("b"),
+                None,
+                Some("e"),
+                Some("g"),
+                Some("h"),
+                None,
+                Some("j")
+            ]
+        );
+    }
+
+    #[test]
+    fn aliases_repeat_the_anchored_subtree() {
+        let map = load("base: &b {x: 1}\ncopy: *b\n");
+        assert_eq!(map.nodes.len(), 9);
+        assert_eq!(map.next_sibling(2), 5);
+        assert_eq!(map.nodes[6].start, map.nodes[2].start);
+        assert_eq!(map.nodes[6].anchor.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn repeated_keys_keep_the_first_position_and_last_value() {
+        let (docs, map) = load_with_source_map("a: 1\nb: [2]\na: [3, 4]\nc: 5\n", true).unwrap();
+        let lines: Vec<_> = map.nodes.iter().map(|node| node.start.line()).collect();
+        assert_eq!(lines, [1, 1, 3, 3, 3, 2, 2, 2, 4, 4]);
+        assert_eq!(map.next_sibling(2), 5);
+        assert_eq!(docs[0].as_mapping().map(|map| map.len()), Some(3));
+    }
+
+    #[test]
+    fn repeated_collection_keys_replace_the_earlier_entry() {
+        let text = "? [a]\n: 1\n? [a]\n: [2, 3]\nz: 9\n";
+        let (docs, map) = load_with_source_map(text, true).unwrap();
+        assert_eq!(docs[0].as_mapping().map(|map| map.len()), Some(2));
+        let lines: Vec<_> = map.nodes.iter().map(|node| node.start.line()).collect();
+        assert_eq!(lines, [1, 1, 1, 4, 4, 4, 5, 5]);
+        assert_eq!(map.next_sibling(1), 3);
+        assert_eq!(map.next_sibling(3), 6);
+        assert_eq!(map.nodes[6].style.as_str(), "plain");
+
+        let text = "? &k {a: [b]}\n: 1\n? {a: [b]}\n: 2\n? *k\n: 3\n? !t {a: [b]}\n: 4\n";
+        let (docs, map) = load_with_source_map(text, true).unwrap();
+        assert_eq!(docs[0].as_mapping().map(|map| map.len()), Some(2));
+        let lines: Vec<_> = map.nodes.iter().map(|node| node.start.line()).collect();
+        assert_eq!(lines, [1, 1, 1, 1, 1, 6, 7, 7, 7, 7, 8]);
+    }
+
+    #[test]
+    fn empty_documents_get_a_placeholder() {
+        let map = load("---\n---\nvalue\n");
+        assert_eq!(map.roots, [0, 1]);
+        assert_eq!(map.nodes.len(), 2);
+    }
+
+    #[test]
+    fn handles_multibyte_text_before_anchors() {
+        let map = load("é: &ä ü\n");
+        assert_eq!(anchors(&map), [None, None, Some("ä")]);
+    }
+}
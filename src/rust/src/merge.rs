@@ -0,0 +1,367 @@
+use crate::r_to_yaml::robj_to_yaml;
+use crate::yaml_to_r::{resolve_representation, scan_yaml_documents, yaml_value_to_robj};
+use crate::{api_other, r_ext, Fallible};
+use saphyr::{Mapping, Scalar, Tag, Yaml};
+use savvy::{ListSexp, Sexp};
+use std::borrow::Cow;
+use std::mem;
+
+/// How a sequence in a later layer combines with an earlier sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SequenceMerge {
+    /// The later sequence replaces the earlier one.
+    Replace,
+    /// The later items are appended to the earlier ones.
+    Append,
+}
+
+/// What a null value in a later layer does to a mapping key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum NullMerge {
+    /// Null removes the key, as in JSON Merge Patch (RFC 7386).
+    Delete,
+    /// Null is an ordinary value and replaces the earlier one.
+    Keep,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MergeOptions {
+    pub(crate) sequences: SequenceMerge,
+    pub(crate) nulls: NullMerge,
+    /// Whether an untagged scalar stands for an R vector of length one,
+    /// which [`SequenceMerge::Append`] combines with a sequence.
+    pub(crate) vectors: bool,
+}
+
+impl MergeOptions {
+    pub(crate) fn from_args(sequences: &str, nulls: &str) -> Fallible<Self> {
+        let sequences = match sequences {
+            "replace" => SequenceMerge::Replace,
+            "append" => SequenceMerge::Append,
+            _ => return Err(api_other("`sequences` must be \"replace\" or \"append\"")),
+        };
+        let nulls = match nulls {
+            "delete" => NullMerge::Delete,
+            "keep" => NullMerge::Keep,
+            _ => return Err(api_other("`nulls` must be \"delete\" or \"keep\"")),
+        };
+        Ok(Self {
+            sequences,
+            nulls,
+            vectors: false,
+        })
+    }
+}
+
+/// Merge `patch` over `base`.
+///
+/// A mapping patch is merged key by key into `base`, recursively; if `base`
+/// is not a mapping it is treated as an empty one. Keys keep their position
+/// in `base` and new keys are appended. With [`NullMerge::Delete`], a null
+/// value in the patch removes the key. A sequence patch over a sequence is
+/// appended with [`SequenceMerge::Append`], and with `vectors`, so is a
+/// scalar patch over a sequence or a sequence patch over a scalar. Anything
+/// else replaces `base`.
+/// A tag on a merged mapping or sequence comes from the patch when it has
+/// one, and otherwise from `base`.
+pub(crate) fn merge_nodes<'a>(base: Yaml<'a>, patch: Yaml<'a>, options: MergeOptions) -> Yaml<'a> {
+    let (patch_tag, patch) = split_collection_tag(patch);
+    match patch {
+        Yaml::Mapping(entries) => {
+            let (base_tag, mut target) = match split_collection_tag(base) {
+                (tag, Yaml::Mapping(target)) => (tag, normalize_keys(target)),
+                _ => (None, Mapping::new()),
+            };
+            merge_mapping(&mut target, entries, options);
+            with_tag(patch_tag.or(base_tag), Yaml::Mapping(target))
+        }
+        Yaml::Sequence(items) if options.sequences == SequenceMerge::Append => {
+            match split_collection_tag(base) {
+                (base_tag, Yaml::Sequence(mut target)) => {
+                    target.extend(items);
+                    with_tag(patch_tag.or(base_tag), Yaml::Sequence(target))
+                }
+                (None, item) if options.vectors && is_vector_item(&item) => {
+                    let mut target = vec![item];
+                    target.extend(items);
+                    with_tag(patch_tag, Yaml::Sequence(target))
+                }
+                _ => with_tag(patch_tag, Yaml::Sequence(items)),
+            }
+        }
+        item if options.sequences == SequenceMerge::Append
+            && options.vectors
+            && patch_tag.is_none()
+            && is_vector_item(&item) =>
+        {
+            match split_collection_tag(base) {
+                (base_tag, Yaml::Sequence(mut target)) => {
+                    target.push(item);
+                    with_tag(base_tag, Yaml::Sequence(target))
+                }
+                _ => item,
+            }
+        }
+        other => with_tag(patch_tag, other),
+    }
+}
+
+/// Whether `node` is a non-null scalar, which an R vector of length one
+/// becomes.
+fn is_vector_item(node: &Yaml<'_>) -> bool {
+    matches!(node, Yaml::Value(scalar) if !matches!(scalar, Scalar::Null))
+}
+
+/// Merge the entries of `patch` into `target`; see [`merge_nodes()`].
+pub(crate) fn merge_mapping<'a>(
+    target: &mut Mapping<'a>,
+    patch: Mapping<'a>,
+    options: MergeOptions,
+) {
+    for (mut key, value) in patch {
+        resolve_representation(&mut key, true);
+        if options.nulls == NullMerge::Delete && is_null(&value) {
+            target.remove(&key);
+            continue;
+        }
+        match target.get_mut(&key) {
+            Some(slot) => {
+                let base = mem::replace(slot, Yaml::BadValue);
+                *slot = merge_nodes(base, value, options);
+            }
+            None => {
+                let value = merge_nodes(Yaml::Value(Scalar::Null), value, options);
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Resolve scalar keys so that `a`, `'a'`, and `"a"` match when merging.
+pub(crate) fn normalize_keys(map: Mapping<'_>) -> Mapping<'_> {
+    map.into_iter()
+        .map(|(mut key, value)| {
+            resolve_representation(&mut key, true);
+            (key, value)
+        })
+        .collect()
+}
+
+/// Whether `node` is a null, without resolving it in place: scalars keep
+/// their source text so handlers and `interpolate` still see it.
+fn is_null(node: &Yaml<'_>) -> bool {
+    match node {
+        Yaml::Representation(..) => {
+            let mut resolved = node.clone();
+            resolve_representation(&mut resolved, true);
+            matches!(resolved, Yaml::Value(Scalar::Null))
+        }
+        other => matches!(other, Yaml::Value(Scalar::Null)),
+    }
+}
+
+fn split_collection_tag(node: Yaml<'_>) -> (Option<Cow<'_, Tag>>, Yaml<'_>) {
+    match node {
+        Yaml::Tagged(tag, inner) if matches!(*inner, Yaml::Mapping(_) | Yaml::Sequence(_)) => {
+            (Some(tag), *inner)
+        }
+        other => (None, other),
+    }
+}
+
+fn with_tag<'a>(tag: Option<Cow<'a, Tag>>, node: Yaml<'a>) -> Yaml<'a> {
+    match tag {
+        Some(tag) => Yaml::Tagged(tag, Box::new(node)),
+        None => node,
+    }
+}
+
+/// Merge the R values, or YAML texts when `text` is set, in `values` from
+/// first to last.
+pub(crate) fn yaml_merge_impl(
+    values: ListSexp,
+    text: bool,
+    options: MergeOptions,
+    simplify: bool,
+) -> Fallible<Sexp> {
+    let layers: Vec<Sexp> = values.values_iter().collect();
+    let texts = if text {
+        layers
+            .iter()
+            .enumerate()
+            .map(|(index, layer)| layer_text(layer, index))
+            .collect::<Fallible<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+
+    let options = MergeOptions {
+        vectors: !text,
+        ..options
+    };
+    let mut merged: Option<Yaml> = None;
+    for (index, layer) in layers.iter().enumerate() {
+        let node = match texts.get(index) {
+            Some(text) => scan_yaml_documents(text, false)
+                .map_err(|err| {
+                    api_other(format!(
+                        "YAML parse error in `{}`: {err}",
+                        layer_name(index)
+                    ))
+                })?
+                .into_iter()
+                .next()
+                .unwrap_or(Yaml::Value(Scalar::Null)),
+            None => robj_to_yaml(layer)?,
+        };
+        merged = Some(match merged {
+            Some(base) => merge_nodes(base, node, options),
+            None => node,
+        });
+    }
+    match merged {
+        Some(mut node) => yaml_value_to_robj(&mut node, simplify),
+        None => Ok(r_ext::null()),
+    }
+}
+
+/// The name of a layer in `...`, for error messages.
+fn layer_name(index: usize) -> String {
+    format!("..{}", index + 1)
+}
+
+fn layer_text(layer: &Sexp, index: usize) -> Fallible<String> {
+    let name = layer_name(index);
+    let lines = r_ext::string_sexp(layer)
+        .ok_or_else(|| api_other(format!("`{name}` must be a character vector of YAML text")))?;
+    r_ext::text_arg(&lines, &name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emitter::YamlEmitter;
+    use saphyr::LoadableYamlNode;
+
+    fn load(text: &str) -> Yaml<'_> {
+        scan_yaml_documents(text, false).unwrap().remove(0)
+    }
+
+    fn merged(base: &str, patch: &str, sequences: SequenceMerge, nulls: NullMerge) -> String {
+        let options = MergeOptions {
+            sequences,
+            nulls,
+            vectors: false,
+        };
+        normalized(&merge_nodes(load(base), load(patch), options))
+    }
+
+    fn normalized(node: &Yaml<'_>) -> String {
+        let mut out = String::new();
+        YamlEmitter::new(&mut out).dump(node).unwrap();
+        // Round-trip through the loader so the comparison ignores styles.
+        let reloaded = Yaml::load_from_str(&out).unwrap();
+        let mut normalized = String::new();
+        YamlEmitter::new(&mut normalized)
+            .dump(&reloaded[0])
+            .unwrap();
+        normalized
+    }
+
+    fn expected(text: &str) -> String {
+        let docs = Yaml::load_from_str(text).unwrap();
+        let mut out = String::new();
+        YamlEmitter::new(&mut out).dump(&docs[0]).unwrap();
+        out
+    }
+
+    #[test]
+    fn follows_json_merge_patch() {
+        use NullMerge::Delete;
+        use SequenceMerge::Replace;
+        // Examples from RFC 7386, appendix A.
+        let cases = [
+            ("{a: b}", "{a: c}", "{a: c}"),
+            ("{a: b}", "{b: c}", "{a: b, b: c}"),
+            ("{a: b}", "{a: null}", "{}"),
+            ("{a: b, b: c}", "{a: null}", "{b: c}"),
+            ("{a: [b]}", "{a: c}", "{a: c}"),
+            ("{a: c}", "{a: [b]}", "{a: [b]}"),
+            ("{a: {b: c}}", "{a: {b: d, c: null}}", "{a: {b: d}}"),
+            ("{a: [{b: c}]}", "{a: [1]}", "{a: [1]}"),
+            ("[a, b]", "[c, d]", "[c, d]"),
+            ("{a: b}", "[c]", "[c]"),
+            ("{a: foo}", "null", "null"),
+            ("{a: foo}", "bar", "bar"),
+            ("{e: null}", "{a: 1}", "{e: null, a: 1}"),
+            ("[1, 2]", "{a: b, c: null}", "{a: b}"),
+            ("{}", "{a: {bb: {ccc: null}}}", "{a: {bb: {}}}"),
+        ];
+        for (base, patch, result) in cases {
+            assert_eq!(
+                merged(base, patch, Replace, Delete),
+                expected(result),
+                "{base} + {patch}"
+            );
+        }
+    }
+
+    #[test]
+    fn supports_appending_keeping_nulls_and_tags() {
+        assert_eq!(
+            merged(
+                "{a: [1], b: {c: 1}}",
+                "{a: [2], b: {c: null}, d: [3]}",
+                SequenceMerge::Append,
+                NullMerge::Keep
+            ),
+            expected("{a: [1, 2], b: {c: null}, d: [3]}")
+        );
+        assert_eq!(
+            merged(
+                "!base {'a': 1, 1: x, !k t: y}",
+                "{\"a\": 2, 1: z, !k t: w}",
+                SequenceMerge::Replace,
+                NullMerge::Delete
+            ),
+            expected("!base {a: 2, 1: z, !k t: w}")
+        );
+        assert_eq!(
+            merged(
+                "!set [1]",
+                "!list [2]",
+                SequenceMerge::Append,
+                NullMerge::Delete
+            ),
+            expected("!list [1, 2]")
+        );
+    }
+
+    #[test]
+    fn appends_scalars_from_r_vectors() {
+        let options = MergeOptions {
+            sequences: SequenceMerge::Append,
+            nulls: NullMerge::Delete,
+            vectors: true,
+        };
+        // R values convert to resolved scalars.
+        let value = |text| Yaml::load_from_str(text).unwrap().remove(0);
+        assert_eq!(
+            normalized(&merge_nodes(
+                value("{a: [1, 2], b: 1, c: x, d: [1], e: !t [1]}"),
+                value("{a: 3, b: [2], c: y, d: ~, e: 2}"),
+                options
+            )),
+            expected("{a: [1, 2, 3], b: [1, 2], c: y, e: !t [1, 2]}")
+        );
+        assert_eq!(
+            merged(
+                "{a: [1]}",
+                "{a: 2}",
+                SequenceMerge::Append,
+                NullMerge::Delete
+            ),
+            expected("{a: 2}")
+        );
+    }
+}
@@ -0,0 +1,579 @@
+use crate::r_ext::{self, text_arg, ListElement};
+use crate::r_to_yaml::{emit_yaml_documents, robj_to_yaml, yaml_body};
+use crate::tree::{load_first, resolve_tree, yaml_equal};
+use crate::{api_other, Fallible, R_STRING_MAX_BYTES};
+use saphyr::{Mapping, Scalar, Yaml};
+use savvy::{NotAvailableValue, Sexp, StringSexp};
+use std::fmt;
+
+/// Why a patch operation failed; each kind is a distinct R condition class.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PatchErrorKind {
+    /// The operation is malformed: unknown `op`, or a missing member.
+    InvalidOperation,
+    /// A pointer is malformed or does not name an existing location.
+    InvalidPointer,
+    /// A `test` operation found a different value.
+    TestFailed,
+}
+
+impl PatchErrorKind {
+    fn class(self) -> &'static str {
+        match self {
+            Self::InvalidOperation => "yaml12_patch_invalid_op",
+            Self::InvalidPointer => "yaml12_patch_invalid_pointer",
+            Self::TestFailed => "yaml12_patch_test_failed",
+        }
+    }
+}
+
+/// A failed operation, reported to R as a classed condition.
+#[derive(Debug)]
+pub(crate) struct PatchError {
+    pub(crate) kind: PatchErrorKind,
+    /// 1-based position of the operation in the patch.
+    pub(crate) index: usize,
+    pub(crate) op: Option<String>,
+    pub(crate) message: String,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.op {
+            Some(op) => write!(
+                f,
+                "Patch operation {} (`{op}`) failed: {}",
+                self.index, self.message
+            ),
+            None => write!(f, "Patch operation {} failed: {}", self.index, self.message),
+        }
+    }
+}
+
+/// The failure of one step, before the operation it belongs to is known.
+struct Failure {
+    kind: PatchErrorKind,
+    message: String,
+}
+
+fn invalid_op(message: impl Into<String>) -> Failure {
+    Failure {
+        kind: PatchErrorKind::InvalidOperation,
+        message: message.into(),
+    }
+}
+
+fn invalid_pointer(message: impl Into<String>) -> Failure {
+    Failure {
+        kind: PatchErrorKind::InvalidPointer,
+        message: message.into(),
+    }
+}
+
+/// A JSON Pointer (RFC 6901), split into unescaped reference tokens.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Pointer {
+    source: String,
+    tokens: Vec<String>,
+}
+
+impl Pointer {
+    fn parse(source: &str) -> Result<Self, Failure> {
+        if source.is_empty() {
+            return Ok(Self {
+                source: String::new(),
+                tokens: Vec::new(),
+            });
+        }
+        let Some(rest) = source.strip_prefix('/') else {
+            return Err(invalid_pointer(format!(
+                "pointer `{source}` must be empty or start with `/`"
+            )));
+        };
+        let tokens = rest
+            .split('/')
+            .map(|token| unescape_token(token, source))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            source: source.to_string(),
+            tokens,
+        })
+    }
+
+    fn is_proper_prefix_of(&self, other: &Pointer) -> bool {
+        self.tokens.len() < other.tokens.len() && other.tokens.starts_with(&self.tokens)
+    }
+}
+
+fn unescape_token(token: &str, source: &str) -> Result<String, Failure> {
+    let mut out = String::with_capacity(token.len());
+    let mut chars = token.chars();
+    while let Some(c) = chars.next() {
+        if c != '~' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('0') => out.push('~'),
+            Some('1') => out.push('/'),
+            _ => {
+                return Err(invalid_pointer(format!(
+                    "pointer `{source}` has `~` not followed by `0` or `1`"
+                )))
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// One RFC 6902 operation.
+enum Operation<'a> {
+    Add(Pointer, Yaml<'a>),
+    Remove(Pointer),
+    Replace(Pointer, Yaml<'a>),
+    Move { from: Pointer, path: Pointer },
+    Copy { from: Pointer, path: Pointer },
+    Test(Pointer, Yaml<'a>),
+}
+
+impl<'a> Operation<'a> {
+    fn from_node(node: Yaml<'a>) -> Result<Self, Failure> {
+        let Yaml::Mapping(mut members) = untagged(node) else {
+            return Err(invalid_op("operation must be a mapping"));
+        };
+        let op = string_member(&members, "op")?;
+        let path = Pointer::parse(&string_member(&members, "path")?)?;
+        let from =
+            || -> Result<Pointer, Failure> { Pointer::parse(&string_member(&members, "from")?) };
+        Ok(match op.as_str() {
+            "add" => Operation::Add(path, take_value(&mut members)?),
+            "remove" => Operation::Remove(path),
+            "replace" => Operation::Replace(path, take_value(&mut members)?),
+            "move" => Operation::Move { from: from()?, path },
+            "copy" => Operation::Copy { from: from()?, path },
+            "test" => Operation::Test(path, take_value(&mut members)?),
+            other => {
+                return Err(invalid_op(format!(
+                    "`op` must be one of \"add\", \"remove\", \"replace\", \"move\", \"copy\", or \"test\", not \"{other}\""
+                )))
+            }
+        })
+    }
+
+    fn apply(self, doc: &mut Yaml<'a>) -> Result<(), Failure> {
+        match self {
+            Operation::Add(path, value) => add(doc, &path, value),
+            Operation::Remove(path) => remove(doc, &path).map(drop),
+            Operation::Replace(path, value) => {
+                *get_mut(doc, &path)? = value;
+                Ok(())
+            }
+            Operation::Move { from, path } => {
+                if from == path {
+                    return get_mut(doc, &from).map(drop);
+                }
+                if from.is_proper_prefix_of(&path) {
+                    return Err(invalid_pointer(format!(
+                        "cannot move `{}` into its own child `{}`",
+                        from.source, path.source
+                    )));
+                }
+                let value = remove(doc, &from)?;
+                add(doc, &path, value)
+            }
+            Operation::Copy { from, path } => {
+                let value = get_mut(doc, &from)?.clone();
+                add(doc, &path, value)
+            }
+            Operation::Test(path, value) => {
+                if yaml_equal(get_mut(doc, &path)?, &value) {
+                    Ok(())
+                } else {
+                    Err(Failure {
+                        kind: PatchErrorKind::TestFailed,
+                        message: format!("value at `{}` does not match", path.source),
+                    })
+                }
+            }
+        }
+    }
+}
+
+fn string_member(members: &Mapping<'_>, name: &str) -> Result<String, Failure> {
+    match members.get(&Yaml::Value(Scalar::String(name.into()))) {
+        Some(Yaml::Value(Scalar::String(value))) => Ok(value.to_string()),
+        Some(_) => Err(invalid_op(format!("`{name}` must be a string"))),
+        None => Err(invalid_op(format!("operation has no `{name}`"))),
+    }
+}
+
+fn take_value<'a>(members: &mut Mapping<'a>) -> Result<Yaml<'a>, Failure> {
+    members
+        .remove(&Yaml::Value(Scalar::String("value".into())))
+        .ok_or_else(|| invalid_op("operation has no `value`"))
+}
+
+/// Strip a tag from a collection so the pointer can look inside it.
+fn untagged(node: Yaml<'_>) -> Yaml<'_> {
+    match node {
+        Yaml::Tagged(_, inner) if matches!(*inner, Yaml::Mapping(_) | Yaml::Sequence(_)) => *inner,
+        other => other,
+    }
+}
+
+fn collection_mut<'n, 'a>(node: &'n mut Yaml<'a>) -> &'n mut Yaml<'a> {
+    if matches!(node, Yaml::Tagged(_, inner) if matches!(**inner, Yaml::Mapping(_) | Yaml::Sequence(_)))
+    {
+        let Yaml::Tagged(_, inner) = node else {
+            unreachable!()
+        };
+        return inner;
+    }
+    node
+}
+
+/// Whether mapping key `key` is referenced by pointer token `token`. String
+/// keys match exactly; integer and boolean keys match their canonical text.
+fn key_matches(key: &Yaml<'_>, token: &str) -> bool {
+    match key {
+        Yaml::Value(Scalar::String(key)) => key == token,
+        Yaml::Value(Scalar::Integer(key)) => key.to_string() == token,
+        Yaml::Value(Scalar::Boolean(key)) => key.to_string() == token,
+        Yaml::Tagged(_, inner) => key_matches(inner, token),
+        _ => false,
+    }
+}
+
+fn find_key<'m, 'a>(map: &'m Mapping<'a>, token: &str) -> Option<&'m Yaml<'a>> {
+    map.keys().find(|key| key_matches(key, token))
+}
+
+/// Parse a sequence index token; leading zeros are not allowed.
+fn index_token(token: &str, pointer: &Pointer) -> Result<usize, Failure> {
+    let valid = !token.is_empty()
+        && token.bytes().all(|b| b.is_ascii_digit())
+        && (token == "0" || !token.starts_with('0'));
+    valid.then(|| token.parse().ok()).flatten().ok_or_else(|| {
+        invalid_pointer(format!(
+            "`{token}` in `{}` is not a sequence index",
+            pointer.source
+        ))
+    })
+}
+
+fn missing(pointer: &Pointer) -> Failure {
+    invalid_pointer(format!("path `{}` does not exist", pointer.source))
+}
+
+fn child_mut<'n, 'a>(
+    node: &'n mut Yaml<'a>,
+    token: &str,
+    pointer: &Pointer,
+) -> Result<&'n mut Yaml<'a>, Failure> {
+    match collection_mut(node) {
+        Yaml::Mapping(map) => {
+            let key = find_key(map, token)
+                .cloned()
+                .ok_or_else(|| missing(pointer))?;
+            Ok(map.get_mut(&key).expect("key was just found"))
+        }
+        Yaml::Sequence(items) => {
+            let index = index_token(token, pointer)?;
+            items.get_mut(index).ok_or_else(|| missing(pointer))
+        }
+        _ => Err(missing(pointer)),
+    }
+}
+
+fn get_mut<'n, 'a>(doc: &'n mut Yaml<'a>, pointer: &Pointer) -> Result<&'n mut Yaml<'a>, Failure> {
+    pointer
+        .tokens
+        .iter()
+        .try_fold(doc, |node, token| child_mut(node, token, pointer))
+}
+
+/// The collection that holds the location `pointer` names, and the last token.
+fn parent_mut<'n, 'a, 'p>(
+    doc: &'n mut Yaml<'a>,
+    pointer: &'p Pointer,
+) -> Result<(&'n mut Yaml<'a>, &'p str), Failure> {
+    let (last, parents) = pointer.tokens.split_last().expect("the root has no parent");
+    let parent = parents
+        .iter()
+        .try_fold(doc, |node, token| child_mut(node, token, pointer))?;
+    Ok((collection_mut(parent), last))
+}
+
+fn add<'a>(doc: &mut Yaml<'a>, pointer: &Pointer, value: Yaml<'a>) -> Result<(), Failure> {
+    if pointer.tokens.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+    match parent_mut(doc, pointer)? {
+        (Yaml::Mapping(map), token) => {
+            match find_key(map, token).cloned() {
+                Some(key) => map.insert(key, value),
+                None => map.insert(Yaml::Value(Scalar::String(token.to_string().into())), value),
+            };
+            Ok(())
+        }
+        (Yaml::Sequence(items), "-") => {
+            items.push(value);
+            Ok(())
+        }
+        (Yaml::Sequence(items), token) => {
+            let index = index_token(token, pointer)?;
+            if index > items.len() {
+                return Err(invalid_pointer(format!(
+                    "index {index} in `{}` is past the end of the sequence",
+                    pointer.source
+                )));
+            }
+            items.insert(index, value);
+            Ok(())
+        }
+        _ => Err(missing(pointer)),
+    }
+}
+
+fn remove<'a>(doc: &mut Yaml<'a>, pointer: &Pointer) -> Result<Yaml<'a>, Failure> {
+    if pointer.tokens.is_empty() {
+        return Err(invalid_pointer("cannot remove the document root"));
+    }
+    match parent_mut(doc, pointer)? {
+        (Yaml::Mapping(map), token) => {
+            let key = find_key(map, token)
+                .cloned()
+                .ok_or_else(|| missing(pointer))?;
+            Ok(map.remove(&key).expect("key was just found"))
+        }
+        (Yaml::Sequence(items), token) => {
+            let index = index_token(token, pointer)?;
+            if index >= items.len() {
+                return Err(missing(pointer));
+            }
+            Ok(items.remove(index))
+        }
+        _ => Err(missing(pointer)),
+    }
+}
+
+/// Apply `ops`, in order, to `doc`. The document is left partially patched
+/// when an operation fails.
+pub(crate) fn apply_patch<'a>(doc: &mut Yaml<'a>, ops: Yaml<'a>) -> Result<(), PatchError> {
+    let ops = match untagged(ops) {
+        Yaml::Sequence(ops) => ops,
+        Yaml::Mapping(op) => vec![Yaml::Mapping(op)],
+        _ => {
+            return Err(PatchError {
+                kind: PatchErrorKind::InvalidOperation,
+                index: 1,
+                op: None,
+                message: "`ops` must be a sequence of operations".to_string(),
+            })
+        }
+    };
+    for (index, node) in ops.into_iter().enumerate() {
+        let op = match &node {
+            Yaml::Mapping(members) => string_member(members, "op").ok(),
+            _ => None,
+        };
+        Operation::from_node(node)
+            .and_then(|operation| operation.apply(doc))
+            .map_err(|failure| PatchError {
+                kind: failure.kind,
+                index: index + 1,
+                op,
+                message: failure.message,
+            })?;
+    }
+    Ok(())
+}
+
+/// Apply the operations in `ops`, an R list or YAML text, to the first
+/// document of the YAML text `doc`, and return the patched YAML. A failed
+/// operation is returned as a list describing the error, which the R
+/// wrapper signals as a classed condition.
+pub(crate) fn yaml_patch_impl(doc: StringSexp, ops: Sexp) -> Fallible<Sexp> {
+    let doc_text = text_arg(&doc, "doc")?;
+    let ops_text = match r_ext::string_sexp(&ops) {
+        Some(text) => Some(text_arg(&text, "ops")?),
+        None => None,
+    };
+    let mut doc = load_first(&doc_text, "doc")?;
+    let ops = match &ops_text {
+        Some(text) => load_first(text, "ops")?,
+        None => {
+            let mut ops = robj_to_yaml(&ops)?;
+            resolve_tree(&mut ops);
+            ops
+        }
+    };
+
+    if let Err(err) = apply_patch(&mut doc, ops) {
+        let message = err.to_string();
+        let op = err.op.as_deref().unwrap_or(<&str>::na());
+        let index = i32::try_from(err.index)
+            .map_err(|_| api_other("Patch operation index exceeds R's integer range"))?;
+        let elements = [
+            ListElement::string(&message)?,
+            ListElement::string(err.kind.class())?,
+            ListElement::integer(index),
+            ListElement::string(op)?,
+        ];
+        let names = [
+            r_ext::string_data("message")?,
+            r_ext::string_data("class")?,
+            r_ext::string_data("index")?,
+            r_ext::string_data("op")?,
+        ];
+        return r_ext::materialize_list(None, &elements, Some(&names));
+    }
+
+    let yaml = emit_yaml_documents(&[doc], false, None)?;
+    let body = yaml_body(&yaml, false);
+    if body.len() > R_STRING_MAX_BYTES {
+        return Err(api_other(
+            "Patched YAML exceeds R's 2^31-1 byte string limit",
+        ));
+    }
+    r_ext::string_scalar(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patched(doc: &str, ops: &str) -> Result<String, PatchError> {
+        let mut doc = load_first(doc, "doc").unwrap();
+        apply_patch(&mut doc, load_first(ops, "ops").unwrap())?;
+        let yaml = emit_yaml_documents(&[doc], false, None).unwrap();
+        Ok(yaml_body(&yaml, false).to_string())
+    }
+
+    #[test]
+    fn applies_rfc_6902_operations() {
+        let cases = [
+            (
+                "{foo: bar}",
+                "[{op: add, path: /baz, value: qux}]",
+                "foo: bar\nbaz: qux",
+            ),
+            (
+                "{foo: [bar, baz]}",
+                "[{op: add, path: /foo/1, value: qux}]",
+                "foo:\n  - bar\n  - qux\n  - baz",
+            ),
+            (
+                "{foo: [bar]}",
+                "[{op: add, path: /foo/-, value: [abc]}]",
+                "foo:\n  - bar\n  - - abc",
+            ),
+            (
+                "{baz: qux, foo: bar}",
+                "[{op: remove, path: /baz}]",
+                "foo: bar",
+            ),
+            (
+                "{baz: qux, foo: bar}",
+                "[{op: replace, path: /baz, value: boo}]",
+                "baz: boo\nfoo: bar",
+            ),
+            (
+                "{foo: {bar: baz, waldo: fred}, qux: {corge: grault}}",
+                "[{op: move, from: /foo/waldo, path: /qux/thud}]",
+                "foo:\n  bar: baz\nqux:\n  corge: grault\n  thud: fred",
+            ),
+            (
+                "{foo: [all, grass, cows, eat]}",
+                "[{op: move, from: /foo/1, path: /foo/3}]",
+                "foo:\n  - all\n  - cows\n  - eat\n  - grass",
+            ),
+            (
+                "{a: {b: 1}}",
+                "[{op: copy, from: /a, path: /c}]",
+                "a:\n  b: 1\nc:\n  b: 1",
+            ),
+            (
+                "{'a/b': 1, 'm~n': 2}",
+                "[{op: replace, path: /a~1b, value: 3}, {op: remove, path: /m~0n}]",
+                "a/b: 3",
+            ),
+            ("{1: one}", "{op: replace, path: /1, value: uno}", "1: uno"),
+            ("x", "[{op: replace, path: '', value: {a: 1}}]", "a: 1"),
+            (
+                "!cfg {a: 1}",
+                "[{op: add, path: /b, value: 2}]",
+                "!cfg \na: 1\nb: 2",
+            ),
+        ];
+        for (doc, ops, expected) in cases {
+            assert_eq!(patched(doc, ops).unwrap(), expected, "{doc} + {ops}");
+        }
+    }
+
+    #[test]
+    fn tests_values_semantically() {
+        let doc = "{a: {x: 1, y: [1.0, 'two']}, b: ~}";
+        assert!(patched(doc, "[{op: test, path: /a, value: {y: [1, two], x: 1.0}}]").is_ok());
+        assert!(patched(doc, "[{op: test, path: /b, value: null}]").is_ok());
+        assert!(patched("{a: {1: x}}", "[{op: test, path: /a, value: {1.0: x}}]").is_ok());
+        let tagged = "%TAG !e! tag:x:\n--- {a: !e!foo {k: !e!bar v}}";
+        let ops = "[{op: test, path: /a, value: !<tag:x:foo> {k: !<tag:x:bar> v}}]";
+        assert!(patched(tagged, ops).is_ok());
+        let err = patched(
+            doc,
+            "[{op: test, path: /a/x, value: 1}, {op: test, path: /a/x, value: '1'}]",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, PatchErrorKind::TestFailed);
+        assert_eq!(err.index, 2);
+        assert_eq!(
+            err.to_string(),
+            "Patch operation 2 (`test`) failed: value at `/a/x` does not match"
+        );
+    }
+
+    #[test]
+    fn reports_the_failing_operation() {
+        let failure = |ops: &str| {
+            let err = patched("{foo: [1, 2]}", ops).unwrap_err();
+            (err.kind, err.index)
+        };
+        use PatchErrorKind::*;
+        assert_eq!(failure("[{op: remove, path: /bar}]"), (InvalidPointer, 1));
+        assert_eq!(
+            failure("[{op: add, path: /foo/01, value: 0}]"),
+            (InvalidPointer, 1)
+        );
+        assert_eq!(
+            failure("[{op: add, path: /foo/3, value: 0}]"),
+            (InvalidPointer, 1)
+        );
+        assert_eq!(
+            failure("[{op: add, path: /a/b, value: 0}]"),
+            (InvalidPointer, 1)
+        );
+        assert_eq!(
+            failure("[{op: add, path: foo, value: 0}]"),
+            (InvalidPointer, 1)
+        );
+        assert_eq!(
+            failure("[{op: remove, path: /foo/~2}]"),
+            (InvalidPointer, 1)
+        );
+        assert_eq!(
+            failure("[{op: move, from: /foo, path: /foo/0}]"),
+            (InvalidPointer, 1)
+        );
+        assert_eq!(failure("[{op: remove, path: ''}]"), (InvalidPointer, 1));
+        assert_eq!(
+            failure("[{op: remove, path: /foo/0}, {op: frob, path: /foo}]"),
+            (InvalidOperation, 2)
+        );
+        assert_eq!(failure("[{op: add, path: /x}]"), (InvalidOperation, 1));
+        assert_eq!(failure("[{op: copy, path: /x}]"), (InvalidOperation, 1));
+        assert_eq!(failure("[{path: /x}]"), (InvalidOperation, 1));
+        assert_eq!(failure("[1]"), (InvalidOperation, 1));
+    }
+}
@@ -0,0 +1,411 @@
+use crate::r_ext;
+use crate::yaml_to_r::{
+    detached_list_to_robj, detached_to_robj, scan_yaml_documents, ParseOptions,
+};
+use crate::{api_other, Fallible};
+use saphyr::{Scalar, Yaml};
+use savvy::Sexp;
+use std::borrow::Cow;
+
+/// One step of a query, applied to every node selected so far.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// A mapping key, or a sequence index written as a name (JSON Pointer
+    /// tokens and `.0`).
+    Name(String),
+    /// A sequence index from `[n]`; negative indices count from the end.
+    Index(i64),
+    /// Every value of a mapping or item of a sequence.
+    Wildcard,
+    /// The node itself and all of its descendants, for `..`.
+    Descendants,
+}
+
+/// A parsed query: a JSON Pointer, a JSONPath subset, or a dotted path.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Query {
+    pub(crate) steps: Vec<Step>,
+}
+
+impl Query {
+    /// Parse `source` as a JSON Pointer when it is empty or starts with `/`,
+    /// as JSONPath when it starts with `$`, and otherwise as a JSONPath with
+    /// the leading `$.` left out, such as `metadata.version`.
+    pub(crate) fn parse(source: &str) -> Fallible<Self> {
+        if source.is_empty() || source.starts_with('/') {
+            return Self::parse_pointer(source);
+        }
+        let path = source.strip_prefix('$').unwrap_or(source);
+        let mut steps = Vec::new();
+        let mut rest = if source.starts_with('$') {
+            path
+        } else {
+            // `a.b` reads as `$.a.b`; `[0].a` as `$[0].a`.
+            steps.extend(parse_dotted_name(path, source)?);
+            &path[dotted_len(path)..]
+        };
+        while !rest.is_empty() {
+            let (parsed, len) = parse_step(rest, source)?;
+            steps.extend(parsed);
+            rest = &rest[len..];
+        }
+        Ok(Self { steps })
+    }
+
+    fn parse_pointer(source: &str) -> Fallible<Self> {
+        let steps = match source.strip_prefix('/') {
+            None => Vec::new(),
+            Some(rest) => rest
+                .split('/')
+                .map(|token| {
+                    if token.contains('~')
+                        && token
+                            .split('~')
+                            .skip(1)
+                            .any(|after| !after.starts_with(['0', '1']))
+                    {
+                        return Err(invalid_query(source, "`~` must be followed by `0` or `1`"));
+                    }
+                    Ok(Step::Name(token.replace("~1", "/").replace("~0", "~")))
+                })
+                .collect::<Fallible<_>>()?,
+        };
+        Ok(Self { steps })
+    }
+
+    /// Whether the query can select at most one node.
+    pub(crate) fn is_definite(&self) -> bool {
+        !self
+            .steps
+            .iter()
+            .any(|step| matches!(step, Step::Wildcard | Step::Descendants))
+    }
+}
+
+fn invalid_query(source: &str, reason: &str) -> savvy::Error {
+    api_other(format!("Invalid query `{source}`: {reason}"))
+}
+
+/// Length of the leading name in a dotted path, up to `.` or `[`.
+fn dotted_len(path: &str) -> usize {
+    path.find(['.', '[']).unwrap_or(path.len())
+}
+
+fn parse_dotted_name(path: &str, source: &str) -> Fallible<Option<Step>> {
+    let name = &path[..dotted_len(path)];
+    match name {
+        "" if path.is_empty() => Err(invalid_query(source, "empty query")),
+        "" => Ok(None),
+        "*" => Ok(Some(Step::Wildcard)),
+        name => Ok(Some(Step::Name(name.to_string()))),
+    }
+}
+
+/// Parse one step at the start of `rest`, returning the steps it adds and
+/// the number of bytes it spans.
+fn parse_step(rest: &str, source: &str) -> Fallible<(Vec<Step>, usize)> {
+    if let Some(after) = rest.strip_prefix("..") {
+        let (mut steps, len) = if after.starts_with('[') {
+            parse_step(after, source)?
+        } else {
+            let len = dotted_len(after);
+            if len == 0 {
+                return Err(invalid_query(
+                    source,
+                    "`..` must be followed by a name, `*`, or `[`",
+                ));
+            }
+            (parse_dotted_name(after, source)?.into_iter().collect(), len)
+        };
+        steps.insert(0, Step::Descendants);
+        return Ok((steps, len + 2));
+    }
+    if let Some(after) = rest.strip_prefix('.') {
+        let len = dotted_len(after);
+        if len == 0 {
+            return Err(invalid_query(
+                source,
+                "`.` must be followed by a name or `*`",
+            ));
+        }
+        let step = parse_dotted_name(after, source)?;
+        return Ok((step.into_iter().collect(), len + 1));
+    }
+    if let Some(after) = rest.strip_prefix('[') {
+        for quote in ['\'', '"'] {
+            if let Some(quoted) = after.strip_prefix(quote) {
+                let end = quoted
+                    .find(quote)
+                    .ok_or_else(|| invalid_query(source, "unterminated quoted name"))?;
+                if !quoted[end + 1..].starts_with(']') {
+                    return Err(invalid_query(source, "expected `]` after quoted name"));
+                }
+                let name = quoted[..end].to_string();
+                return Ok((vec![Step::Name(name)], end + 4));
+            }
+        }
+        let end = after
+            .find(']')
+            .ok_or_else(|| invalid_query(source, "unterminated `[`"))?;
+        let inner = after[..end].trim();
+        let step = if inner == "*" {
+            Step::Wildcard
+        } else {
+            Step::Index(inner.parse().map_err(|_| {
+                invalid_query(source, "`[...]` must hold an index, `*`, or a quoted name")
+            })?)
+        };
+        return Ok((vec![step], end + 2));
+    }
+    Err(invalid_query(source, "expected `.`, `..`, or `[`"))
+}
+
+/// A selected node and the JSON Pointer that leads to it.
+struct Match<'n, 'a> {
+    pointer: String,
+    node: &'n Yaml<'a>,
+}
+
+/// Look through a tag on a collection.
+pub(crate) fn untagged<'n, 'a>(node: &'n Yaml<'a>) -> &'n Yaml<'a> {
+    match node {
+        Yaml::Tagged(_, inner) if matches!(**inner, Yaml::Mapping(_) | Yaml::Sequence(_)) => inner,
+        other => other,
+    }
+}
+
+/// The text a mapping key is referenced by, without resolving the key.
+pub(crate) fn key_text<'n>(key: &'n Yaml<'_>) -> Option<Cow<'n, str>> {
+    match key {
+        Yaml::Representation(value, _, _) => Some(Cow::Borrowed(value.as_ref())),
+        Yaml::Value(Scalar::String(value)) => Some(Cow::Borrowed(value.as_ref())),
+        Yaml::Value(Scalar::Integer(value)) => Some(Cow::Owned(value.to_string())),
+        Yaml::Value(Scalar::Boolean(value)) => Some(Cow::Owned(value.to_string())),
+        Yaml::Tagged(_, inner) => key_text(inner),
+        _ => None,
+    }
+}
+
+fn child_pointer(parent: &str, token: &str) -> String {
+    format!("{parent}/{}", token.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Push the children of `m` onto `out`: mapping values with a text key, and
+/// sequence items.
+fn children<'n, 'a>(m: &Match<'n, 'a>, out: &mut Vec<Match<'n, 'a>>) {
+    match untagged(m.node) {
+        Yaml::Mapping(map) => {
+            for (key, value) in map {
+                if let Some(text) = key_text(key) {
+                    out.push(Match {
+                        pointer: child_pointer(&m.pointer, &text),
+                        node: value,
+                    });
+                }
+            }
+        }
+        Yaml::Sequence(items) => {
+            for (index, item) in items.iter().enumerate() {
+                out.push(Match {
+                    pointer: format!("{}/{index}", m.pointer),
+                    node: item,
+                });
+            }
+        }
+        _ => {}
+    }
+}
+
+fn descendants<'n, 'a>(m: Match<'n, 'a>, out: &mut Vec<Match<'n, 'a>>) {
+    let mut found = Vec::new();
+    children(&m, &mut found);
+    out.push(m);
+    for child in found {
+        descendants(child, out);
+    }
+}
+
+fn apply_step<'n, 'a>(step: &Step, selected: Vec<Match<'n, 'a>>) -> Vec<Match<'n, 'a>> {
+    let mut out = Vec::new();
+    for m in selected {
+        match step {
+            Step::Descendants => descendants(m, &mut out),
+            Step::Wildcard => children(&m, &mut out),
+            Step::Name(name) => match untagged(m.node) {
+                Yaml::Mapping(map) => {
+                    if let Some((_, value)) = map
+                        .iter()
+                        .find(|(key, _)| key_text(key).is_some_and(|text| text == *name))
+                    {
+                        out.push(Match {
+                            pointer: child_pointer(&m.pointer, name),
+                            node: value,
+                        });
+                    }
+                }
+                Yaml::Sequence(items) => {
+                    let index = name
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|_| name == "0" || !name.starts_with('0'));
+                    if let Some(item) = index.and_then(|index| items.get(index)) {
+                        out.push(Match {
+                            pointer: child_pointer(&m.pointer, name),
+                            node: item,
+                        });
+                    }
+                }
+                _ => {}
+            },
+            Step::Index(index) => {
+                if let Yaml::Sequence(items) = untagged(m.node) {
+                    let len = items.len() as i64;
+                    let index = if *index < 0 { len + index } else { *index };
+                    if (0..len).contains(&index) {
+                        out.push(Match {
+                            pointer: format!("{}/{index}", m.pointer),
+                            node: &items[index as usize],
+                        });
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Evaluate `query` on `doc`, returning the JSON Pointer and node of each
+/// match in document order.
+fn evaluate<'n, 'a>(query: &Query, doc: &'n Yaml<'a>) -> Vec<Match<'n, 'a>> {
+    let root = Match {
+        pointer: String::new(),
+        node: doc,
+    };
+    query
+        .steps
+        .iter()
+        .fold(vec![root], |selected, step| apply_step(step, selected))
+}
+
+/// Follow JSON Pointer `tokens` from `doc`, as for the pointer
+/// `/tokens[0]/tokens[1]/...`.
+pub(crate) fn lookup<'n, 'a>(doc: &'n Yaml<'a>, tokens: &[&str]) -> Option<&'n Yaml<'a>> {
+    let query = Query {
+        steps: tokens
+            .iter()
+            .map(|token| Step::Name(token.to_string()))
+            .collect(),
+    };
+    evaluate(&query, doc).into_iter().next().map(|m| m.node)
+}
+
+/// Evaluate `query` on the first document of YAML text `input` and convert
+/// only the matched nodes. A definite query returns its match, or `NULL`;
+/// any other returns a list of matches named by their JSON Pointers.
+pub(crate) fn yaml_get_impl(
+    input: &str,
+    query: &str,
+    handlers: Sexp,
+    options: ParseOptions,
+) -> Fallible<Sexp> {
+    let query = Query::parse(query)?;
+    let docs = scan_yaml_documents(input, false)
+        .map_err(|err| api_other(format!("YAML parse error: {err}")))?;
+    let doc = docs.first().unwrap_or(&Yaml::BadValue);
+    let matches = evaluate(&query, doc);
+
+    if query.is_definite() {
+        return match matches.into_iter().next() {
+            Some(m) => detached_to_robj(&mut m.node.clone(), &handlers, &options),
+            None => Ok(r_ext::null()),
+        };
+    }
+    let names: Vec<String> = matches.iter().map(|m| m.pointer.clone()).collect();
+    let mut nodes: Vec<Yaml> = matches.into_iter().map(|m| m.node.clone()).collect();
+    detached_list_to_robj(&mut nodes, Some(&names), &handlers, &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(query: &str) -> Vec<Step> {
+        Query::parse(query).unwrap().steps
+    }
+
+    fn pointers(text: &str, query: &str) -> Vec<String> {
+        let doc = scan_yaml_documents(text, false).unwrap().remove(0);
+        let query = Query::parse(query).unwrap();
+        evaluate(&query, &doc)
+            .into_iter()
+            .map(|m| m.pointer)
+            .collect()
+    }
+
+    #[test]
+    fn parses_query_syntaxes() {
+        use Step::*;
+        let name = |s: &str| Name(s.to_string());
+        assert_eq!(steps(""), []);
+        assert_eq!(steps("/a~1b/0/m~0n"), [name("a/b"), name("0"), name("m~n")]);
+        assert_eq!(steps("$"), []);
+        assert_eq!(
+            steps("$.metadata.version"),
+            [name("metadata"), name("version")]
+        );
+        assert_eq!(
+            steps("metadata.version"),
+            [name("metadata"), name("version")]
+        );
+        assert_eq!(
+            steps("items[0].name"),
+            [name("items"), Index(0), name("name")]
+        );
+        assert_eq!(steps("[-1]"), [Index(-1)]);
+        assert_eq!(steps("$['a.b'][\"c\"]"), [name("a.b"), name("c")]);
+        assert_eq!(steps("$.*[*]"), [Wildcard, Wildcard]);
+        assert_eq!(steps("$..name"), [Descendants, name("name")]);
+        assert_eq!(steps("$..*"), [Descendants, Wildcard]);
+        assert_eq!(steps("$..[0]"), [Descendants, Index(0)]);
+        for bad in [
+            "$.", "$..", "$[", "$[x]", "$['a'", "$a", "/a~2", "a..", "$['a'x]",
+        ] {
+            assert!(Query::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn evaluates_queries() {
+        let text = r#"
+metadata: {version: 1.2, name: app}
+'items':
+  - {name: a, tags: [x]}
+  - {name: b, nested: {name: c}}
+1: one
+"a/b": slash
+"#;
+        assert_eq!(pointers(text, "metadata.version"), ["/metadata/version"]);
+        assert_eq!(pointers(text, "/items/1/name"), ["/items/1/name"]);
+        assert_eq!(pointers(text, "$.items[-1].name"), ["/items/1/name"]);
+        assert_eq!(pointers(text, "$.items[5]"), Vec::<String>::new());
+        assert_eq!(pointers(text, "$.missing.name"), Vec::<String>::new());
+        assert_eq!(
+            pointers(text, "$.items[*].name"),
+            ["/items/0/name", "/items/1/name"]
+        );
+        assert_eq!(
+            pointers(text, "$..name"),
+            [
+                "/metadata/name",
+                "/items/0/name",
+                "/items/1/name",
+                "/items/1/nested/name"
+            ]
+        );
+        assert_eq!(pointers(text, "$..[0]"), ["/items/0", "/items/0/tags/0"]);
+        assert_eq!(pointers(text, "/1"), ["/1"]);
+        assert_eq!(pointers(text, "$['a/b']"), ["/a~1b"]);
+        assert_eq!(pointers(text, ""), [""]);
+        assert_eq!(pointers("!set {a: 1}", "a"), ["/a"]);
+    }
+}
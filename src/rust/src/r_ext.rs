@@ -1,6 +1,7 @@
 use crate::{api_other, Fallible};
 use savvy::{FunctionSexp, NotAvailableValue, OwnedListSexp, Sexp, StringSexp};
 use savvy_ffi as ffi;
+use std::ffi::CString;
 use std::marker::PhantomData;
 use std::os::raw::c_char;
 use std::ptr;
@@ -13,6 +14,7 @@ use std::{slice, str};
 static mut YAML_KEYS_SYMBOL: ffi::SEXP = ptr::null_mut();
 static mut YAML_TAG_SYMBOL: ffi::SEXP = ptr::null_mut();
 static mut TZONE_SYMBOL: ffi::SEXP = ptr::null_mut();
+static mut YAML_DOCUMENT_SYMBOL: ffi::SEXP = ptr::null_mut();
 
 #[allow(improper_ctypes)]
 extern "C" {
@@ -38,6 +40,20 @@ extern "C" {
         length: ffi::R_xlen_t,
     ) -> ffi::SEXP;
     fn yaml12_call1(function: ffi::SEXP, argument: ffi::SEXP) -> ffi::SEXP;
+    fn yaml12_call_handler(
+        function: ffi::SEXP,
+        argument: ffi::SEXP,
+        tag: ffi::SEXP,
+        style: ffi::SEXP,
+        context: ffi::SEXP,
+    ) -> ffi::SEXP;
+    fn yaml12_has_formal(function: ffi::SEXP, name: *const c_char) -> i32;
+    fn yaml12_takes_second_argument(function: ffi::SEXP) -> i32;
+    fn yaml12_deparse(value: ffi::SEXP) -> ffi::SEXP;
+    fn yaml12_new_lazy_list(state: ffi::SEXP, names: ffi::SEXP, length: ffi::R_xlen_t)
+        -> ffi::SEXP;
+    fn R_ExternalPtrTag(s: ffi::SEXP) -> ffi::SEXP;
+    fn R_SetExternalPtrTag(s: ffi::SEXP, tag: ffi::SEXP);
 }
 
 const CHARSXP_MUST_TRANSLATE: i32 = 0;
@@ -95,6 +111,7 @@ pub(crate) fn init_symbols() -> Fallible<()> {
             YAML_KEYS_SYMBOL = install_symbol(b"yaml_keys\0");
             YAML_TAG_SYMBOL = install_symbol(b"yaml_tag\0");
             TZONE_SYMBOL = install_symbol(b"tzone\0");
+            YAML_DOCUMENT_SYMBOL = install_symbol(b"yaml_document\0");
             ffi::R_NilValue
         })?;
     }
@@ -113,6 +130,20 @@ pub(crate) fn sym_tzone() -> ffi::SEXP {
     unsafe { TZONE_SYMBOL }
 }
 
+pub(crate) fn sym_yaml_document() -> ffi::SEXP {
+    unsafe { YAML_DOCUMENT_SYMBOL }
+}
+
+/// Mark the external pointer `pointer` with the symbol `tag`, so that it
+/// can be told apart from pointers made elsewhere.
+pub(crate) fn set_external_pointer_tag(pointer: &Sexp, tag: ffi::SEXP) {
+    unsafe { R_SetExternalPtrTag(pointer.0, tag) }
+}
+
+pub(crate) fn has_external_pointer_tag(pointer: &Sexp, tag: ffi::SEXP) -> bool {
+    unsafe { R_ExternalPtrTag(pointer.0) == tag }
+}
+
 pub(crate) fn get_attrib_sym(value: &Sexp, attr: ffi::SEXP) -> Option<Sexp> {
     let attr_value = unsafe { ffi::Rf_getAttrib(value.0, attr) };
     if attr_value == unsafe { ffi::R_NilValue } {
@@ -299,6 +330,62 @@ pub(crate) fn call1(handler: &FunctionSexp, arg: Sexp) -> Fallible<Sexp> {
     unsafe { check_unwind(yaml12_call1(handler.inner(), arg.0)).map(Sexp) }
 }
 
+// Calls `handler(arg, tag, style = style, context = context)`, leaving out the
+// arguments that are `None`. All arguments must already be rooted by the caller.
+pub(crate) fn call_handler(
+    handler: &FunctionSexp,
+    arg: Sexp,
+    tag: Option<Sexp>,
+    style: Option<Sexp>,
+    context: Option<Sexp>,
+) -> Fallible<Sexp> {
+    let tag = tag.unwrap_or_else(null);
+    let style = style.unwrap_or_else(null);
+    let context = context.unwrap_or_else(null);
+    unsafe {
+        check_unwind(yaml12_call_handler(
+            handler.inner(),
+            arg.0,
+            tag.0,
+            style.0,
+            context.0,
+        ))
+        .map(Sexp)
+    }
+}
+
+// Only closures have formals; primitives always report `false`.
+pub(crate) fn has_formal(function: &FunctionSexp, name: &str) -> Fallible<bool> {
+    let name = CString::new(name).map_err(|_| api_other("Formal names cannot contain NUL"))?;
+    Ok(unsafe { yaml12_has_formal(function.inner(), name.as_ptr()) != 0 })
+}
+
+// Whether a closure accepts a second positional argument: `...`, or a second
+// formal other than `style` and `context`, which are always passed by name.
+// Primitives always report `false`.
+pub(crate) fn takes_second_argument(function: &FunctionSexp) -> bool {
+    unsafe { yaml12_takes_second_argument(function.inner()) != 0 }
+}
+
+// Wrap external pointer `state` in a lazy ALTREP list of `length` elements
+// (see `yaml12_lazy.c`). `state` must stay rooted until this returns.
+pub(crate) fn new_lazy_list(state: &Sexp, names: Sexp, length: usize) -> Fallible<Sexp> {
+    unsafe {
+        check_unwind(yaml12_new_lazy_list(
+            state.0,
+            names.0,
+            length as ffi::R_xlen_t,
+        ))
+        .map(Sexp)
+    }
+}
+
+// Deparse a symbol or call, or each element of an expression vector, to a
+// character vector. Like the scalar constructors, the result is not rooted.
+pub(crate) fn deparse(value: &Sexp) -> Fallible<Sexp> {
+    unsafe { check_unwind(yaml12_deparse(value.0)).map(Sexp) }
+}
+
 pub(crate) fn as_string_scalar(value: &Sexp) -> Fallible<Option<&'static str>> {
     let strings = match StringSexp::try_from(Sexp(value.0)) {
         Ok(strings) => strings,
@@ -327,6 +414,23 @@ pub(crate) fn string_elt(strings: &StringSexp, i: usize) -> Fallible<&'static st
     }
 }
 
+// Join the elements of the character argument `name` with newlines, as
+// `parse_yaml()` does with `text`.
+pub(crate) fn text_arg(text: &StringSexp, name: &str) -> Fallible<String> {
+    let mut joined = String::new();
+    for i in 0..text.len() {
+        let line = string_elt(text, i)?;
+        if line.is_na() {
+            return Err(api_other(format!("`{name}` must not contain NA strings")));
+        }
+        if i > 0 {
+            joined.push('\n');
+        }
+        joined.push_str(line);
+    }
+    Ok(joined)
+}
+
 pub(crate) fn names(value: &Sexp) -> Fallible<Option<StringSexp>> {
     let Some(names) = get_attrib_sym(value, unsafe { ffi::R_NamesSymbol }) else {
         return Ok(None);
@@ -1,5 +1,6 @@
 use crate::emitter::YamlEmitter;
-use crate::r_ext;
+use crate::handlers::YAML_CORE_HANDLE;
+use crate::r_ext::{self, PreservedSexp};
 use crate::{api_other, Fallible};
 use crate::{
     timestamp::{
@@ -25,8 +26,8 @@ pub(crate) fn yaml_body(yaml: &str, multi: bool) -> &str {
     }
 }
 
-fn emit_yaml_documents(
-    docs: &[Yaml<'static>],
+pub(crate) fn emit_yaml_documents(
+    docs: &[Yaml<'_>],
     multi: bool,
     width: Option<usize>,
 ) -> Fallible<String> {
@@ -73,7 +74,7 @@ fn write_to_r_stdout(mut content: String) -> Fallible<()> {
     Ok(())
 }
 
-fn robj_to_yaml(robj: &Sexp) -> Fallible<Yaml<'static>> {
+pub(crate) fn robj_to_yaml(robj: &Sexp) -> Fallible<Yaml<'static>> {
     if TIMESTAMP_SUPPORT_ENABLED && r_ext::get_attrib_sym(robj, r_ext::sym_yaml_tag()).is_none() {
         if r_ext::inherits(robj, "POSIXt")? || r_ext::inherits(robj, "POSIXct")? {
             return posix_to_yaml(robj);
@@ -83,6 +84,16 @@ fn robj_to_yaml(robj: &Sexp) -> Fallible<Yaml<'static>> {
         }
     }
 
+    if matches!(
+        unsafe { ffi::TYPEOF(robj.0) },
+        ffi::SYMSXP | ffi::LANGSXP | ffi::EXPRSXP
+    ) {
+        return language_to_yaml(robj);
+    }
+    if r_ext::inherits(robj, "yaml_tagged_null")? {
+        return apply_tag_if_present(robj, Yaml::Value(Scalar::Null));
+    }
+
     let node = match Sexp(robj.0).into_typed() {
         TypedSexp::Null(_) => Ok(Yaml::Value(Scalar::Null)),
         TypedSexp::Logical(value) => logical_to_yaml(value),
@@ -98,6 +109,32 @@ fn robj_to_yaml(robj: &Sexp) -> Fallible<Yaml<'static>> {
     apply_tag_if_present(robj, node)
 }
 
+/// Write symbols, calls, and expression vectors as deparsed `!expr` strings;
+/// each element of an expression vector goes on its own line.
+fn language_to_yaml(robj: &Sexp) -> Fallible<Yaml<'static>> {
+    let deparsed = PreservedSexp::new(r_ext::deparse(robj)?);
+    let lines = StringSexp::try_from(deparsed.value())
+        .map_err(|_| api_other("`deparse1()` must return a character vector"))?;
+    let mut text = String::new();
+    for i in 0..lines.len() {
+        if i > 0 {
+            text.push('\n');
+        }
+        text.push_str(r_ext::string_elt(&lines, i)?);
+    }
+    let tag = match extract_yaml_tag(robj)? {
+        Some(tag) => tag,
+        None => Tag {
+            handle: "!".to_string(),
+            suffix: "expr".to_string(),
+        },
+    };
+    Ok(Yaml::Tagged(
+        Cow::Owned(tag),
+        Box::new(Yaml::Value(Scalar::String(Cow::Owned(text)))),
+    ))
+}
+
 fn typed_name(value: &TypedSexp) -> &'static str {
     match value {
         TypedSexp::Null(_) => "Null",
@@ -307,17 +344,6 @@ fn list_to_yaml(robj: &Sexp, list: ListSexp) -> Fallible<Yaml<'static>> {
 
 fn apply_tag_if_present(robj: &Sexp, node: Yaml<'static>) -> Fallible<Yaml<'static>> {
     if let Some(tag) = extract_yaml_tag(robj)? {
-        // saphyr represents a bare `!` tag as handle="" / suffix="!" when parsing,
-        // but the emitter cannot round-trip that shape. Normalize to handle="!" /
-        // suffix="" so the output is a single `!` tag that still round-trips.
-        let tag = if tag.handle.is_empty() && tag.suffix.as_str() == "!" {
-            Tag {
-                handle: "!".to_string(),
-                suffix: String::new(),
-            }
-        } else {
-            tag
-        };
         Ok(Yaml::Tagged(Cow::Owned(tag), Box::new(node)))
     } else {
         Ok(node)
@@ -339,48 +365,44 @@ fn extract_yaml_tag(robj: &Sexp) -> Fallible<Option<Tag>> {
         return Ok(None);
     }
 
-    let invalid_tag_error = || api_other(format!("Invalid YAML tag `{tag_str}`"));
+    tag_from_attr(tag_str)
+        .map(Some)
+        .ok_or_else(|| api_other(format!("Invalid YAML tag `{tag_str}`")))
+}
 
-    let tag = if tag_str == "!" {
-        Tag {
-            handle: String::new(),
-            suffix: "!".to_string(),
-        }
-    } else if let Some(rest) = tag_str.strip_prefix("!!") {
-        if rest.is_empty() {
-            return Err(invalid_tag_error());
-        }
-        let mut suffix = String::with_capacity(rest.len() + 1);
-        suffix.push('!');
-        suffix.push_str(rest);
-        Tag {
-            handle: "!".to_string(),
-            suffix,
-        }
-    } else if let Some(rest) = tag_str.strip_prefix('!') {
-        if rest.is_empty() {
-            return Err(invalid_tag_error());
-        }
-        Tag {
-            handle: "!".to_string(),
-            suffix: rest.to_string(),
-        }
-    } else if let Some((handle, suffix)) = tag_str.rsplit_once('!') {
-        if suffix.is_empty() {
-            return Err(invalid_tag_error());
-        }
-        Tag {
-            handle: handle.to_string(),
-            suffix: suffix.to_string(),
-        }
-    } else {
-        Tag {
-            handle: String::new(),
-            suffix: tag_str.to_string(),
-        }
+/// Convert a `yaml_tag` attribute into the resolved handle/suffix pair the
+/// parser produces for the same tag, so that emitting and re-parsing yields
+/// the same attribute string.
+///
+/// Parsed tags come back as `!suffix` (local tags), a full URI such as
+/// `tag:yaml.org,2002:binary` (`!!` shorthands, `%TAG` expansions, and
+/// verbatim tags), or `!` (the non-specific tag). The `!!suffix` and
+/// `!<uri>` spellings are also accepted. The emitter renders the pair in
+/// valid YAML syntax, percent-encoding characters the tag grammar does not
+/// allow.
+fn tag_from_attr(tag_str: &str) -> Option<Tag> {
+    let tag = |handle: &str, suffix: &str| Tag {
+        handle: handle.to_string(),
+        suffix: suffix.to_string(),
     };
 
-    Ok(Some(tag))
+    if tag_str == "!" {
+        return Some(tag("", "!"));
+    }
+    if let Some(uri) = tag_str.strip_prefix("!<") {
+        let uri = uri.strip_suffix('>').filter(|uri| !uri.is_empty())?;
+        return Some(tag("", uri));
+    }
+    if let Some(suffix) = tag_str.strip_prefix("!!") {
+        return (!suffix.is_empty()).then(|| tag(YAML_CORE_HANDLE, suffix));
+    }
+    if let Some(suffix) = tag_str.strip_prefix('!') {
+        return Some(tag("!", suffix));
+    }
+    match tag_str.strip_prefix(YAML_CORE_HANDLE) {
+        Some(suffix) if !suffix.is_empty() => Some(tag(YAML_CORE_HANDLE, suffix)),
+        _ => Some(tag("", tag_str)),
+    }
 }
 
 pub(crate) fn format_yaml_impl(
@@ -0,0 +1,319 @@
+//! Error recovery: find every syntax error in a stream rather than the first.
+//!
+//! saphyr's parser cannot continue after an error, so recovery works on the
+//! text. The stream is split into documents at `---` and `...` lines, which
+//! cannot occur inside a document's content. Within a broken document, the
+//! lines from the node the parser was reading when it failed up to the next
+//! line indented no deeper are blanked, and the document is parsed again to
+//! find the next error. Blanking keeps line numbers, so every error is
+//! reported where it is in the original text.
+
+use crate::r_ext;
+use crate::validate::{usize_column, Diagnostic};
+use crate::Fallible;
+use saphyr_parser::{Event, Parser, Span, SpannedEventReceiver};
+use savvy::{OwnedListSexp, Sexp};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// The result of recovery: the errors found, each with the 0-based index of
+/// the document it is in, and the text left after blanking the documents
+/// that have errors, if any document is usable.
+pub(crate) struct Recovered<'a> {
+    pub(crate) text: Option<Cow<'a, str>>,
+    pub(crate) errors: Vec<(usize, Diagnostic)>,
+}
+
+/// Notes the line of the last node the parser reported before an error, and
+/// counts the documents started.
+#[derive(Default)]
+struct ErrorTracker {
+    last_node_line: Option<usize>,
+    has_node: bool,
+    documents: usize,
+}
+
+impl SpannedEventReceiver<'_> for ErrorTracker {
+    fn on_event(&mut self, ev: Event<'_>, span: Span) {
+        if matches!(ev, Event::DocumentStart(_)) {
+            self.documents += 1;
+        }
+        if !matches!(
+            ev,
+            Event::StreamStart | Event::StreamEnd | Event::DocumentStart(_) | Event::DocumentEnd
+        ) {
+            self.last_node_line = Some(span.start.line());
+            self.has_node = true;
+        }
+    }
+}
+
+fn is_marker(line: &str, marker: &str) -> bool {
+    line.strip_prefix(marker)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t', '\r']))
+}
+
+fn is_content(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Split `lines` into the line ranges of documents. A `---` line starts a
+/// document, together with any directives directly before it, and a `...`
+/// line ends one.
+fn split_documents(lines: &[&str]) -> Vec<Range<usize>> {
+    let mut starts = vec![0];
+    for (index, line) in lines.iter().enumerate() {
+        let start = if is_marker(line, "---") {
+            let mut start = index;
+            while start > 0 && lines[start - 1].starts_with('%') {
+                start -= 1;
+            }
+            start
+        } else if is_marker(line, "...") {
+            index + 1
+        } else {
+            continue;
+        };
+        if start > *starts.last().unwrap_or(&0) && start < lines.len() {
+            starts.push(start);
+        }
+    }
+    let ends = starts.iter().skip(1).copied().chain([lines.len()]);
+    starts
+        .iter()
+        .copied()
+        .zip(ends)
+        .map(|(s, e)| s..e)
+        .collect()
+}
+
+/// What checking one segment of the stream found.
+#[derive(Default)]
+struct Segment {
+    errors: Vec<Diagnostic>,
+    has_node: bool,
+    /// The number of documents in the segment, when it has no errors.
+    documents: usize,
+}
+
+/// Find the errors in one document's `lines`, whose first line is line
+/// `first_line` of the stream: all of them, or with `!all` only the first.
+fn document_errors(lines: &[&str], first_line: usize, all: bool) -> Segment {
+    let mut lines: Vec<&str> = lines.to_vec();
+    let mut segment = Segment::default();
+    let errors = &mut segment.errors;
+    loop {
+        let text = lines.join("\n");
+        let mut tracker = ErrorTracker::default();
+        let result = Parser::new_from_str(&text).load(&mut tracker, true);
+        segment.has_node |= tracker.has_node;
+        let Err(err) = result else {
+            if errors.is_empty() {
+                segment.documents = tracker.documents;
+            }
+            return segment;
+        };
+
+        let mut error = Diagnostic::from_scan_error(&err);
+        error.line = error.line.map(|line| line + first_line - 1);
+        // An error no further on than the last one comes from the blanking.
+        let position = (error.line, error.column);
+        if !errors
+            .last()
+            .is_some_and(|last| position <= (last.line, last.column))
+        {
+            errors.push(error);
+        }
+        if !all {
+            return segment;
+        }
+
+        // Blank from the node being read to the next line at its indentation.
+        let error_line = err.marker().line().saturating_sub(1);
+        let start = tracker
+            .last_node_line
+            .map_or(error_line, |line| line.saturating_sub(1).min(error_line));
+        let indent = lines.get(start).map_or(0, |line| indentation(line));
+        let end = (start + 1..lines.len())
+            .find(|&index| {
+                let line = lines[index];
+                is_marker(line, "...") || (is_content(line) && indentation(line) <= indent)
+            })
+            .unwrap_or(lines.len());
+        let blanked = lines
+            .get_mut(start..end)
+            .into_iter()
+            .flatten()
+            .filter(|line| is_content(line))
+            .map(|line| *line = "")
+            .count();
+        if blanked == 0 {
+            return segment;
+        }
+    }
+}
+
+/// Check `text` for syntax errors, collecting all of them, or with `!all`
+/// the first in each document. With `multi`, documents with errors are
+/// blanked out of the returned text, so the rest can still be loaded;
+/// otherwise only the first document counts, and no text is returned when it
+/// has an error.
+pub(crate) fn recover(text: &str, multi: bool, all: bool) -> Recovered<'_> {
+    if Parser::new_from_str(text)
+        .load(&mut ErrorTracker::default(), multi)
+        .is_ok()
+    {
+        return Recovered {
+            text: Some(Cow::Borrowed(text)),
+            errors: Vec::new(),
+        };
+    }
+
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    let mut errors = Vec::new();
+    let mut documents = 0;
+    for range in split_documents(&lines) {
+        let segment = document_errors(&lines[range.clone()], range.start + 1, all);
+        if segment.errors.is_empty() {
+            if !multi && segment.has_node {
+                break;
+            }
+            documents += segment.documents;
+            continue;
+        }
+        errors.extend(segment.errors.into_iter().map(|error| (documents, error)));
+        documents += 1;
+        if !multi {
+            return Recovered { text: None, errors };
+        }
+        // Keep a closing `...` so the next document stays separate.
+        let end = match lines[range.end - 1] {
+            last if is_marker(last, "...") => range.end - 1,
+            _ => range.end,
+        };
+        lines[range.start..end].fill("");
+    }
+    Recovered {
+        text: Some(Cow::Owned(lines.join("\n"))),
+        errors,
+    }
+}
+
+/// Parse `text` with recovery, loading what is usable with `load`. Returns
+/// a list of the `value` and the `document`, `line`, `column`, and `message`
+/// columns of the `errors`, with 1-based document indices.
+pub(crate) fn parse_recovering(
+    text: &str,
+    multi: bool,
+    all: bool,
+    load: impl FnOnce(&str) -> Fallible<Sexp>,
+) -> Fallible<Sexp> {
+    let recovered = recover(text, multi, all);
+    let value = match &recovered.text {
+        Some(text) => load(text)?,
+        None => r_ext::null(),
+    };
+    let errors = &recovered.errors;
+    let messages = errors
+        .iter()
+        .map(|(_, error)| r_ext::string_data(&error.message))
+        .collect::<Fallible<Vec<_>>>()?;
+    let mut columns = OwnedListSexp::new(4, true)?;
+    columns.set_name_and_value(
+        0,
+        "document",
+        usize_column(errors.iter().map(|(document, _)| Some(document + 1)))?,
+    )?;
+    columns.set_name_and_value(
+        1,
+        "line",
+        usize_column(errors.iter().map(|(_, error)| error.line))?,
+    )?;
+    columns.set_name_and_value(
+        2,
+        "column",
+        usize_column(errors.iter().map(|(_, error)| error.column))?,
+    )?;
+    columns.set_name_and_value(3, "message", r_ext::materialize_string_vector(&messages)?)?;
+
+    let mut out = OwnedListSexp::new(2, true)?;
+    out.set_name_and_value(0, "value", value)?;
+    out.set_name_and_value(1, "errors", columns)?;
+    Ok(out.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yaml_to_r::scan_yaml_documents;
+
+    fn lines(recovered: &Recovered<'_>) -> Vec<usize> {
+        recovered
+            .errors
+            .iter()
+            .map(|(_, error)| error.line.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn splits_documents_at_markers() {
+        let text = "a: 1\n--- |\n  x\n...\n# c\n%YAML 1.2\n---\nb\n...\n";
+        let lines: Vec<&str> = text.split('\n').collect();
+        assert_eq!(split_documents(&lines), [0..1, 1..4, 4..5, 5..9, 9..10]);
+    }
+
+    #[test]
+    fn finds_every_error_and_keeps_clean_documents() {
+        let text = "\
+a: 1
+b: [1, 2
+c: 3
+d: 'x
+e: 4
+---
+f: 5
+...
+g: {h
+---
+i: 6
+ j: 7
+";
+        let recovered = recover(text, true, true);
+        assert_eq!(lines(&recovered), [3, 4, 10, 12]);
+        let clean = recovered.text.unwrap();
+        assert_eq!(clean, "\n\n\n\n\n---\nf: 5\n...\n\n\n\n\n");
+        assert_eq!(scan_yaml_documents(&clean, true).unwrap().len(), 1);
+
+        let recovered = recover(text, false, true);
+        assert_eq!(lines(&recovered), [3, 4]);
+        assert!(recovered.text.is_none());
+    }
+
+    #[test]
+    fn numbers_documents_with_errors() {
+        let text = "a: [1\nb: {c\n---\nd: 1\n--- x\n---\n...\n# e\n---\nf: 'g\n";
+        let recovered = recover(text, true, false);
+        let found: Vec<(usize, usize)> = recovered
+            .errors
+            .iter()
+            .map(|(document, error)| (*document, error.line.unwrap()))
+            .collect();
+        assert_eq!(found, [(0, 2), (4, 10)]);
+        let clean = recovered.text.unwrap();
+        assert_eq!(scan_yaml_documents(&clean, true).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn leaves_valid_text_alone() {
+        let recovered = recover("a: 1\n---\nb: 2\n", true, true);
+        assert!(recovered.errors.is_empty());
+        assert!(matches!(recovered.text, Some(Cow::Borrowed(_))));
+        let recovered = recover("a: 1\n--- [\n", false, true);
+        assert!(recovered.errors.is_empty());
+    }
+}
@@ -0,0 +1,739 @@
+//! Reformatting that keeps comments.
+//!
+//! The parser events are built into a tree of the nodes as written, keeping
+//! anchors, tags, aliases, and the text of plain scalars, and each
+//! document's directives. Comments are found
+//! in the source outside scalars and attached to the entry they precede or
+//! the line they end. The tree is then printed in the emitter's layout:
+//! block collections indented by two spaces, with quoted and block scalars
+//! restyled by the emitter's quoting rules.
+
+use crate::emitter::YamlEmitter;
+use crate::events::{parse_events, EventKind, ParseEvent};
+use crate::r_ext;
+use crate::{api_other, Fallible};
+use saphyr_parser::ScanError;
+use savvy::{NotAvailableValue, OwnedListSexp, OwnedLogicalSexp, Sexp, StringSexp};
+use std::ops::Range;
+
+enum Kind {
+    Document {
+        explicit: bool,
+        /// The `%YAML` and `%TAG` lines before the document marker.
+        directives: Vec<String>,
+        /// The line after the previous document's `...` marker, from which
+        /// comments come before the directives.
+        directives_line: usize,
+    },
+    /// A scalar with its value; plain scalars are printed as written.
+    Scalar {
+        value: String,
+        plain: bool,
+    },
+    Empty,
+    Alias(String),
+    Sequence,
+    Mapping,
+}
+
+struct Node {
+    kind: Kind,
+    /// The anchor and tag, as written before the node.
+    props: Vec<String>,
+    line: usize,
+    end_line: usize,
+    /// 1-based.
+    column: usize,
+    parent: Option<usize>,
+    /// The index of the node's last descendant, or its own.
+    last: usize,
+    /// Items of a sequence, keys and values of a mapping, or the root of a
+    /// document.
+    children: Vec<usize>,
+    /// Whether a blank line comes before the node.
+    blank_before: bool,
+    leading: Vec<Comment>,
+    trailing: Option<String>,
+    /// Comments after the entries of a collection, at their indentation.
+    footer: Vec<Comment>,
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        match self.kind {
+            Kind::Sequence | Kind::Mapping => self.children.is_empty(),
+            Kind::Document { .. } => false,
+            _ => true,
+        }
+    }
+}
+
+struct Comment {
+    line: usize,
+    /// 1-based.
+    column: usize,
+    text: String,
+    own_line: bool,
+    blank_before: bool,
+}
+
+fn is_blank_before(lines: &[&str], line: usize) -> bool {
+    line > 1 && lines[line - 2].trim().is_empty()
+}
+
+/// The directives of a document whose `---` marker is on `line`: the `%`
+/// lines, as written, after the previous document's `...` marker or the
+/// stream start. Also returns the line the directives may start on.
+fn directives_before(lines: &[&str], line: usize) -> (Vec<String>, usize) {
+    let mut directives = Vec::new();
+    let mut first = line - 1;
+    while first > 0 {
+        let text = lines[first - 1].trim_end();
+        if text.starts_with('%') {
+            directives.push(text.to_string());
+        } else if text.split_whitespace().next() == Some("...") {
+            break;
+        } else if !text.is_empty() && !text.trim_start().starts_with('#') {
+            // Without a `...` marker, the lines belong to the previous
+            // document.
+            return (Vec::new(), line);
+        }
+        first -= 1;
+    }
+    directives.reverse();
+    (directives, first + 1)
+}
+
+/// Build the node tree from `events`, in document order.
+fn build_tree(events: &[ParseEvent], lines: &[&str]) -> Vec<Node> {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    for event in events {
+        let kind = match event.kind {
+            EventKind::DocumentStart => {
+                let explicit = event.style == Some("explicit");
+                let (directives, directives_line) = if explicit {
+                    directives_before(lines, event.line)
+                } else {
+                    (Vec::new(), event.line)
+                };
+                Kind::Document {
+                    explicit,
+                    directives,
+                    directives_line,
+                }
+            }
+            EventKind::MappingStart => Kind::Mapping,
+            EventKind::SequenceStart => Kind::Sequence,
+            EventKind::Scalar => {
+                let value = event.value.clone().unwrap_or_default();
+                // A plain scalar that spans lines may fold line breaks.
+                let plain = event.style == Some("plain") && !value.contains('\n');
+                if plain && value.is_empty() {
+                    Kind::Empty
+                } else {
+                    Kind::Scalar { value, plain }
+                }
+            }
+            EventKind::Alias => Kind::Alias(event.anchor.clone().unwrap_or_default()),
+            EventKind::DocumentEnd | EventKind::MappingEnd | EventKind::SequenceEnd => {
+                if let Some(closed) = open.pop() {
+                    nodes[closed].last = nodes.len() - 1;
+                }
+                continue;
+            }
+            EventKind::StreamStart | EventKind::StreamEnd => continue,
+        };
+        let mut props = Vec::new();
+        if !matches!(kind, Kind::Alias(_)) {
+            props.extend(event.anchor.as_ref().map(|anchor| format!("&{anchor}")));
+        }
+        props.extend(event.written_tag.clone());
+        let opens = matches!(kind, Kind::Document { .. } | Kind::Sequence | Kind::Mapping);
+        let mut line = event.line;
+        if matches!(event.style, Some("literal" | "folded")) {
+            // The span starts at the content; the node starts at its header.
+            line -= 1;
+            while line > 1 && lines[line - 1].trim().is_empty() {
+                line -= 1;
+            }
+        }
+        let mut column = event.column;
+        if matches!(kind, Kind::Sequence) && event.style == Some("block") {
+            // The span of a block sequence may start at its first item; the
+            // sequence starts at the dash before it.
+            let before: Vec<char> = lines[line - 1].chars().take(column + 1).collect();
+            column = before
+                .windows(2)
+                .rposition(|pair| pair[0] == '-' && pair[1].is_whitespace())
+                .map_or(column, |dash| dash + 1);
+            // A sequence at the indentation of its mapping's keys counts as
+            // deeper, so comments at the keys' indentation go to the mapping.
+            if open
+                .last()
+                .is_some_and(|&parent| nodes[parent].column == column)
+            {
+                column += 1;
+            }
+        }
+        let index = nodes.len();
+        nodes.push(Node {
+            kind,
+            props,
+            line,
+            end_line: event.end_line,
+            column,
+            parent: open.last().copied(),
+            last: index,
+            children: Vec::new(),
+            blank_before: is_blank_before(lines, line),
+            leading: Vec::new(),
+            trailing: None,
+            footer: Vec::new(),
+        });
+        if let Some(&parent) = open.last() {
+            nodes[parent].children.push(index);
+        }
+        if opens {
+            open.push(index);
+        }
+    }
+    nodes
+}
+
+/// The comments in `lines`, skipping `#` inside the character ranges of
+/// `scalars`, which are in order.
+fn find_comments(lines: &[&str], scalars: &[Range<usize>]) -> Vec<Comment> {
+    let mut comments = Vec::new();
+    let mut offset = 0;
+    let mut next_scalar = 0;
+    for (index, line) in lines.iter().enumerate() {
+        if line.starts_with('%') {
+            // A directive is printed as written, with its comment.
+            offset += line.chars().count() + 1;
+            continue;
+        }
+        let mut after_space = true;
+        for (column, (byte, ch)) in line.char_indices().enumerate() {
+            let position = offset + column;
+            while scalars
+                .get(next_scalar)
+                .is_some_and(|scalar| scalar.end <= position)
+            {
+                next_scalar += 1;
+            }
+            let in_scalar = scalars
+                .get(next_scalar)
+                .is_some_and(|scalar| scalar.start <= position);
+            if ch == '#' && after_space && !in_scalar {
+                comments.push(Comment {
+                    line: index + 1,
+                    column: column + 1,
+                    text: line[byte..].trim_end().to_string(),
+                    own_line: line[..byte].trim().is_empty(),
+                    blank_before: is_blank_before(lines, index + 1),
+                });
+                break;
+            }
+            after_space = ch == ' ' || ch == '\t';
+        }
+        offset += line.chars().count() + 1;
+    }
+    comments
+}
+
+/// The innermost collection that the comment at `line` and `column` ends,
+/// if it comes after the collection's entries, is indented at least as deep
+/// as they are, and is indented deeper than the node after it or separated
+/// from it by other content, such as the `:` of an explicit key.
+fn ending_collection(nodes: &[Node], lines: &[&str], line: usize, column: usize) -> Option<usize> {
+    let next = nodes.iter().position(|node| node.line > line);
+    let mut current = match next {
+        Some(0) => return None,
+        Some(next) => Some(next - 1),
+        None => nodes.len().checked_sub(1),
+    };
+    while let Some(index) = current {
+        let node = &nodes[index];
+        if matches!(node.kind, Kind::Sequence | Kind::Mapping)
+            && !node.is_leaf()
+            && node.column <= column
+            && next.map_or(true, |next| {
+                let between = &lines[line..nodes[next].line - 1];
+                next > node.last
+                    && (nodes[next].column < column
+                        || between.iter().any(|text| {
+                            let text = text.trim();
+                            !text.is_empty() && !text.starts_with('#')
+                        }))
+            })
+        {
+            return Some(index);
+        }
+        current = node.parent;
+    }
+    None
+}
+
+/// The end of the quoted scalar starting at `start`. The parser's span of a
+/// quoted scalar runs on over what follows it on the line.
+pub(crate) fn quoted_end(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut index = start + 1;
+    while index < chars.len() {
+        match chars[index] {
+            '\\' if quote == '"' => index += 1,
+            '\'' if quote == '\'' && chars.get(index + 1) == Some(&'\'') => index += 1,
+            ch if ch == quote => return index + 1,
+            _ => {}
+        }
+        index += 1;
+    }
+    chars.len()
+}
+
+/// Attach each comment to a node: a comment ending a line to the last leaf
+/// or explicit document marker on that line, a comment after the entries of
+/// a collection to that collection, and any other comment to the next node.
+/// Returns the comments after the last node.
+fn attach_comments(nodes: &mut [Node], lines: &[&str], comments: Vec<Comment>) -> Vec<Comment> {
+    let mut rest = Vec::new();
+    for comment in comments {
+        if !comment.own_line {
+            let ends_line = |node: &Node, line: usize| {
+                node.line == line
+                    && (node.is_leaf()
+                        || matches!(node.kind, Kind::Document { explicit: true, .. }))
+            };
+            let target = nodes
+                .iter()
+                .rposition(|node| ends_line(node, comment.line))
+                .or_else(|| {
+                    nodes
+                        .iter()
+                        .rposition(|node| node.is_leaf() && node.end_line == comment.line)
+                });
+            if let Some(target) = target {
+                let trailing = &mut nodes[target].trailing;
+                match trailing {
+                    Some(existing) => {
+                        existing.push(' ');
+                        existing.push_str(&comment.text);
+                    }
+                    None => *trailing = Some(comment.text),
+                }
+                continue;
+            }
+        }
+        let document = nodes.iter().position(|node| match &node.kind {
+            Kind::Document {
+                directives,
+                directives_line,
+                ..
+            } => !directives.is_empty() && (*directives_line..node.line).contains(&comment.line),
+            _ => false,
+        });
+        if let Some(document) = document {
+            // A comment among the directives goes before them.
+            nodes[document].leading.push(comment);
+            continue;
+        }
+        if let Some(collection) = ending_collection(nodes, lines, comment.line, comment.column) {
+            nodes[collection].footer.push(comment);
+            continue;
+        }
+        match nodes.iter_mut().find(|node| node.line > comment.line) {
+            Some(node) => node.leading.push(comment),
+            None => rest.push(comment),
+        }
+    }
+    rest
+}
+
+struct Printer<'a> {
+    nodes: &'a [Node],
+    out: String,
+}
+
+impl<'a> Printer<'a> {
+    fn indent(&mut self, indent: usize) {
+        self.out.extend(std::iter::repeat(' ').take(indent));
+    }
+
+    fn blank_line(&mut self, blank: bool) {
+        if blank && !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn comments<'c>(&mut self, comments: impl IntoIterator<Item = &'c Comment>, indent: usize) {
+        for comment in comments {
+            self.blank_line(comment.blank_before);
+            self.indent(indent);
+            self.out.push_str(&comment.text);
+            self.out.push('\n');
+        }
+    }
+
+    /// End the current line with `comments`.
+    fn end_line(&mut self, comments: &[Option<&str>]) {
+        for comment in comments.iter().flatten() {
+            self.out.push(' ');
+            self.out.push_str(comment);
+        }
+        self.out.push('\n');
+    }
+
+    /// The text of a leaf: a scalar in an entry nested `level` deep, which
+    /// may span lines as a block scalar, an alias, or an empty collection.
+    fn leaf(&self, index: usize, level: isize, key: bool) -> String {
+        let node = &self.nodes[index];
+        let mut text = node.props.join(" ");
+        let body = match &node.kind {
+            Kind::Scalar { value, plain: true } => value.clone(),
+            Kind::Scalar {
+                value,
+                plain: false,
+            } => {
+                let mut rendered = String::new();
+                let mut emitter = YamlEmitter::new(&mut rendered);
+                emitter.multiline_strings(true);
+                // Writing to a `String` cannot fail.
+                let _ = emitter.emit_string(value, level, key);
+                rendered
+            }
+            Kind::Empty if key && text.is_empty() => "~".to_string(),
+            Kind::Alias(name) => format!("*{name}"),
+            Kind::Sequence => "[]".to_string(),
+            Kind::Mapping => "{}".to_string(),
+            Kind::Empty | Kind::Document { .. } => String::new(),
+        };
+        if !text.is_empty() && !body.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(&body);
+        text
+    }
+
+    /// Print the leaf at `index` after the indicator on the current line,
+    /// ending the line with `comments` after the leaf's first line.
+    fn print_leaf(&mut self, index: usize, level: isize, comments: &[Option<&str>]) {
+        let text = self.leaf(index, level, false);
+        let (first, rest) = text.split_once('\n').unwrap_or((&text, ""));
+        if !first.is_empty() {
+            self.out.push(' ');
+            self.out.push_str(first);
+        }
+        self.end_line(comments);
+        if !rest.is_empty() {
+            self.out.push_str(rest);
+            self.out.push('\n');
+        }
+    }
+
+    /// The comments printed before an entry of the collection at `index`.
+    fn entry_leading(&self, index: usize, entry: usize) -> Vec<&'a Comment> {
+        let nodes: &'a [Node] = self.nodes;
+        let node = &nodes[index];
+        let mut leading: Vec<&'a Comment> = Vec::new();
+        match node.kind {
+            Kind::Mapping => {
+                let key = &nodes[node.children[2 * entry]];
+                let value = &nodes[node.children[2 * entry + 1]];
+                leading.extend(&key.leading);
+                if value.is_leaf() {
+                    leading.extend(&value.leading);
+                }
+            }
+            _ => leading.extend(&nodes[node.children[entry]].leading),
+        }
+        leading
+    }
+
+    /// Print the node at `index` after the indicator of an entry indented
+    /// by `indent` (`-`, `?`, or a key and `:`). A collection in a sequence
+    /// starts on the same line when it has no properties or comments there.
+    fn print_value(
+        &mut self,
+        index: usize,
+        indent: usize,
+        compact: bool,
+        comment: Option<&str>,
+        leading: bool,
+    ) {
+        let node = &self.nodes[index];
+        let level = (indent / 2) as isize;
+        if node.is_leaf() {
+            self.print_leaf(index, level, &[comment, node.trailing.as_deref()]);
+            return;
+        }
+        let child_indent = indent + 2;
+        let compact = compact
+            && node.props.is_empty()
+            && comment.is_none()
+            && (!leading || node.leading.is_empty())
+            && self.entry_leading(index, 0).is_empty();
+        if compact {
+            self.out.push(' ');
+            self.print_entries(index, child_indent, true);
+            return;
+        }
+        if !node.props.is_empty() {
+            self.out.push(' ');
+            self.out.push_str(&node.props.join(" "));
+        }
+        self.end_line(&[comment]);
+        if leading {
+            self.comments(&node.leading, child_indent);
+        }
+        self.print_entries(index, child_indent, false);
+    }
+
+    /// Print the entries of the collection at `index`, indented by
+    /// `indent`; with `inline`, the first starts on the current line.
+    fn print_entries(&mut self, index: usize, indent: usize, inline: bool) {
+        let node = &self.nodes[index];
+        let entries = match node.kind {
+            Kind::Mapping => node.children.len() / 2,
+            _ => node.children.len(),
+        };
+        for entry in 0..entries {
+            let first_child = match node.kind {
+                Kind::Mapping => node.children[2 * entry],
+                _ => node.children[entry],
+            };
+            if !(inline && entry == 0) {
+                let leading = self.entry_leading(index, entry);
+                self.comments(leading, indent);
+                self.blank_line(self.nodes[first_child].blank_before);
+                self.indent(indent);
+            }
+            if !matches!(node.kind, Kind::Mapping) {
+                self.out.push('-');
+                self.print_value(first_child, indent, true, None, false);
+                continue;
+            }
+            let key = &self.nodes[first_child];
+            let value = node.children[2 * entry + 1];
+            if key.is_leaf() {
+                let text = self.leaf(first_child, (indent / 2) as isize, true);
+                self.out.push_str(&text);
+                // An alias or properties would take a `:` right after them
+                // into their name.
+                if matches!(key.kind, Kind::Alias(_))
+                    || matches!(key.kind, Kind::Empty) && !key.props.is_empty()
+                {
+                    self.out.push(' ');
+                }
+                self.out.push(':');
+                self.print_value(value, indent, false, key.trailing.as_deref(), true);
+            } else {
+                self.out.push('?');
+                self.print_value(first_child, indent, true, None, false);
+                self.indent(indent);
+                self.out.push(':');
+                self.print_value(value, indent, true, None, true);
+            }
+        }
+        self.comments(&node.footer, indent);
+    }
+
+    fn print_document(&mut self, index: usize, first: bool) {
+        let node = &self.nodes[index];
+        let Kind::Document {
+            explicit,
+            directives,
+            ..
+        } = &node.kind
+        else {
+            return;
+        };
+        if !first && !directives.is_empty() {
+            self.out.push_str("...\n");
+        }
+        self.comments(&node.leading, 0);
+        for directive in directives {
+            self.out.push_str(directive);
+            self.out.push('\n');
+        }
+        let Some(&root) = node.children.first() else {
+            return;
+        };
+        let root_node = &self.nodes[root];
+        let empty = matches!(root_node.kind, Kind::Empty) && root_node.props.is_empty();
+        let mut header = Vec::new();
+        if *explicit || !first || empty {
+            header.push("---".to_string());
+        }
+        if root_node.is_leaf() {
+            if !header.is_empty() {
+                self.out.push_str(&header.join(" "));
+                self.end_line(&[node.trailing.as_deref()]);
+            }
+            self.comments(&root_node.leading, 0);
+            let text = self.leaf(root, -1, false);
+            if !text.is_empty() {
+                self.out.push_str(&text);
+                self.end_line(&[root_node.trailing.as_deref()]);
+            }
+            return;
+        }
+        header.extend(root_node.props.iter().cloned());
+        if !header.is_empty() {
+            self.out.push_str(&header.join(" "));
+            self.end_line(&[node.trailing.as_deref()]);
+        }
+        self.comments(&root_node.leading, 0);
+        self.print_entries(root, 0, false);
+    }
+}
+
+/// Reformat `text` in the emitter's layout, keeping comments.
+pub(crate) fn reformat(text: &str) -> Result<String, ScanError> {
+    let events = parse_events(text)?;
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut nodes = build_tree(&events, &lines);
+    let chars: Vec<char> = text.chars().collect();
+    let scalars: Vec<Range<usize>> = events
+        .iter()
+        .filter(|event| event.kind == EventKind::Scalar && event.value.as_deref() != Some(""))
+        .map(|event| match event.style {
+            Some("single_quoted" | "double_quoted") => {
+                event.span.start..quoted_end(&chars, event.span.start)
+            }
+            _ => event.span.clone(),
+        })
+        .collect();
+    let rest = attach_comments(&mut nodes, &lines, find_comments(&lines, &scalars));
+
+    let mut printer = Printer {
+        nodes: &nodes,
+        out: String::new(),
+    };
+    let documents = nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| matches!(node.kind, Kind::Document { .. }));
+    for (count, (index, _)) in documents.enumerate() {
+        printer.print_document(index, count == 0);
+    }
+    printer.comments(&rest, 0);
+    Ok(printer.out)
+}
+
+fn reformat_error(err: ScanError, source: Option<&str>) -> savvy::Error {
+    match source {
+        Some(path) => api_other(format!("YAML parse error in `{path}`: {err}")),
+        None => api_other(format!("YAML parse error: {err}")),
+    }
+}
+
+pub(crate) fn yaml_reformat_impl(text: &str) -> Fallible<Sexp> {
+    let formatted = reformat(text).map_err(|err| reformat_error(err, None))?;
+    r_ext::string_scalar(&formatted)
+}
+
+/// Reformat each file in `paths`, unless `check` is set, keeping Windows
+/// line endings. Returns whether each file changed, or would change, and
+/// for a file that cannot be read or parsed, `NA` and the error message.
+pub(crate) fn yaml_format_file_impl(paths: &StringSexp, check: bool) -> Fallible<Sexp> {
+    let mut changed = OwnedLogicalSexp::new(paths.len())?;
+    let mut messages = Vec::with_capacity(paths.len());
+    for index in 0..paths.len() {
+        let path = r_ext::string_elt(paths, index)?;
+        if path.is_na() {
+            return Err(api_other("`path` must not contain NA strings"));
+        }
+        let formatted = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read `{path}`: {err}"))
+            .and_then(|contents| {
+                let formatted = reformat(&contents)
+                    .map_err(|err| reformat_error(err, Some(path)).to_string())?;
+                Ok((contents, formatted))
+            });
+        let (contents, mut formatted) = match formatted {
+            Ok(read) => read,
+            Err(message) => {
+                changed.set_na(index)?;
+                messages.push(message);
+                continue;
+            }
+        };
+        if contents.contains("\r\n") {
+            formatted = formatted.replace('\n', "\r\n");
+        }
+        let differs = formatted != contents;
+        if differs && !check {
+            std::fs::write(path, &formatted)
+                .map_err(|err| api_other(format!("Failed to write `{path}`: {err}")))?;
+        }
+        changed.set_elt(index, differs)?;
+    }
+    let messages = messages
+        .iter()
+        .map(|message| r_ext::string_data(message))
+        .collect::<Fallible<Vec<_>>>()?;
+    let mut out = OwnedListSexp::new(2, true)?;
+    out.set_name_and_value(0, "changed", changed)?;
+    out.set_name_and_value(1, "errors", r_ext::materialize_string_vector(&messages)?)?;
+    Ok(out.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use saphyr::{LoadableYamlNode, Yaml};
+
+    fn assert_reformats(text: &str, expected: &str) {
+        let formatted = reformat(text).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(reformat(&formatted).unwrap(), formatted);
+        assert_eq!(
+            Yaml::load_from_str(&formatted).unwrap(),
+            Yaml::load_from_str(text).unwrap()
+        );
+    }
+
+    #[test]
+    fn normalizes_layout_and_quoting() {
+        assert_reformats(
+            "a:   'x'\nb:\n    - 'yes'\n    - \"1\"\n    -   [c,   {d: e}]\nf: {}\n",
+            "a: x\nb:\n  - yes\n  - \"1\"\n  - - c\n    - d: e\nf: {}\n",
+        );
+        assert_reformats(
+            "--- &r !t\nk: !!str 1\n? [a]\n: *r\ns: |\n    x\n     y\n",
+            "--- &r !t\nk: !!str 1\n? - a\n: *r\ns: |\n  x\n   y\n",
+        );
+        assert_reformats("-\n  &a : a\n", "- &a : a\n");
+        assert_reformats(
+            "- !!str\n-\n  !!null : a\n  b: !!str\n- !!str : !!null\n",
+            "- !!str\n- !!null : a\n  b: !!str\n- !!str : !!null\n",
+        );
+        assert_reformats("x\n---\n", "x\n---\n");
+        assert_reformats("a:  'x' # c\r\nb: [1]\r\n", "a: x # c\nb:\n  - 1\n");
+    }
+
+    #[test]
+    fn keeps_directives_and_tags_as_written() {
+        assert_reformats(
+            "%TAG !e! tag:x:\n--- !e!foo\na:   !e!bar 1\nb: !<tag:x:baz>   [2]\nc: !!str 3\n",
+            "%TAG !e! tag:x:\n--- !e!foo\na: !e!bar 1\nb: !<tag:x:baz>\n  - 2\nc: !!str 3\n",
+        );
+        assert_reformats(
+            "a: 1\n...\n# second\n%YAML 1.2\n%TAG ! tag:y:  # local\n---\nb: !c   d\n",
+            "a: 1\n...\n# second\n%YAML 1.2\n%TAG ! tag:y:  # local\n---\nb: !c d\n",
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        assert_reformats(
+            "# head\n\na:   1  # one\n\n# before b\nb:   # open\n    c: |  # lit\n      z\n    # end of b\nd:\n-   e # item\n# tail\n",
+            "# head\n\na: 1 # one\n\n# before b\nb: # open\n  c: | # lit\n    z\n  # end of b\nd:\n  - e # item\n# tail\n",
+        );
+        assert_reformats(
+            "k: ['a' , # first\n  b]\nl:\n- x\n  # end of l\n---   # doc\n\"q\"  # q\n",
+            "k:\n  - a # first\n  - b\nl:\n  - x\n  # end of l\n--- # doc\nq # q\n",
+        );
+    }
+}
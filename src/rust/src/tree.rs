@@ -0,0 +1,81 @@
+//! Helpers for resolved `Yaml` trees shared by the modules that compare,
+//! patch, and edit documents.
+
+use crate::handlers::same_tag;
+use crate::yaml_to_r::{resolve_representation, scan_yaml_documents};
+use crate::{api_other, Fallible};
+use saphyr::{Scalar, Yaml};
+
+/// Load and resolve the first document of `text`, null when there is none.
+/// `name` names the argument in parse errors.
+pub(crate) fn load_first<'a>(text: &'a str, name: &str) -> Fallible<Yaml<'a>> {
+    let mut node = scan_yaml_documents(text, false)
+        .map_err(|err| api_other(format!("YAML parse error in `{name}`: {err}")))?
+        .into_iter()
+        .next()
+        .unwrap_or(Yaml::Value(Scalar::Null));
+    resolve_tree(&mut node);
+    Ok(node)
+}
+
+/// Resolve every scalar in `node`, keys included, so that it can be
+/// compared, searched, and emitted.
+pub(crate) fn resolve_tree(node: &mut Yaml<'_>) {
+    match node {
+        Yaml::Representation(..) => {
+            resolve_representation(node, true);
+            if !matches!(node, Yaml::Representation(..)) {
+                resolve_tree(node);
+            }
+        }
+        Yaml::Tagged(_, inner) => {
+            if let Yaml::Representation(..) = **inner {
+                // A tagged plain null is kept unresolved for handlers.
+                **inner = Yaml::Value(Scalar::Null);
+            }
+            resolve_tree(inner);
+        }
+        Yaml::Sequence(items) => items.iter_mut().for_each(resolve_tree),
+        Yaml::Mapping(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(mut key, mut value)| {
+                    resolve_tree(&mut key);
+                    resolve_tree(&mut value);
+                    (key, value)
+                })
+                .collect();
+        }
+        _ => {}
+    }
+}
+
+/// Equality of resolved nodes: mappings compare without regard to key
+/// order, integers equal floats of the same value, and tags compare as
+/// written in full, however the parser split them.
+pub(crate) fn yaml_equal(a: &Yaml<'_>, b: &Yaml<'_>) -> bool {
+    match (a, b) {
+        (Yaml::Mapping(a), Yaml::Mapping(b)) => {
+            a.len() == b.len()
+                && a.iter().all(|(key, value)| match b.get(key) {
+                    Some(other_value) => yaml_equal(value, other_value),
+                    // Only keys that are equal but not identical, such as
+                    // `1` and `1.0`, need a search.
+                    None => b.iter().any(|(other, other_value)| {
+                        yaml_equal(key, other) && yaml_equal(value, other_value)
+                    }),
+                })
+        }
+        (Yaml::Sequence(a), Yaml::Sequence(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| yaml_equal(a, b))
+        }
+        (Yaml::Tagged(tag_a, a), Yaml::Tagged(tag_b, b)) => {
+            same_tag(tag_a, tag_b) && yaml_equal(a, b)
+        }
+        (Yaml::Value(Scalar::Integer(a)), Yaml::Value(Scalar::FloatingPoint(b)))
+        | (Yaml::Value(Scalar::FloatingPoint(b)), Yaml::Value(Scalar::Integer(a))) => {
+            *a as f64 == b.0
+        }
+        (a, b) => a == b,
+    }
+}
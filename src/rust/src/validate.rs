@@ -0,0 +1,354 @@
+//! Syntax validation that runs only the parser, without loading documents.
+
+use crate::events::{EventKind, ParseEvent};
+use crate::handlers::render_tag;
+use crate::r_ext;
+use crate::yaml_to_r::resolve_representation;
+use crate::{api_other, Fallible};
+use saphyr::{Tag, Yaml};
+use saphyr_parser::{Event, Parser, ScanError, Span, SpannedEventReceiver};
+use savvy::{
+    NotAvailableValue, OwnedIntegerSexp, OwnedListSexp, OwnedLogicalSexp, Sexp, StringSexp,
+};
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+/// Structural checks applied on top of the syntax check.
+#[derive(Default)]
+pub(crate) struct ValidateOptions {
+    pub(crate) duplicate_keys: bool,
+    /// Tags allowed besides the core schema and `!`; `None` allows any tag.
+    pub(crate) known_tags: Option<HashSet<String>>,
+}
+
+impl ValidateOptions {
+    pub(crate) fn from_args(duplicate_keys: bool, known_tags: &Sexp) -> Fallible<Self> {
+        let known_tags = if known_tags.is_null() {
+            None
+        } else {
+            let tags = r_ext::string_sexp(known_tags)
+                .ok_or_else(|| api_other("`known_tags` must be NULL or a character vector"))?;
+            let tags = (0..tags.len())
+                .map(|index| r_ext::string_elt(&tags, index).map(str::to_string))
+                .collect::<Fallible<HashSet<_>>>()?;
+            Some(tags)
+        };
+        Ok(Self {
+            duplicate_keys,
+            known_tags,
+        })
+    }
+}
+
+/// A problem found in one input. Positions are 1-based and absent when the
+/// input could not be read.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Diagnostic {
+    pub(crate) line: Option<usize>,
+    pub(crate) column: Option<usize>,
+    pub(crate) message: String,
+}
+
+impl Diagnostic {
+    /// A problem at 1-based `line` and `column`.
+    fn at((line, column): (usize, usize), message: String) -> Self {
+        Self {
+            line: Some(line),
+            column: Some(column),
+            message,
+        }
+    }
+
+    pub(crate) fn from_scan_error(err: &ScanError) -> Self {
+        Self {
+            line: Some(err.marker().line()),
+            column: Some(err.marker().col() + 1),
+            message: err.info().to_string(),
+        }
+    }
+}
+
+/// Keys seen so far in an open mapping.
+#[derive(Default)]
+struct MappingKeys<'input> {
+    expecting_key: bool,
+    keys: HashSet<Yaml<'input>>,
+}
+
+struct Validator<'a, 'input> {
+    options: &'a ValidateOptions,
+    /// One entry per open collection; `None` for a sequence.
+    open: Vec<Option<MappingKeys<'input>>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'input> Validator<'_, 'input> {
+    fn check_tag(&mut self, tag: Option<&Cow<'input, Tag>>, span: Span) {
+        let (Some(known), Some(tag)) = (&self.options.known_tags, tag) else {
+            return;
+        };
+        let non_specific = tag.handle.is_empty() && tag.suffix == "!";
+        let rendered = render_tag(tag);
+        if !tag.is_yaml_core_schema() && !non_specific && !known.contains(&rendered) {
+            self.diagnostics.push(Diagnostic::at(
+                position(span),
+                format!("Unknown tag `{rendered}`"),
+            ));
+        }
+    }
+
+    fn open_collection(&mut self, mapping: bool) {
+        self.open.push(mapping.then(|| MappingKeys {
+            expecting_key: true,
+            keys: HashSet::new(),
+        }));
+    }
+
+    /// Record a finished scalar `node`, an unresolved representation.
+    fn complete_scalar(&mut self, mut node: Yaml<'input>, text: &str, at: (usize, usize)) {
+        let is_key = matches!(self.open.last(), Some(Some(mapping)) if mapping.expecting_key);
+        let key = (is_key && self.options.duplicate_keys).then(|| {
+            resolve_representation(&mut node, true);
+            node
+        });
+        self.complete(key, text, at);
+    }
+
+    /// Record a finished node; `key` is the resolved value of a scalar.
+    fn complete(&mut self, key: Option<Yaml<'input>>, text: &str, at: (usize, usize)) {
+        let Some(Some(mapping)) = self.open.last_mut() else {
+            return;
+        };
+        let is_key = mapping.expecting_key;
+        mapping.expecting_key = !is_key;
+        if !is_key || !self.options.duplicate_keys {
+            return;
+        }
+        // Collection and alias keys are never treated as repeated.
+        if let Some(key) = key {
+            if !mapping.keys.insert(key) {
+                self.diagnostics.push(Diagnostic::at(
+                    at,
+                    format!("Duplicate mapping key `{text}`"),
+                ));
+            }
+        }
+    }
+}
+
+/// The 1-based line and column where `span` starts.
+fn position(span: Span) -> (usize, usize) {
+    (span.start.line(), span.start.col() + 1)
+}
+
+impl<'input> SpannedEventReceiver<'input> for Validator<'_, 'input> {
+    fn on_event(&mut self, ev: Event<'input>, span: Span) {
+        match ev {
+            Event::Scalar(value, style, _, tag) => {
+                self.check_tag(tag.as_ref(), span);
+                let node = Yaml::Representation(value.clone(), style, tag);
+                self.complete_scalar(node, &value, position(span));
+            }
+            Event::SequenceStart(_, tag) => {
+                self.check_tag(tag.as_ref(), span);
+                self.open_collection(false);
+            }
+            Event::MappingStart(_, tag) => {
+                self.check_tag(tag.as_ref(), span);
+                self.open_collection(true);
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                self.open.pop();
+                self.complete(None, "", position(span));
+            }
+            Event::Alias(_) => self.complete(None, "", position(span)),
+            _ => {}
+        }
+    }
+}
+
+/// The repeated mapping keys among `events`, which were recorded by
+/// [`crate::events::parse_events_until_error`].
+pub(crate) fn duplicate_keys(events: &[ParseEvent]) -> Vec<Diagnostic> {
+    let options = ValidateOptions {
+        duplicate_keys: true,
+        known_tags: None,
+    };
+    let mut validator = Validator {
+        options: &options,
+        open: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    for event in events {
+        let at = (event.line, event.column);
+        match event.kind {
+            EventKind::Scalar => {
+                let text = event.value.as_deref().unwrap_or_default();
+                let node = event.representation().unwrap_or(Yaml::BadValue);
+                validator.complete_scalar(node, text, at);
+            }
+            EventKind::MappingStart | EventKind::SequenceStart => {
+                validator.open_collection(event.kind == EventKind::MappingStart);
+            }
+            EventKind::MappingEnd | EventKind::SequenceEnd => {
+                validator.open.pop();
+                validator.complete(None, "", at);
+            }
+            EventKind::Alias => validator.complete(None, "", at),
+            _ => {}
+        }
+    }
+    validator.diagnostics
+}
+
+/// Check that every document in `text` parses, and apply the structural
+/// checks in `options`.
+pub(crate) fn validate_text(text: &str, options: &ValidateOptions) -> Vec<Diagnostic> {
+    let (mut diagnostics, syntax_error) = check_structure(text, options);
+    diagnostics.extend(syntax_error);
+    diagnostics
+}
+
+/// The structural problems `options` asks for in `text`, up to its first
+/// syntax error, and that error.
+pub(crate) fn check_structure(
+    text: &str,
+    options: &ValidateOptions,
+) -> (Vec<Diagnostic>, Option<Diagnostic>) {
+    let mut validator = Validator {
+        options,
+        open: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    let mut parser = Parser::new_from_str(text);
+    let syntax_error = parser.load(&mut validator, true).err();
+    (
+        validator.diagnostics,
+        syntax_error.as_ref().map(Diagnostic::from_scan_error),
+    )
+}
+
+/// An integer column of `values`, with `NA` for `None`.
+pub(crate) fn usize_column(values: impl ExactSizeIterator<Item = Option<usize>>) -> Fallible<Sexp> {
+    let mut column = OwnedIntegerSexp::new(values.len())?;
+    for (index, value) in values.enumerate() {
+        let value = value.and_then(|value| i32::try_from(value).ok());
+        column.set_elt(index, value.unwrap_or(i32::na()))?;
+    }
+    Ok(column.into())
+}
+
+/// Validate each element of `x`, a file path or, with `text`, YAML text.
+/// Returns a list of the `valid` flags and the `input`, `file`, `line`,
+/// `column`, and `message` columns of the diagnostics.
+pub(crate) fn yaml_validate_impl(
+    x: StringSexp,
+    text: bool,
+    options: &ValidateOptions,
+) -> Fallible<Sexp> {
+    let mut valid = OwnedLogicalSexp::new(x.len())?;
+    let mut found: Vec<(usize, Diagnostic)> = Vec::new();
+    for index in 0..x.len() {
+        let input = r_ext::string_elt(&x, index)?;
+        if input.is_na() {
+            return Err(api_other("`x` must not contain NA strings"));
+        }
+        let diagnostics = if text {
+            validate_text(input, options)
+        } else {
+            match std::fs::read_to_string(input) {
+                Ok(contents) => validate_text(&contents, options),
+                Err(err) => vec![Diagnostic {
+                    line: None,
+                    column: None,
+                    message: format!("Failed to read file: {err}"),
+                }],
+            }
+        };
+        valid.set_elt(index, diagnostics.is_empty())?;
+        found.extend(
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| (index, diagnostic)),
+        );
+    }
+
+    let files = found
+        .iter()
+        .map(|(index, _)| {
+            let file = if text {
+                <&str>::na()
+            } else {
+                r_ext::string_elt(&x, *index)?
+            };
+            r_ext::string_data(file)
+        })
+        .collect::<Fallible<Vec<_>>>()?;
+    let messages = found
+        .iter()
+        .map(|(_, diagnostic)| r_ext::string_data(&diagnostic.message))
+        .collect::<Fallible<Vec<_>>>()?;
+    let mut columns = OwnedListSexp::new(5, true)?;
+    columns.set_name_and_value(
+        0,
+        "input",
+        usize_column(found.iter().map(|(index, _)| Some(index + 1)))?,
+    )?;
+    columns.set_name_and_value(1, "file", r_ext::materialize_string_vector(&files)?)?;
+    columns.set_name_and_value(
+        2,
+        "line",
+        usize_column(found.iter().map(|(_, diagnostic)| diagnostic.line))?,
+    )?;
+    columns.set_name_and_value(
+        3,
+        "column",
+        usize_column(found.iter().map(|(_, diagnostic)| diagnostic.column))?,
+    )?;
+    columns.set_name_and_value(4, "message", r_ext::materialize_string_vector(&messages)?)?;
+
+    let mut out = OwnedListSexp::new(2, true)?;
+    out.set_name_and_value(0, "valid", valid)?;
+    out.set_name_and_value(1, "diagnostics", columns)?;
+    Ok(out.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(text: &str, options: &ValidateOptions) -> Vec<(usize, usize, String)> {
+        validate_text(text, options)
+            .into_iter()
+            .map(|d| (d.line.unwrap(), d.column.unwrap(), d.message))
+            .collect()
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let options = ValidateOptions::default();
+        assert!(messages("a: [1, 2]\n---\nb: {c: d}\n", &options).is_empty());
+        let found = messages("a: 1\nb: [1, 2\n", &options);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].0, found[0].1), (3, 1));
+    }
+
+    #[test]
+    fn applies_structural_checks() {
+        let text = "a: 1\n'a': 2\n? [a]\n: 3\n? [a]\n: 4\n0x1: !x 5\n1: !y 6\nb: {a: 1}\n";
+        assert!(messages(text, &ValidateOptions::default()).is_empty());
+        let options = ValidateOptions {
+            duplicate_keys: true,
+            known_tags: Some(HashSet::from(["!x".to_string()])),
+        };
+        assert_eq!(
+            messages(text, &options),
+            [
+                (2, 1, "Duplicate mapping key `a`".to_string()),
+                (8, 1, "Duplicate mapping key `1`".to_string()),
+                (8, 7, "Unknown tag `!y`".to_string()),
+            ]
+        );
+        assert!(messages("!!str a: ! b", &options).is_empty());
+    }
+}
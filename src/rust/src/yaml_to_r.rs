@@ -1,21 +1,25 @@
-use crate::handlers::HandlerRegistry;
+use crate::config::select_profile;
+use crate::handlers::{render_tag, CoreHandlers, CoreType, Handler, HandlerRegistry, StrictTags};
+use crate::include::{is_include_tag, IncludeFrame, IncludeOptions};
+use crate::interpolate::interpolate_documents;
+use crate::loader::{load_with_source_map, NodeStyle, SourceMap};
 use crate::r_ext::{self, PreservedSexp};
 use crate::timestamp::{is_timestamp_tag, parse_timestamp_node, simplify_timestamp_sequence};
-use crate::warning::emit_warning;
 use crate::{api_other, Fallible, TIMESTAMP_SUPPORT_ENABLED};
 use saphyr::{Mapping, Scalar, Tag, Yaml, YamlLoader};
-use saphyr_parser::{Parser, ScalarStyle};
+use saphyr_parser::{Parser, ScalarStyle, ScanError};
 use savvy::{
     NotAvailableValue, OwnedIntegerSexp, OwnedListSexp, OwnedLogicalSexp, OwnedRealSexp, Sexp,
     StringSexp,
 };
 use savvy_ffi as ffi;
 use std::{
+    borrow::Cow,
     fs,
     mem::{self, MaybeUninit},
 };
 
-fn resolve_representation(node: &mut Yaml, _simplify: bool) {
+pub(crate) fn resolve_representation(node: &mut Yaml, _simplify: bool) {
     let (value, style, tag) = match mem::replace(node, Yaml::BadValue) {
         Yaml::Representation(value, style, tag) => (value, style, tag),
         other => {
@@ -59,38 +63,263 @@ fn resolve_representation(node: &mut Yaml, _simplify: bool) {
                         }
                     }
                 }
+            } else if style == ScalarStyle::Plain && is_null_text(&value) {
+                // Left unresolved so handlers still see the text; without a
+                // handler this becomes a `yaml_tagged_null`.
+                Yaml::Tagged(tag, Box::new(Yaml::Representation(value, style, None)))
             } else {
                 Yaml::Tagged(tag, Box::new(Yaml::Value(Scalar::String(value))))
             }
         }
-        None if is_plain_empty => Yaml::Value(Scalar::Null),
-        None => Yaml::value_from_cow_and_metadata(value, style, None),
+        None => resolve_untagged(value, style),
     };
 
     *node = parsed;
 }
 
-fn yaml_to_robj(
-    node: &mut Yaml,
+/// Whether a tagged plain scalar is written as a null: `~` or nothing at all.
+/// Tagged `null` text stays a string, as other tagged plain scalars do.
+fn is_null_text(value: &str) -> bool {
+    matches!(value.trim(), "" | "~")
+}
+
+fn resolve_untagged(value: Cow<'_, str>, style: ScalarStyle) -> Yaml<'_> {
+    if style == ScalarStyle::Plain && value.trim().is_empty() {
+        Yaml::Value(Scalar::Null)
+    } else {
+        Yaml::value_from_cow_and_metadata(value, style, None)
+    }
+}
+
+/// Settings shared by every node of one conversion.
+#[derive(Copy, Clone)]
+struct Converter<'a, 'r> {
     simplify: bool,
-    handlers: Option<&HandlerRegistry<'_>>,
-) -> Fallible<Sexp> {
+    handlers: Option<&'a HandlerRegistry<'r>>,
+    /// Set when handlers for core tags also apply to untagged nodes.
+    core_handlers: Option<&'a CoreHandlers<'a>>,
+    /// Set when tags without a handler are rejected instead of kept.
+    strict_tags: Option<&'a StrictTags<'r>>,
+    /// The file being read, when `!include` is enabled.
+    include: Option<&'a IncludeFrame<'a>>,
+    /// Whether `${VAR}` references in scalars are expanded before resolution.
+    interpolate: bool,
+    /// Recorded only when a handler takes a `context` argument, or when
+    /// strict mode or interpolation needs positions for their errors.
+    source_map: Option<&'a SourceMap>,
+}
+
+impl<'a> Converter<'a, '_> {
+    /// The raw handler for an unresolved scalar: the handler for its tag, or
+    /// for untagged scalars the core handler for the type it resolves to.
+    fn raw_handler<'n>(&self, node: &'n Yaml<'_>) -> Option<(Handler<'a>, &'n Tag)>
+    where
+        'a: 'n,
+    {
+        let registry = self.handlers.filter(|registry| registry.has_raw())?;
+        let Yaml::Representation(value, style, tag) = node else {
+            return None;
+        };
+        let (handler, tag) = match tag {
+            Some(tag) => (registry.get_for_tag(tag)?, tag.as_ref()),
+            None => {
+                let resolved = resolve_untagged(Cow::Borrowed(value.as_ref()), *style);
+                self.core_handler(core_type(&resolved)?)?
+            }
+        };
+        handler.is_raw().then_some((handler, tag))
+    }
+
+    fn core_handler(&self, core_type: CoreType) -> Option<(Handler<'a>, &'a Tag)> {
+        self.core_handlers?.get(core_type)
+    }
+
+    /// Whether converting `node` calls a handler on the node itself.
+    fn has_handler(&self, node: &Yaml) -> bool {
+        match node {
+            Yaml::Tagged(tag, _) => self
+                .handlers
+                .is_some_and(|registry| registry.get_for_tag(tag).is_some()),
+            _ => core_type(node).is_some_and(|core_type| self.core_handler(core_type).is_some()),
+        }
+    }
+
+    fn records_context(&self) -> bool {
+        self.source_map.is_some()
+    }
+
+    fn doc_root(&self, doc: usize) -> usize {
+        self.source_map.map_or(0, |map| map.root(doc))
+    }
+
+    fn next_sibling(&self, ordinal: usize) -> usize {
+        self.source_map
+            .map_or(ordinal + 1, |map| map.next_sibling(ordinal))
+    }
+}
+
+/// Where a node sits in its document: its position in the source map and
+/// the path of keys and indices leading to it.
+#[derive(Copy, Clone)]
+struct NodeAt<'p> {
+    ordinal: usize,
+    path: Option<&'p PathSegment<'p>>,
+    is_key: bool,
+}
+
+impl<'p> NodeAt<'p> {
+    fn root(ordinal: usize) -> Self {
+        Self {
+            ordinal,
+            path: None,
+            is_key: false,
+        }
+    }
+
+    fn child(ordinal: usize, segment: &'p PathSegment<'p>) -> Self {
+        Self {
+            ordinal,
+            path: Some(segment),
+            is_key: false,
+        }
+    }
+}
+
+struct PathSegment<'p> {
+    parent: Option<&'p PathSegment<'p>>,
+    key: PathKey<'p>,
+}
+
+enum PathKey<'p> {
+    Index(usize),
+    Name(&'p str),
+}
+
+/// Render a path as a JSON Pointer (RFC 6901), e.g. `/servers/0/port`.
+fn json_pointer(path: Option<&PathSegment<'_>>) -> String {
+    let mut segments = Vec::new();
+    let mut current = path;
+    while let Some(segment) = current {
+        segments.push(&segment.key);
+        current = segment.parent;
+    }
+    let mut pointer = String::new();
+    for key in segments.into_iter().rev() {
+        pointer.push('/');
+        match key {
+            PathKey::Index(index) => pointer.push_str(&index.to_string()),
+            PathKey::Name(name) => {
+                pointer.push_str(&name.replace('~', "~0").replace('/', "~1"));
+            }
+        }
+    }
+    pointer
+}
+
+/// The source text of a mapping key, used as its path segment.
+fn path_key_name(key: &Yaml) -> String {
+    match key {
+        Yaml::Representation(value, _, _) => value.to_string(),
+        Yaml::Value(Scalar::String(value)) => value.to_string(),
+        Yaml::Value(Scalar::Boolean(value)) => value.to_string(),
+        Yaml::Value(Scalar::Integer(value)) => value.to_string(),
+        Yaml::Value(Scalar::FloatingPoint(value)) => value.to_string(),
+        Yaml::Tagged(_, inner) => path_key_name(inner),
+        _ => String::new(),
+    }
+}
+
+/// The core-schema type of a resolved, untagged node.
+fn core_type(node: &Yaml) -> Option<CoreType> {
+    Some(match node {
+        Yaml::Value(Scalar::Null) => CoreType::Null,
+        Yaml::Value(Scalar::Boolean(_)) => CoreType::Bool,
+        Yaml::Value(Scalar::Integer(_)) => CoreType::Int,
+        Yaml::Value(Scalar::FloatingPoint(_)) => CoreType::Float,
+        Yaml::Value(Scalar::String(_)) => CoreType::Str,
+        Yaml::Sequence(_) => CoreType::Seq,
+        Yaml::Mapping(_) => CoreType::Map,
+        _ => return None,
+    })
+}
+
+fn yaml_to_robj(node: &mut Yaml, cx: Converter<'_, '_>, at: NodeAt<'_>) -> Fallible<Sexp> {
+    if let Some(value) = convert_raw(node, cx, at)? {
+        return Ok(value);
+    }
+    if cx.core_handlers.is_none() {
+        return node_to_robj(node, cx, at);
+    }
+    resolve_representation(node, cx.simplify);
+    let core_type = core_type(node);
+    let value = node_to_robj(node, cx, at)?;
+    match core_type {
+        Some(core_type) => apply_core_handler(value, core_type, cx, at),
+        None => Ok(value),
+    }
+}
+
+/// Convert `node` without applying a core-type handler to the node itself,
+/// as for the content of a tagged node.
+fn node_to_robj(node: &mut Yaml, cx: Converter<'_, '_>, at: NodeAt<'_>) -> Fallible<Sexp> {
     match node {
         Yaml::Value(scalar) => scalar_to_robj(scalar),
-        Yaml::Tagged(tag, inner) => convert_tagged(tag, inner.as_mut(), simplify, handlers),
-        Yaml::Sequence(seq) => sequence_to_robj(seq, simplify, handlers),
-        Yaml::Mapping(map) => mapping_to_robj(map, simplify, handlers),
+        Yaml::Tagged(tag, inner) => convert_tagged(tag, inner.as_mut(), cx, at),
+        Yaml::Sequence(seq) => sequence_to_robj(seq, cx, at),
+        Yaml::Mapping(map) => mapping_to_robj(map, cx, at),
         Yaml::Alias(_) => Err(api_other(
             "Internal error: encountered unresolved YAML alias node",
         )),
         Yaml::BadValue => Err(api_other("Encountered an invalid YAML scalar value")),
         Yaml::Representation(_, _, _) => {
-            resolve_representation(node, simplify);
-            yaml_to_robj(node, simplify, handlers)
+            resolve_representation(node, cx.simplify);
+            node_to_robj(node, cx, at)
         }
     }
 }
 
+fn apply_core_handler(
+    value: Sexp,
+    core_type: CoreType,
+    cx: Converter<'_, '_>,
+    at: NodeAt<'_>,
+) -> Fallible<Sexp> {
+    match cx.core_handler(core_type) {
+        Some((handler, tag)) => apply_handler(handler, tag, value, None, cx, at),
+        None => Ok(value),
+    }
+}
+
+/// Call a raw handler on the source text of an unresolved scalar, if one
+/// applies.
+fn convert_raw(node: &Yaml, cx: Converter<'_, '_>, at: NodeAt<'_>) -> Fallible<Option<Sexp>> {
+    let Some((handler, tag)) = cx.raw_handler(node) else {
+        return Ok(None);
+    };
+    let Yaml::Representation(text, style, _) = node else {
+        unreachable!("raw handlers only apply to unresolved scalars");
+    };
+    let style = NodeStyle::Scalar(*style).as_str();
+    let text = r_ext::string_scalar(text)?;
+    apply_handler(handler, tag, text, Some(style), cx, at).map(Some)
+}
+
+fn apply_handler(
+    handler: Handler<'_>,
+    tag: &Tag,
+    value: Sexp,
+    style: Option<&str>,
+    cx: Converter<'_, '_>,
+    at: NodeAt<'_>,
+) -> Fallible<Sexp> {
+    if !handler.wants_context() {
+        return handler.call(tag, value, style, None);
+    }
+    let value = PreservedSexp::new(value);
+    let context = PreservedSexp::new(node_context(cx, at, tag)?);
+    handler.call(tag, value.value(), style, Some(context.value()))
+}
+
 fn scalar_to_robj(scalar: &Scalar) -> Fallible<Sexp> {
     match scalar {
         Scalar::Null => Ok(r_ext::null()),
@@ -124,19 +353,21 @@ fn prepare_list_element(
     node: &mut Yaml,
     index: usize,
     length: usize,
-    simplify: bool,
-    handlers: Option<&HandlerRegistry<'_>>,
+    cx: Converter<'_, '_>,
+    at: NodeAt<'_>,
     target: &mut Option<OwnedListSexp>,
 ) -> Fallible<bool> {
-    resolve_representation(node, simplify);
-    if matches!(node, Yaml::Value(_)) {
-        return Ok(true);
+    if cx.raw_handler(node).is_none() {
+        resolve_representation(node, cx.simplify);
+        if matches!(node, Yaml::Value(_)) && !cx.has_handler(node) {
+            return Ok(true);
+        }
     }
 
     if target.is_none() {
         *target = Some(OwnedListSexp::new(length, false)?);
     }
-    let value = yaml_to_robj(node, simplify, handlers)?;
+    let value = yaml_to_robj(node, cx, at)?;
     target.as_mut().unwrap().set_value(index, value)?;
     // The R value is rooted now; retain only a marker for the final batch.
     *node = Yaml::BadValue;
@@ -150,17 +381,30 @@ fn prepared_list_element<'a>(node: &'a Yaml<'_>) -> Fallible<r_ext::ListElement<
     }
 }
 
+/// Convert sequence items, or whole documents when `parent` is `None`.
 fn materialize_node_list(
     nodes: &mut [Yaml],
-    simplify: bool,
-    handlers: Option<&HandlerRegistry<'_>>,
+    cx: Converter<'_, '_>,
+    parent: Option<NodeAt<'_>>,
 ) -> Fallible<Sexp> {
     let length = nodes.len();
     let mut target = None;
     let mut has_immediate = false;
+    let mut ordinal = parent.map_or(0, |parent| parent.ordinal + 1);
     for (index, node) in nodes.iter_mut().enumerate() {
-        has_immediate |=
-            prepare_list_element(node, index, length, simplify, handlers, &mut target)?;
+        let segment;
+        let at = match parent {
+            Some(parent) => {
+                segment = PathSegment {
+                    parent: parent.path,
+                    key: PathKey::Index(index),
+                };
+                NodeAt::child(ordinal, &segment)
+            }
+            None => NodeAt::root(cx.doc_root(index)),
+        };
+        has_immediate |= prepare_list_element(node, index, length, cx, at, &mut target)?;
+        ordinal = cx.next_sibling(ordinal);
     }
     if !has_immediate {
         if let Some(target) = target.take() {
@@ -174,11 +418,7 @@ fn materialize_node_list(
     r_ext::materialize_list(target.as_ref(), &elements, None)
 }
 
-fn sequence_to_robj(
-    seq: &mut [Yaml],
-    simplify_seqs: bool,
-    handlers: Option<&HandlerRegistry<'_>>,
-) -> Fallible<Sexp> {
+fn sequence_to_robj(seq: &mut [Yaml], cx: Converter<'_, '_>, at: NodeAt<'_>) -> Fallible<Sexp> {
     #[derive(Copy, Clone, PartialEq, Eq)]
     enum RVectorType {
         List,
@@ -189,15 +429,19 @@ fn sequence_to_robj(
     }
 
     let mut out_type = RVectorType::List;
-    let mut simplify = simplify_seqs;
+    let mut simplify = cx.simplify;
 
-    if !simplify_seqs {
-        return materialize_node_list(seq, simplify_seqs, handlers);
+    if !cx.simplify {
+        return materialize_node_list(seq, cx, Some(at));
     }
 
     // iterate over the vec once to see if we can simplify, fail early/fast if not
     for node in seq.iter_mut() {
-        resolve_representation(node, simplify_seqs);
+        if cx.raw_handler(node).is_some() {
+            simplify = false;
+            break;
+        }
+        resolve_representation(node, cx.simplify);
         match node {
             Yaml::Tagged(_, _) => {
                 simplify = false;
@@ -244,14 +488,18 @@ fn sequence_to_robj(
 
     if simplify {
         match out_type {
+            // Handlers for untagged scalars receive the whole vector.
             RVectorType::Logical => {
-                return simplified_logical_sequence_to_robj(seq);
+                let value = simplified_logical_sequence_to_robj(seq)?;
+                return apply_core_handler(value, CoreType::Bool, cx, at);
             }
             RVectorType::Integer => {
-                return simplified_integer_sequence_to_robj(seq);
+                let value = simplified_integer_sequence_to_robj(seq)?;
+                return apply_core_handler(value, CoreType::Int, cx, at);
             }
             RVectorType::Double => {
-                return simplified_double_sequence_to_robj(seq);
+                let value = simplified_double_sequence_to_robj(seq)?;
+                return apply_core_handler(value, CoreType::Float, cx, at);
             }
             RVectorType::Character => {
                 let values: Vec<_> = seq
@@ -262,7 +510,8 @@ fn sequence_to_robj(
                         _ => unreachable!("expected only strings or nulls"),
                     })
                     .collect::<Fallible<Vec<_>>>()?;
-                return r_ext::materialize_string_vector(&values);
+                let value = r_ext::materialize_string_vector(&values)?;
+                return apply_core_handler(value, CoreType::Str, cx, at);
             }
             RVectorType::List => {}
         }
@@ -278,7 +527,7 @@ fn sequence_to_robj(
     }
 
     // can't simplify, return a list
-    materialize_node_list(seq, simplify_seqs, handlers)
+    materialize_node_list(seq, cx, Some(at))
 }
 
 fn simplified_logical_sequence_to_robj(seq: &[Yaml]) -> Fallible<Sexp> {
@@ -359,22 +608,31 @@ enum KeyHandlerResult {
     Preserved(PreservedSexp),
 }
 
-fn mapping_to_robj(
-    map: &mut Mapping,
-    simplify: bool,
-    handlers: Option<&HandlerRegistry<'_>>,
-) -> Fallible<Sexp> {
+fn mapping_to_robj(map: &mut Mapping, cx: Converter<'_, '_>, at: NodeAt<'_>) -> Fallible<Sexp> {
     let len = map.len();
 
-    let all_plain_string_keys = map
-        .iter()
-        .all(|(key, _)| matches!(key, Yaml::Value(Scalar::String(_))));
+    // Keys go through the general path when a `!!str` handler may apply.
+    let all_plain_string_keys = cx.core_handlers.is_none()
+        && map
+            .iter()
+            .all(|(key, _)| matches!(key, Yaml::Value(Scalar::String(_))));
 
     if all_plain_string_keys {
         let mut entries: Vec<_> = mem::take(map).into_iter().collect();
         let mut target = None;
-        for (index, (_, value)) in entries.iter_mut().enumerate() {
-            prepare_list_element(value, index, len, simplify, handlers, &mut target)?;
+        let mut ordinal = at.ordinal + 1;
+        for (index, (key, value)) in entries.iter_mut().enumerate() {
+            let Yaml::Value(Scalar::String(name)) = &*key else {
+                unreachable!("checked for only plain string keys");
+            };
+            let value_ordinal = cx.next_sibling(ordinal);
+            ordinal = cx.next_sibling(value_ordinal);
+            let segment = PathSegment {
+                parent: at.path,
+                key: PathKey::Name(name.as_ref()),
+            };
+            let value_at = NodeAt::child(value_ordinal, &segment);
+            prepare_list_element(value, index, len, cx, value_at, &mut target)?;
         }
         let elements = entries
             .iter()
@@ -394,38 +652,62 @@ fn mapping_to_robj(
     let mut keys: Vec<Yaml> = Vec::with_capacity(len);
     let mut values: Vec<Yaml> = Vec::with_capacity(len);
     let mut key_handler_results: Vec<Option<KeyHandlerResult>> = Vec::with_capacity(len);
+    // Key positions and path names are kept for converting keys in the 3rd pass.
+    let mut key_ordinals: Vec<usize> = Vec::with_capacity(len);
+    let mut key_names: Vec<String> = Vec::new();
     let mut value_target = None;
+    let mut ordinal = at.ordinal + 1;
 
     // 1st pass: resolve keys/values while consuming the mapping to avoid cloning keys.
     for (i, (mut key, mut value)) in mem::take(map).into_iter().enumerate() {
-        resolve_representation(&mut key, simplify);
+        let key_name = if cx.records_context() {
+            path_key_name(&key)
+        } else {
+            String::new()
+        };
+        let raw_key = cx.raw_handler(&key).is_some();
+        if !raw_key {
+            resolve_representation(&mut key, cx.simplify);
+        }
+        let key_ordinal = ordinal;
+        let value_ordinal = cx.next_sibling(key_ordinal);
+        ordinal = cx.next_sibling(value_ordinal);
+        let segment = PathSegment {
+            parent: at.path,
+            key: PathKey::Name(&key_name),
+        };
+        let key_at = NodeAt {
+            is_key: true,
+            ..NodeAt::child(key_ordinal, &segment)
+        };
 
         // If the key is tagged and a handler exists, apply it to the key itself.
         // Keep the handled value alive so we can borrow its string data when
         // constructing R names without allocating.
-        let key_handler_result = if let (Some(registry), Yaml::Tagged(tag, _)) = (handlers, &key) {
-            if let Some(handler) = registry.get_for_tag(tag.as_ref()) {
-                let key_obj = yaml_to_robj(&mut key, simplify, handlers)?;
-                let handled = PreservedSexp::new(registry.apply(handler, key_obj)?);
-                Some(if let Some(name) = name_if_bare_string(&handled.value())? {
-                    KeyHandlerResult::BareString {
-                        name,
-                        _guard: handled,
-                    }
-                } else {
-                    KeyHandlerResult::Preserved(handled)
-                })
+        let key_handler_result = if raw_key || cx.has_handler(&key) {
+            // `yaml_to_robj()` applies the handler.
+            let handled = PreservedSexp::new(yaml_to_robj(&mut key, cx, key_at)?);
+            Some(if let Some(name) = name_if_bare_string(&handled.value())? {
+                KeyHandlerResult::BareString {
+                    name,
+                    _guard: handled,
+                }
             } else {
-                None
-            }
+                KeyHandlerResult::Preserved(handled)
+            })
         } else {
             None
         };
 
-        prepare_list_element(&mut value, i, len, simplify, handlers, &mut value_target)?;
+        let value_at = NodeAt::child(value_ordinal, &segment);
+        prepare_list_element(&mut value, i, len, cx, value_at, &mut value_target)?;
         keys.push(key);
         values.push(value);
         key_handler_results.push(key_handler_result);
+        key_ordinals.push(key_ordinal);
+        if cx.records_context() {
+            key_names.push(key_name);
+        }
     }
 
     // 2nd pass: build names as &str from keys.
@@ -485,7 +767,15 @@ fn mapping_to_robj(
                 keys_target.as_mut().unwrap().set_value(i, value.value())?;
             }
             None => {
-                prepare_list_element(key, i, len, simplify, handlers, &mut keys_target)?;
+                let segment = PathSegment {
+                    parent: at.path,
+                    key: PathKey::Name(key_names.get(i).map_or("", String::as_str)),
+                };
+                let key_at = NodeAt {
+                    is_key: true,
+                    ..NodeAt::child(key_ordinals[i], &segment)
+                };
+                prepare_list_element(key, i, len, cx, key_at, &mut keys_target)?;
             }
         }
     }
@@ -515,14 +805,26 @@ fn name_if_bare_string(robj: &Sexp) -> Fallible<Option<&'static str>> {
 fn convert_tagged(
     tag: &Tag,
     node: &mut Yaml,
-    simplify: bool,
-    handlers: Option<&HandlerRegistry<'_>>,
+    cx: Converter<'_, '_>,
+    at: NodeAt<'_>,
 ) -> Fallible<Sexp> {
-    if let Some(registry) = handlers {
-        if let Some(handler) = registry.get_for_tag(tag) {
-            let value = yaml_to_robj(node, simplify, handlers)?;
-            return registry.apply(handler, value);
+    let handler = cx.handlers.and_then(|registry| registry.get_for_tag(tag));
+    // A handler named `!include` replaces the built-in include; prefix and
+    // catch-all handlers do not.
+    let include_handler = handler.is_some_and(|handler| handler.is_exact());
+    if let Some(frame) = cx
+        .include
+        .filter(|_| is_include_tag(tag) && !include_handler)
+    {
+        return convert_include(node, frame, cx);
+    }
+
+    if let Some(handler) = handler {
+        if let Yaml::Representation(text, _, None) = node {
+            *node = Yaml::Value(Scalar::String(mem::take(text)));
         }
+        let value = node_to_robj(node, cx, at)?;
+        return apply_handler(handler, tag, value, None, cx, at);
     }
 
     if TIMESTAMP_SUPPORT_ENABLED && is_timestamp_tag(tag) {
@@ -533,7 +835,13 @@ fn convert_tagged(
         }
     }
 
-    let value = yaml_to_robj(node, simplify, handlers)?;
+    if let Some(strict_tags) = cx.strict_tags {
+        if !tag.is_yaml_core_schema() && !is_non_specific_tag(tag) && !strict_tags.allows(tag) {
+            return Err(strict_tag_error(tag, cx, at));
+        }
+    }
+
+    let value = node_to_robj(node, cx, at)?;
     if tag.is_yaml_core_schema() {
         return match tag.suffix.as_str() {
             "str" | "null" | "bool" | "int" | "float" | "seq" | "map" => Ok(value),
@@ -548,6 +856,95 @@ fn convert_tagged(
     set_yaml_tag_attr(value, tag)
 }
 
+/// Replace an `!include path` node with the first document of that file.
+fn convert_include(node: &Yaml, frame: &IncludeFrame<'_>, cx: Converter<'_, '_>) -> Fallible<Sexp> {
+    let Yaml::Value(Scalar::String(target)) = node else {
+        return Err(api_other(format!(
+            "`!include` in `{file}` must tag a file path string",
+            file = frame.file().display()
+        )));
+    };
+    let frame = frame.include(target)?;
+    let file = frame.file();
+    let contents = fs::read_to_string(file)
+        .map_err(|err| api_other(format!("Failed to read `{}`: {err}", file.display())))?;
+    let parse_error = |err| api_other(format!("YAML parse error in `{}`: {err}", file.display()));
+    let cx = Converter {
+        include: Some(&frame),
+        ..cx
+    };
+    if !needs_source_map(cx) {
+        let mut docs = scan_yaml_documents(&contents, false).map_err(parse_error)?;
+        return included_document(&mut docs, cx);
+    }
+    let (mut docs, source_map) = load_with_source_map(&contents, false).map_err(parse_error)?;
+    let cx = Converter {
+        source_map: Some(&source_map),
+        ..cx
+    };
+    included_document(&mut docs, cx)
+}
+
+fn included_document(docs: &mut [Yaml<'_>], cx: Converter<'_, '_>) -> Fallible<Sexp> {
+    expand_variables(docs, cx)?;
+    match docs.first_mut() {
+        Some(doc) => yaml_to_robj(doc, cx, NodeAt::root(cx.doc_root(0))),
+        None => Ok(r_ext::null()),
+    }
+}
+
+/// The non-specific `!` tag only marks a scalar as a string.
+fn is_non_specific_tag(tag: &Tag) -> bool {
+    tag.handle.is_empty() && tag.suffix.as_str() == "!"
+}
+
+fn strict_tag_error(tag: &Tag, cx: Converter<'_, '_>, at: NodeAt<'_>) -> savvy::Error {
+    let position = cx
+        .source_map
+        .and_then(|map| map.node(at.ordinal))
+        .map(|info| {
+            format!(
+                " at line {}, column {}",
+                info.start.line(),
+                info.start.col() + 1
+            )
+        })
+        .unwrap_or_default();
+    api_other(format!(
+        "Tag `{tag}`{position} is not allowed by `strict_tags`; add a handler for it or list it in `strict_tags`",
+        tag = render_tag(tag)
+    ))
+}
+
+const CONTEXT_NAMES: [&str; 7] = ["tag", "path", "line", "column", "style", "is_key", "anchor"];
+
+/// Build the `context` argument passed to handlers that declare one.
+fn node_context(cx: Converter<'_, '_>, at: NodeAt<'_>, tag: &Tag) -> Fallible<Sexp> {
+    let info = cx.source_map.and_then(|map| map.node(at.ordinal));
+    let (line, column) = info.map_or((i32::na(), i32::na()), |info| {
+        let line = i32::try_from(info.start.line()).unwrap_or(i32::na());
+        let column = i32::try_from(info.start.col() + 1).unwrap_or(i32::na());
+        (line, column)
+    });
+    let style = info.map_or(<&str>::na(), |info| info.style.as_str());
+    let anchor = info
+        .and_then(|info| info.anchor.as_deref())
+        .unwrap_or(<&str>::na());
+
+    let mut context = OwnedListSexp::new(CONTEXT_NAMES.len(), true)?;
+    context.set_value(0, r_ext::string_scalar(&render_tag(tag))?)?;
+    context.set_value(1, r_ext::string_scalar(&json_pointer(at.path))?)?;
+    context.set_value(2, r_ext::integer_scalar(line)?)?;
+    context.set_value(3, r_ext::integer_scalar(column)?)?;
+    context.set_value(4, r_ext::string_scalar(style)?)?;
+    context.set_value(5, r_ext::logical_scalar(at.is_key)?)?;
+    context.set_value(6, r_ext::string_scalar(anchor)?)?;
+    for (index, name) in CONTEXT_NAMES.iter().enumerate() {
+        context.set_name(index, name)?;
+    }
+    Ok(context.into())
+}
+
 #[cfg_attr(not(test), allow(dead_code))]
 fn is_core_string_tag(tag: &Tag) -> bool {
     tag.is_yaml_core_schema() && tag.suffix.as_str() == "str"
@@ -558,22 +955,17 @@ fn is_core_null_tag(tag: &Tag) -> bool {
 }
 
 fn set_yaml_tag_attr(value: Sexp, tag: &Tag) -> Fallible<Sexp> {
-    let mut rendered_tag = String::with_capacity(tag.handle.len() + tag.suffix.len());
-    rendered_tag.push_str(tag.handle.as_str());
-    rendered_tag.push_str(tag.suffix.as_str());
+    let rendered_tag = render_tag(tag);
 
     if rendered_tag.is_empty() {
         return Ok(value);
     }
 
     if value.is_null() {
-        if !is_core_null_tag(tag) {
-            let warn_msg = format!(
-                "yaml12: discarding tag `{rendered_tag}` on null scalar; R NULL cannot carry attributes"
-            );
-            emit_warning(&warn_msg)?;
+        if is_core_null_tag(tag) {
+            return Ok(value);
         }
-        return Ok(value);
+        return tagged_null(&rendered_tag);
     }
 
     let value_guard = PreservedSexp::new(value);
@@ -583,6 +975,17 @@ fn set_yaml_tag_attr(value: Sexp, tag: &Tag) -> Fallible<Sexp> {
     Ok(value)
 }
 
+/// R `NULL` cannot carry attributes, so a tagged null is an empty list of
+/// class `yaml_tagged_null` holding the tag.
+fn tagged_null(rendered_tag: &str) -> Fallible<Sexp> {
+    let value = PreservedSexp::new(OwnedListSexp::new(0, false)?.into());
+    let tag_value = PreservedSexp::new(r_ext::string_scalar(rendered_tag)?);
+    let mut value = value.value();
+    r_ext::set_attrib_sym(&mut value, r_ext::sym_yaml_tag(), tag_value.value())?;
+    r_ext::set_class(&mut value, ["yaml_tagged_null"])?;
+    Ok(value)
+}
+
 fn wrap_unsupported(err: savvy::Error) -> savvy::Error {
     match err {
         savvy::Error::Aborted(token) => savvy::Error::Aborted(token),
@@ -591,53 +994,137 @@ fn wrap_unsupported(err: savvy::Error) -> savvy::Error {
 }
 
 fn load_yaml_documents<'input>(text: &'input str, multi: bool) -> Fallible<Vec<Yaml<'input>>> {
+    scan_yaml_documents(text, multi).map_err(|err| api_other(format!("YAML parse error: {err}")))
+}
+
+pub(crate) fn scan_yaml_documents<'input>(
+    text: &'input str,
+    multi: bool,
+) -> Result<Vec<Yaml<'input>>, ScanError> {
     let mut parser = Parser::new_from_str(text);
     let mut loader = YamlLoader::default();
     loader.early_parse(false);
-    parser
-        .load(&mut loader, multi)
-        .map_err(|err| api_other(format!("YAML parse error: {err}")))?;
+    parser.load(&mut loader, multi)?;
     Ok(loader.into_documents())
 }
 
+/// Conversion options shared by `parse_yaml()` and `read_yaml()`.
+pub(crate) struct ParseOptions {
+    pub(crate) multi: bool,
+    pub(crate) simplify: bool,
+    pub(crate) handle_untagged: bool,
+    pub(crate) strict_tags: Sexp,
+    pub(crate) interpolate: bool,
+}
+
+impl ParseOptions {
+    /// Options for functions that take only `simplify` and `handlers`.
+    pub(crate) fn basic(simplify: bool) -> Self {
+        Self {
+            multi: false,
+            simplify,
+            handle_untagged: false,
+            strict_tags: r_ext::null(),
+            interpolate: false,
+        }
+    }
+}
+
+fn convert_with_handlers(
+    handlers: &Sexp,
+    options: &ParseOptions,
+    convert: impl FnOnce(Converter<'_, '_>) -> Fallible<Sexp>,
+) -> Fallible<Sexp> {
+    let handler_registry = HandlerRegistry::from_robj(handlers)?;
+    let core_handlers = handler_registry
+        .as_ref()
+        .filter(|_| options.handle_untagged)
+        .and_then(HandlerRegistry::core_handlers);
+    let strict_tags = StrictTags::from_robj(&options.strict_tags)?;
+    convert(Converter {
+        simplify: options.simplify,
+        handlers: handler_registry.as_ref(),
+        core_handlers: core_handlers.as_ref(),
+        strict_tags: strict_tags.as_ref(),
+        include: None,
+        interpolate: options.interpolate,
+        source_map: None,
+    })
+}
+
 pub(crate) fn parse_yaml_impl(
     text: StringSexp,
-    multi: bool,
-    simplify: bool,
     handlers: Sexp,
+    options: ParseOptions,
 ) -> Fallible<Sexp> {
-    let handler_registry = HandlerRegistry::from_robj(&handlers)?;
-    let handlers = handler_registry.as_ref();
-
-    match text.len() {
+    let multi = options.multi;
+    convert_with_handlers(&handlers, &options, |cx| match text.len() {
         0 => Ok(r_ext::null()),
         1 => {
             let first = r_ext::string_elt(&text, 0)?;
             if first.is_na() {
                 return Err(api_other("`text` must not contain NA strings"));
             }
-            let docs = load_yaml_documents(first, multi)?;
-            docs_to_robj(docs, multi, simplify, handlers)
+            convert_text(first, multi, cx)
+        }
+        _ if needs_source_map(cx) => {
+            // Source positions index into a single string.
+            let joined: String = joined_lines_iter(&text)?.collect();
+            convert_text(&joined, multi, cx)
         }
         _ => {
             let joined_iter = joined_lines_iter(&text)?;
             let docs = load_yaml_documents_iter(joined_iter, multi)?;
-            docs_to_robj(docs, multi, simplify, handlers)
+            docs_to_robj(docs, multi, cx)
         }
+    })
+}
+
+/// Convert YAML `text` that is already a single string.
+pub(crate) fn parse_text_impl(text: &str, handlers: Sexp, options: ParseOptions) -> Fallible<Sexp> {
+    convert_with_handlers(&handlers, &options, |cx| {
+        convert_text(text, options.multi, cx)
+    })
+}
+
+fn needs_source_map(cx: Converter<'_, '_>) -> bool {
+    cx.strict_tags.is_some()
+        || cx.interpolate
+        || cx.handlers.is_some_and(HandlerRegistry::wants_context)
+}
+
+fn convert_text(text: &str, multi: bool, cx: Converter<'_, '_>) -> Fallible<Sexp> {
+    if !needs_source_map(cx) {
+        let docs = load_yaml_documents(text, multi)?;
+        return docs_to_robj(docs, multi, cx);
     }
+    let (docs, source_map) = load_with_source_map(text, multi)
+        .map_err(|err| api_other(format!("YAML parse error: {err}")))?;
+    let cx = Converter {
+        source_map: Some(&source_map),
+        ..cx
+    };
+    docs_to_robj(docs, multi, cx)
 }
 
-fn docs_to_robj(
-    mut docs: Vec<Yaml<'_>>,
-    multi: bool,
-    simplify: bool,
-    handlers: Option<&HandlerRegistry<'_>>,
-) -> Fallible<Sexp> {
+/// Expand `${VAR}` references when interpolation is on; the source map is
+/// always recorded in that case.
+fn expand_variables(docs: &mut [Yaml<'_>], cx: Converter<'_, '_>) -> Fallible<()> {
+    match cx.source_map.filter(|_| cx.interpolate) {
+        Some(source_map) => interpolate_documents(docs, source_map),
+        None => Ok(()),
+    }
+}
+
+fn docs_to_robj(mut docs: Vec<Yaml<'_>>, multi: bool, cx: Converter<'_, '_>) -> Fallible<Sexp> {
+    expand_variables(&mut docs, cx)?;
     if multi {
-        materialize_node_list(&mut docs, simplify, handlers).map_err(wrap_unsupported)
+        materialize_node_list(&mut docs, cx, None).map_err(wrap_unsupported)
     } else {
         match docs.first_mut() {
-            Some(doc) => yaml_to_robj(doc, simplify, handlers).map_err(wrap_unsupported),
+            Some(doc) => {
+                yaml_to_robj(doc, cx, NodeAt::root(cx.doc_root(0))).map_err(wrap_unsupported)
+            }
             None => Ok(r_ext::null()),
         }
     }
@@ -703,17 +1190,116 @@ where
 
 pub(crate) fn read_yaml_impl(
     path: &str,
-    multi: bool,
-    simplify: bool,
     handlers: Sexp,
+    options: ParseOptions,
+    include: Sexp,
 ) -> Fallible<Sexp> {
-    let handler_registry = HandlerRegistry::from_robj(&handlers)?;
-    let handlers = handler_registry.as_ref();
-
     let contents = fs::read_to_string(path)
         .map_err(|err| api_other(format!("Failed to read `{path}`: {err}")))?;
-    let docs = load_yaml_documents(&contents, multi)?;
-    docs_to_robj(docs, multi, simplify, handlers)
+    read_text_impl(path, &contents, handlers, options, include)
+}
+
+/// Convert `contents`, read from the file at `path`.
+pub(crate) fn read_text_impl(
+    path: &str,
+    contents: &str,
+    handlers: Sexp,
+    options: ParseOptions,
+    include: Sexp,
+) -> Fallible<Sexp> {
+    let include = IncludeOptions::from_robj(&include)?;
+    convert_with_handlers(&handlers, &options, |cx| {
+        let Some(include) = include else {
+            return convert_text(contents, options.multi, cx);
+        };
+        let frame = include.top_frame(path)?;
+        let cx = Converter {
+            include: Some(&frame),
+            ..cx
+        };
+        convert_text(contents, options.multi, cx)
+    })
+}
+
+/// Convert a node built in Rust, rather than loaded from text, to R.
+pub(crate) fn yaml_value_to_robj(node: &mut Yaml, simplify: bool) -> Fallible<Sexp> {
+    let cx = Converter {
+        simplify,
+        handlers: None,
+        core_handlers: None,
+        strict_tags: None,
+        include: None,
+        interpolate: false,
+        source_map: None,
+    };
+    yaml_to_robj(node, cx, NodeAt::root(0)).map_err(wrap_unsupported)
+}
+
+/// Convert `node`, detached from its document, with `handlers`. Source
+/// positions are not tracked, so a handler's `context` describes the node as
+/// a document root.
+pub(crate) fn detached_to_robj(
+    node: &mut Yaml,
+    handlers: &Sexp,
+    options: &ParseOptions,
+) -> Fallible<Sexp> {
+    convert_with_handlers(handlers, options, |cx| {
+        yaml_to_robj(node, cx, NodeAt::root(0)).map_err(wrap_unsupported)
+    })
+}
+
+/// Convert detached `nodes` into an R list, named by `names` when given; see
+/// [`detached_to_robj()`].
+pub(crate) fn detached_list_to_robj(
+    nodes: &mut [Yaml],
+    names: Option<&[String]>,
+    handlers: &Sexp,
+    options: &ParseOptions,
+) -> Fallible<Sexp> {
+    convert_with_handlers(handlers, options, |cx| {
+        let mut list = OwnedListSexp::new(nodes.len(), names.is_some())?;
+        for (index, node) in nodes.iter_mut().enumerate() {
+            let value = yaml_to_robj(node, cx, NodeAt::root(0)).map_err(wrap_unsupported)?;
+            match names {
+                Some(names) => list.set_name_and_value(index, &names[index], value)?,
+                None => list.set_value(index, value)?,
+            }
+        }
+        Ok(list.into())
+    })
+}
+
+/// Read the `config` profile of the configuration file at `path`, merged
+/// over the profiles it inherits from.
+pub(crate) fn read_config_impl(
+    path: &str,
+    config: &str,
+    handlers: Sexp,
+    options: ParseOptions,
+) -> Fallible<Sexp> {
+    convert_with_handlers(&handlers, &options, |cx| {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| api_other(format!("Failed to read `{path}`: {err}")))?;
+        let mut docs = if cx.interpolate {
+            let (mut docs, source_map) = load_with_source_map(&contents, false)
+                .map_err(|err| api_other(format!("YAML parse error: {err}")))?;
+            interpolate_documents(&mut docs, &source_map)?;
+            docs
+        } else {
+            load_yaml_documents(&contents, false)?
+        };
+        if docs.is_empty() {
+            return Err(api_other(format!("Configuration file `{path}` is empty")));
+        }
+        let mut merged = select_profile(docs.swap_remove(0), config)?;
+        // Source positions do not survive merging, so none are reported.
+        let cx = Converter {
+            source_map: None,
+            interpolate: false,
+            ..cx
+        };
+        yaml_to_robj(&mut merged, cx, NodeAt::root(0)).map_err(wrap_unsupported)
+    })
 }
 
 #[cfg(test)]
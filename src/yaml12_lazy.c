@@ -0,0 +1,92 @@
+#include <Rinternals.h>
+#include <Rversion.h>
+#include <R_ext/Rdynload.h>
+
+#if R_VERSION >= R_Version(4, 3, 0)
+#include <R_ext/Altrep.h>
+#endif
+
+SEXP handle_result(SEXP res_);
+
+/* Defined in src/rust/src/lazy.rs; returns a Savvy tagged result. */
+SEXP yaml12_lazy_list_elt(SEXP state, R_xlen_t index);
+
+void yaml12_init_lazy_list(DllInfo *dll);
+SEXP yaml12_make_lazy_list(SEXP state, SEXP names, R_xlen_t length);
+
+#if R_VERSION >= R_Version(4, 3, 0)
+
+/*
+ * A list whose elements are converted from retained YAML nodes on first
+ * access. data1 is the external pointer to the Rust state and data2 the
+ * cache of converted elements. A cache slot still holding the state pointer
+ * has not been converted; no converted value can be that pointer.
+ */
+static R_altrep_class_t yaml12_lazy_list_class;
+
+static R_xlen_t yaml12_lazy_list_length(SEXP x) {
+    return XLENGTH(R_altrep_data2(x));
+}
+
+static SEXP yaml12_lazy_list_get(SEXP x, R_xlen_t i) {
+    SEXP state = R_altrep_data1(x);
+    SEXP cache = R_altrep_data2(x);
+    SEXP value = VECTOR_ELT(cache, i);
+    if (value == state) {
+        /* Raises the conversion error, if any, from a C frame. */
+        value = handle_result(yaml12_lazy_list_elt(state, i));
+        SET_VECTOR_ELT(cache, i, value);
+    }
+    return value;
+}
+
+static void yaml12_lazy_list_set(SEXP x, R_xlen_t i, SEXP value) {
+    SET_VECTOR_ELT(R_altrep_data2(x), i, value);
+}
+
+static void *yaml12_lazy_list_dataptr(SEXP x, Rboolean writable) {
+    /* Convert everything so the cache can stand in for the list. */
+    R_xlen_t length = yaml12_lazy_list_length(x);
+    for (R_xlen_t i = 0; i < length; i++) {
+        yaml12_lazy_list_get(x, i);
+    }
+    return (void *)DATAPTR_RO(R_altrep_data2(x));
+}
+
+static const void *yaml12_lazy_list_dataptr_or_null(SEXP x) {
+    return NULL;
+}
+
+void yaml12_init_lazy_list(DllInfo *dll) {
+    yaml12_lazy_list_class =
+        R_make_altlist_class("yaml12_lazy_list", "yaml12", dll);
+    R_set_altrep_Length_method(yaml12_lazy_list_class, yaml12_lazy_list_length);
+    R_set_altlist_Elt_method(yaml12_lazy_list_class, yaml12_lazy_list_get);
+    R_set_altlist_Set_elt_method(yaml12_lazy_list_class, yaml12_lazy_list_set);
+    R_set_altvec_Dataptr_method(yaml12_lazy_list_class, yaml12_lazy_list_dataptr);
+    R_set_altvec_Dataptr_or_null_method(yaml12_lazy_list_class,
+                                        yaml12_lazy_list_dataptr_or_null);
+}
+
+SEXP yaml12_make_lazy_list(SEXP state, SEXP names, R_xlen_t length) {
+    SEXP cache = PROTECT(Rf_allocVector(VECSXP, length));
+    for (R_xlen_t i = 0; i < length; i++) {
+        SET_VECTOR_ELT(cache, i, state);
+    }
+    SEXP list = PROTECT(R_new_altrep(yaml12_lazy_list_class, state, cache));
+    if (names != R_NilValue) {
+        Rf_setAttrib(list, R_NamesSymbol, names);
+    }
+    UNPROTECT(2);
+    return list;
+}
+
+#else
+
+void yaml12_init_lazy_list(DllInfo *dll) {}
+
+SEXP yaml12_make_lazy_list(SEXP state, SEXP names, R_xlen_t length) {
+    Rf_error("`lazy = TRUE` requires R 4.3.0 or later");
+}
+
+#endif
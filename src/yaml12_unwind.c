@@ -78,6 +78,13 @@ SEXP yaml12_materialize_list(SEXP target,
                              const struct yaml12_string_data *names,
                              R_xlen_t length);
 SEXP yaml12_call1(SEXP function, SEXP argument);
+SEXP yaml12_call_handler(SEXP function, SEXP argument, SEXP tag, SEXP style,
+                         SEXP context);
+int yaml12_has_formal(SEXP function, const char *name);
+int yaml12_takes_second_argument(SEXP function);
+SEXP yaml12_deparse(SEXP value);
+SEXP yaml12_make_lazy_list(SEXP state, SEXP names, R_xlen_t length);
+SEXP yaml12_new_lazy_list(SEXP state, SEXP names, R_xlen_t length);
 
 static SEXP yaml12_unwind_protect(SEXP (*fun)(void *data), void *data) {
     SEXP token = R_MakeUnwindCont();
@@ -355,3 +362,149 @@ SEXP yaml12_call1(SEXP function, SEXP argument) {
     UNPROTECT(1);
     return result;
 }
+
+struct yaml12_call_handler_data {
+    SEXP function;
+    SEXP argument;
+    SEXP tag;
+    SEXP style;
+    SEXP context;
+};
+
+static SEXP yaml12_append_argument(SEXP last, SEXP value, const char *name) {
+    SETCDR(last, Rf_cons(value, R_NilValue));
+    last = CDR(last);
+    if (name != NULL) {
+        SET_TAG(last, Rf_install(name));
+    }
+    return last;
+}
+
+static SEXP yaml12_call_handler_impl(void *data) {
+    struct yaml12_call_handler_data *call_data = data;
+    SEXP call = PROTECT(Rf_lang2(call_data->function, call_data->argument));
+    SEXP last = CDR(call);
+    if (call_data->tag != R_NilValue) {
+        last = yaml12_append_argument(last, call_data->tag, NULL);
+    }
+    if (call_data->style != R_NilValue) {
+        last = yaml12_append_argument(last, call_data->style, "style");
+    }
+    if (call_data->context != R_NilValue) {
+        yaml12_append_argument(last, call_data->context, "context");
+    }
+    SEXP result = Rf_eval(call, R_GlobalEnv);
+    UNPROTECT(1);
+    return result;
+}
+
+SEXP yaml12_call_handler(SEXP function, SEXP argument, SEXP tag, SEXP style,
+                         SEXP context) {
+    /*
+     * The Rust caller keeps all arguments preserved across this call. A NULL
+     * `tag`, `style`, or `context` leaves that argument out of the call.
+     */
+    struct yaml12_call_handler_data data = {function, argument, tag, style,
+                                            context};
+    return yaml12_unwind_protect(yaml12_call_handler_impl, &data);
+}
+
+int yaml12_has_formal(SEXP function, const char *name) {
+    if (TYPEOF(function) != CLOSXP) {
+        return 0;
+    }
+#if R_VERSION >= R_Version(4, 5, 0)
+    SEXP formals = R_ClosureFormals(function);
+#else
+    SEXP formals = FORMALS(function);
+#endif
+    for (; formals != R_NilValue; formals = CDR(formals)) {
+        if (strcmp(CHAR(PRINTNAME(TAG(formals))), name) == 0) {
+            return 1;
+        }
+    }
+    return 0;
+}
+
+int yaml12_takes_second_argument(SEXP function) {
+    if (TYPEOF(function) != CLOSXP) {
+        return 0;
+    }
+#if R_VERSION >= R_Version(4, 5, 0)
+    SEXP formals = R_ClosureFormals(function);
+#else
+    SEXP formals = FORMALS(function);
+#endif
+    /* `style` and `context` are always passed by name, so they never take
+     * a positional argument. */
+    int positional = 0;
+    for (; formals != R_NilValue; formals = CDR(formals)) {
+        const char *name = CHAR(PRINTNAME(TAG(formals)));
+        if (strcmp(name, "...") == 0) {
+            return 1;
+        }
+        if (strcmp(name, "style") != 0 && strcmp(name, "context") != 0 &&
+            ++positional == 2) {
+            return 1;
+        }
+    }
+    return 0;
+}
+
+static SEXP yaml12_deparse_one(SEXP value) {
+    SEXP quoted = PROTECT(Rf_lang2(Rf_install("quote"), value));
+    SEXP collapse = PROTECT(Rf_mkString("\n"));
+    SEXP call = PROTECT(Rf_lang3(Rf_install("deparse1"), quoted, collapse));
+    SET_TAG(CDDR(call), Rf_install("collapse"));
+    SEXP result = Rf_eval(call, R_BaseEnv);
+    UNPROTECT(3);
+    return STRING_ELT(result, 0);
+}
+
+static SEXP yaml12_deparse_impl(void *data) {
+    SEXP value = *(SEXP *)data;
+    if (TYPEOF(value) != EXPRSXP) {
+        return Rf_ScalarString(yaml12_deparse_one(value));
+    }
+    R_xlen_t length = XLENGTH(value);
+    SEXP result = PROTECT(Rf_allocVector(STRSXP, length));
+    for (R_xlen_t i = 0; i < length; i++) {
+        SET_STRING_ELT(result, i, yaml12_deparse_one(VECTOR_ELT(value, i)));
+    }
+    UNPROTECT(1);
+    return result;
+}
+
+SEXP yaml12_deparse(SEXP value) {
+    /*
+     * Deparse a symbol or call to a string, or each element of an expression
+     * vector to one string per element.
+     */
+    return yaml12_unwind_protect(yaml12_deparse_impl, &value);
+}
+
+struct yaml12_new_lazy_list_data {
+    SEXP state;
+    SEXP names;
+    R_xlen_t length;
+};
+
+static SEXP yaml12_new_lazy_list_impl(void *data) {
+    struct yaml12_new_lazy_list_data *list = data;
+    return yaml12_make_lazy_list(list->state, list->names, list->length);
+}
+
+SEXP yaml12_new_lazy_list(SEXP state, SEXP names, R_xlen_t length) {
+    /* Protect fresh arguments before the unwind runner allocates its token. */
+    PROTECT(state);
+    PROTECT(names);
+    struct yaml12_new_lazy_list_data data = {state, names, length};
+    SEXP result = yaml12_unwind_protect(yaml12_new_lazy_list_impl, &data);
+
+    if (((uintptr_t)result & 1) == 1) {
+        /* R_ContinueUnwind() will restore the protection stack. */
+        return result;
+    }
+    UNPROTECT(2);
+    return result;
+}